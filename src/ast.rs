@@ -0,0 +1,829 @@
+use crate::{error::Blame, prelude::*, utils::Redir};
+
+/// An owned slice of the source text that a node was built from.
+/// Nodes hold onto these so that errors can still be blamed on the right piece of input
+/// long after the pest pairs that produced them have been dropped.
+#[derive(Debug,Clone)]
+pub struct SrcSpan {
+	src: Arc<str>,
+	start: usize,
+	end: usize
+}
+
+impl SrcSpan {
+	pub fn new(src: &Arc<str>, span: Span) -> Self {
+		Self { src: src.clone(), start: span.start(), end: span.end() }
+	}
+	pub fn as_str(&self) -> &str {
+		&self.src[self.start..self.end]
+	}
+	pub fn as_span(&self) -> Span<'_> {
+		Span::new(&self.src, self.start, self.end).unwrap()
+	}
+}
+
+impl Blame for SrcSpan {
+	fn blame_span(&self) -> Span<'_> {
+		self.as_span()
+	}
+}
+
+/// A sequence of command lists, separated by `;` or newlines.
+/// This is what the input to `exec_input`, function bodies, loop bodies, etc. all boil down to.
+#[derive(Debug,Clone)]
+pub struct Block {
+	pub lists: Vec<CmdList>,
+	pub span: SrcSpan
+}
+
+impl Block {
+	/// Whether any simple command in the block matches, including ones nested inside of compound commands and function definitions
+	pub fn any_simple_cmd(&self, pred: &impl Fn(&SimpleCmd) -> bool) -> bool {
+		self.lists.iter().any(|list| {
+			list.first.any_simple_cmd(pred) || list.rest.iter().any(|(_,cmd)| cmd.any_simple_cmd(pred))
+		})
+	}
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ListOp {
+	And,
+	Or
+}
+
+/// Commands chained together with `&&` and `||`
+#[derive(Debug,Clone)]
+pub struct CmdList {
+	pub first: Command,
+	pub rest: Vec<(ListOp,Command)>,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub enum Command {
+	Simple(SimpleCmd),
+	Pipeline(Pipeline),
+	Shell(ShellCmd),
+	Background(Box<Command>)
+}
+
+impl Command {
+	pub fn span(&self) -> &SrcSpan {
+		match self {
+			Command::Simple(cmd) => &cmd.span,
+			Command::Pipeline(pipeline) => &pipeline.span,
+			Command::Shell(cmd) => &cmd.span,
+			Command::Background(cmd) => cmd.span()
+		}
+	}
+	pub fn any_simple_cmd(&self, pred: &impl Fn(&SimpleCmd) -> bool) -> bool {
+		match self {
+			Command::Simple(cmd) => pred(cmd),
+			Command::Pipeline(pipeline) => pipeline.cmds.iter().any(|cmd| cmd.any_simple_cmd(pred)),
+			Command::Shell(cmd) => cmd.kind.blocks().into_iter().any(|block| block.any_simple_cmd(pred)),
+			Command::Background(cmd) => cmd.any_simple_cmd(pred)
+		}
+	}
+}
+
+#[derive(Debug,Clone)]
+pub struct Pipeline {
+	pub cmds: Vec<Command>,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub enum WordKind {
+	Plain,
+	CmdName,
+	Array,
+	/// A dict literal, i.e. `{a: 1, b: [1,2]}`
+	Dict,
+	/// `foo=bar` in argument position
+	Assign { var: String, val: Option<Box<Word>> }
+}
+
+#[derive(Debug,Clone)]
+pub struct Word {
+	pub kind: WordKind,
+	/// Whether or not this word contains anything that needs to be expanded
+	pub expand: bool,
+	pub span: SrcSpan
+}
+
+impl Word {
+	pub fn as_str(&self) -> &str {
+		self.span.as_str()
+	}
+	pub fn is_assign(&self) -> bool {
+		matches!(self.kind, WordKind::Assign { .. })
+	}
+}
+
+#[derive(Debug,Clone)]
+pub struct SimpleCmd {
+	pub name: Option<Word>,
+	pub args: Vec<Word>,
+	pub redirs: Vec<Redir>,
+	pub span: SrcSpan
+}
+
+impl SimpleCmd {
+	pub fn name(&self) -> Option<&str> {
+		self.name.as_ref().map(|name| name.as_str())
+	}
+	/// The command name followed by all of the arguments
+	pub fn words(&self) -> impl Iterator<Item = &Word> {
+		self.name.iter().chain(self.args.iter())
+	}
+	/// Produces a new command using the first argument as the command name.
+	/// Used by things like `builtin`, `command`, and `exec` which run their arguments as a command.
+	pub fn shift(&self) -> Option<SimpleCmd> {
+		let mut args = self.args.clone();
+		if args.is_empty() {
+			return None
+		}
+		let mut name = args.remove(0);
+		name.kind = WordKind::CmdName;
+		Some(SimpleCmd { name: Some(name), args, redirs: self.redirs.clone(), span: self.span.clone() })
+	}
+}
+
+#[derive(Debug,Clone)]
+pub struct ShellCmd {
+	pub kind: Compound,
+	pub redirs: Vec<Redir>,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub enum Compound {
+	If(IfCmd),
+	Try(TryCmd),
+	Arith(ArithCmd),
+	Loop(LoopCmd),
+	For(ForCmd),
+	Select(SelectCmd),
+	Match(MatchCmd),
+	Case(CaseCmd),
+	Subshell(Subshell),
+	BraceGrp(Block),
+	Assign(Assignment),
+	FuncDef(FuncDef)
+}
+
+impl Compound {
+	/// The blocks of commands held by this compound command
+	pub fn blocks(&self) -> Vec<&Block> {
+		match self {
+			Compound::If(if_cmd) => {
+				let mut blocks = vec![&if_cmd.cond, &if_cmd.body];
+				for (cond,body) in &if_cmd.elifs {
+					blocks.extend([cond, body]);
+				}
+				blocks.extend(&if_cmd.else_body);
+				blocks
+			}
+			Compound::Try(try_cmd) => std::iter::once(&try_cmd.body).chain(try_cmd.arms.iter().map(|arm| &arm.body)).collect(),
+			Compound::Loop(loop_cmd) => vec![&loop_cmd.cond, &loop_cmd.body],
+			Compound::For(for_cmd) => vec![&for_cmd.body],
+			Compound::Select(select_cmd) => vec![&select_cmd.body],
+			Compound::Match(match_cmd) => match_cmd.arms.iter().map(|arm| &arm.body).collect(),
+			Compound::Case(case_cmd) => case_cmd.items.iter().map(|item| &item.body).collect(),
+			Compound::Subshell(Subshell { body: SubshBody::Slash(body), .. }) => vec![body],
+			Compound::BraceGrp(body) => vec![body],
+			Compound::Assign(ass) => ass.cmd.iter().collect(),
+			Compound::FuncDef(func_def) => vec![&func_def.body],
+			Compound::Arith(_) | Compound::Subshell(_) => vec![]
+		}
+	}
+}
+
+#[derive(Debug,Clone)]
+pub struct IfCmd {
+	pub cond: Block,
+	pub body: Block,
+	pub elifs: Vec<(Block,Block)>,
+	pub else_body: Option<Block>,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub struct ExceptArm {
+	/// The kind of error caught by this arm, or None to catch any error
+	pub kind: Option<String>,
+	/// The name of the variable that holds the caught error
+	pub var: Option<String>,
+	pub body: Block,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub struct TryCmd {
+	pub body: Block,
+	pub arms: Vec<ExceptArm>,
+	pub span: SrcSpan
+}
+
+/// An arithmetic command, i.e. `(( x > 5 ))`
+#[derive(Debug,Clone)]
+pub struct ArithCmd {
+	pub expr: String,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum LoopKind {
+	While,
+	Until
+}
+
+#[derive(Debug,Clone)]
+pub struct LoopCmd {
+	pub kind: LoopKind,
+	pub cond: Block,
+	pub body: Block,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub enum ForKind {
+	/// `for x in words`
+	Words(Vec<Word>),
+	/// `for i in 0..10 step 2`, where the bounds are evaluated when the loop starts
+	Range { start: String, end: String, inclusive: bool, step: Option<String> },
+	/// `for ((init; cond; step))`
+	Arith { init: String, cond: String, step: String }
+}
+
+#[derive(Debug,Clone)]
+pub struct ForCmd {
+	pub vars: Vec<String>,
+	pub kind: ForKind,
+	pub body: Block,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub struct SelectCmd {
+	pub var: String,
+	/// The words offered in the menu, or None to offer the positional parameters
+	pub arr: Option<Vec<Word>>,
+	pub body: Block,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub enum MatchPat {
+	/// A glob pattern, which has to match the whole subject
+	Glob(Vec<Word>),
+	/// A regex, whose capture groups are bound to variables when it matches
+	Regex(String),
+	/// Matches on the type of the subject, like `<int>` or `<dict>`
+	Type(String),
+	/// `_` matches anything
+	Wildcard
+}
+
+#[derive(Debug,Clone)]
+pub struct MatchArm {
+	/// Alternatives separated by `|`, the arm runs if any of them match
+	pub pats: Vec<MatchPat>,
+	pub body: Block,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub struct MatchCmd {
+	pub subject: Word,
+	pub arms: Vec<MatchArm>,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum CaseTerm {
+	/// `;;` stops after the body
+	Break,
+	/// `;&` runs the next body without testing its patterns
+	FallThrough,
+	/// `;;&` goes on to test the patterns of the next item
+	Continue
+}
+
+#[derive(Debug,Clone)]
+pub struct CaseItem {
+	pub pats: Vec<MatchPat>,
+	pub body: Block,
+	pub term: CaseTerm,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub struct CaseCmd {
+	pub subject: Word,
+	pub items: Vec<CaseItem>,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub enum SubshBody {
+	/// A body that we execute ourselves
+	Slash(Block),
+	/// A body with a shebang, handed off to some other interpreter
+	Foreign(String)
+}
+
+#[derive(Debug,Clone)]
+pub struct Subshell {
+	pub shebang: Option<String>,
+	pub body: SubshBody,
+	pub args: Vec<Word>,
+	pub redirs: Vec<Redir>,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum AssignKind {
+	Std,
+	Plus,
+	Minus,
+	Times,
+	Div,
+	Mod,
+	Pow,
+	Increment,
+	Decrement
+}
+
+#[derive(Debug,Clone)]
+pub struct Assignment {
+	pub var: String,
+	/// The keys of an element assignment, i.e. `d["key"]=val`
+	pub index: Vec<String>,
+	pub kind: AssignKind,
+	pub val: Option<Word>,
+	/// Commands attached to the assignment, i.e. `FOO=bar cmd`
+	pub cmd: Option<Block>,
+	pub span: SrcSpan
+}
+
+#[derive(Debug,Clone)]
+pub struct FuncDef {
+	pub name: String,
+	pub body: Arc<Block>,
+	pub span: SrcSpan
+}
+
+/// Parses the input and builds an AST from it.
+/// This is the only place where shell input gets turned into pest pairs,
+/// everything after this point operates on the owned nodes.
+pub fn parse(input: &str) -> SlashResult<Block> {
+	let src: Arc<str> = Arc::from(input);
+	let main = SlashParse::parse(Rule::main, &src)
+		.map_err(|e| Low(SlashErrLow::Parse(e.to_string())))?
+		.next()
+		.unpack()?;
+	let builder = AstBuilder { src: src.clone(), heredocs: collect_heredocs(&main)? };
+	builder.build_block(main)
+}
+
+/// Matches each heredoc with the body that was read from the lines after it
+/// Bodies come in the same order as their heredocs, and are keyed by where the heredoc's redirection starts
+fn collect_heredocs(main: &Pair<Rule>) -> SlashResult<HashMap<usize,String>> {
+	let mut pending = VecDeque::new();
+	let mut heredocs = HashMap::new();
+	for pair in main.clone().into_inner().flatten() {
+		match pair.as_rule() {
+			Rule::redir if pair.clone().into_inner().any(|pr| pr.as_rule() == Rule::heredoc) => {
+				pending.push_back(pair.as_span().start());
+			}
+			Rule::heredoc_body => {
+				if let (Some(start), Some(text)) = (pending.pop_front(), pair.scry(Rule::heredoc_text)) {
+					heredocs.insert(start, text.as_str().to_string());
+				}
+			}
+			Rule::heredoc_extra => {
+				return Err(High(SlashErrHigh::syntax_err("Only one heredoc per line is supported", pair)))
+			}
+			_ => {}
+		}
+	}
+	Ok(heredocs)
+}
+
+struct AstBuilder {
+	src: Arc<str>,
+	heredocs: HashMap<usize,String>
+}
+
+impl AstBuilder {
+	fn span(&self, pair: &Pair<Rule>) -> SrcSpan {
+		SrcSpan::new(&self.src, pair.as_span())
+	}
+
+	fn build_redir(&self, pair: Pair<Rule>) -> SlashResult<Redir> {
+		let start = pair.as_span().start();
+		let mut redir = Redir::from_pair(pair)?;
+		if let Some(body) = self.heredocs.get(&start) {
+			redir.set_heredoc_body(body);
+		}
+		Ok(redir)
+	}
+
+	/// Collects the `cmd_list` children of a pair into a block
+	fn build_block(&self, pair: Pair<Rule>) -> SlashResult<Block> {
+		let span = self.span(&pair);
+		let mut lists = vec![];
+		for list in pair.filter(Rule::cmd_list) {
+			lists.push(self.build_list(list)?);
+		}
+		Ok(Block { lists, span })
+	}
+
+	fn build_list(&self, pair: Pair<Rule>) -> SlashResult<CmdList> {
+		let span = self.span(&pair);
+		let mut inner = pair.into_inner();
+		let first = self.build_command(inner.next().unpack()?)?;
+		let mut rest = vec![];
+		while let Some(op) = inner.next() {
+			let op = match op.scry(&[Rule::and,Rule::or][..]).unpack()?.as_rule() {
+				Rule::and => ListOp::And,
+				Rule::or => ListOp::Or,
+				_ => unreachable!()
+			};
+			let cmd = self.build_command(inner.next().unpack()?)?;
+			rest.push((op,cmd));
+		}
+		Ok(CmdList { first, rest, span })
+	}
+
+	fn build_command(&self, pair: Pair<Rule>) -> SlashResult<Command> {
+		let span = self.span(&pair);
+		match pair.as_rule() {
+			Rule::bg_cmd => {
+				let cmd = self.build_command(pair.step(1).unpack()?)?;
+				Ok(Command::Background(Box::new(cmd)))
+			}
+			Rule::pipeline => {
+				let mut cmds = vec![];
+				for cmd in pair.into_inner() {
+					cmds.push(self.build_command(cmd)?);
+				}
+				Ok(Command::Pipeline(Pipeline { cmds, span }))
+			}
+			Rule::shell_cmd => {
+				let mut inner = pair.into_inner();
+				let kind = self.build_compound(inner.next().unpack()?)?;
+				let mut redirs = vec![];
+				for redir in inner.filter(|pr| pr.as_rule() == Rule::redir) {
+					redirs.push(self.build_redir(redir)?);
+				}
+				Ok(Command::Shell(ShellCmd { kind, redirs, span }))
+			}
+			Rule::assignment => {
+				let kind = self.build_compound(pair)?;
+				Ok(Command::Shell(ShellCmd { kind, redirs: vec![], span }))
+			}
+			Rule::simple_cmd => Ok(Command::Simple(self.build_simple_cmd(pair)?)),
+			_ => Err(Low(SlashErrLow::InternalErr(format!("Unexpected rule in command position: {:?}",pair.as_rule()))))
+		}
+	}
+
+	fn build_simple_cmd(&self, pair: Pair<Rule>) -> SlashResult<SimpleCmd> {
+		let span = self.span(&pair);
+		let mut name = None;
+		let mut args = vec![];
+		let mut redirs = vec![];
+		for arg in pair.into_inner() {
+			match arg.as_rule() {
+				Rule::cmd_name => name = Some(self.build_word(arg)?),
+				Rule::word | Rule::arg_assign => args.push(self.build_word(arg)?),
+				Rule::redir => redirs.push(self.build_redir(arg)?),
+				_ => unreachable!("Unexpected rule: {:?}",arg.as_rule())
+			}
+		}
+		Ok(SimpleCmd { name, args, redirs, span })
+	}
+
+	fn build_word(&self, pair: Pair<Rule>) -> SlashResult<Word> {
+		let span = self.span(&pair);
+		let expand = pair.contains_rules(&[Rule::expand_word,Rule::dquoted][..]);
+		let kind = match pair.as_rule() {
+			Rule::cmd_name => WordKind::CmdName,
+			Rule::array => WordKind::Array,
+			Rule::dict => WordKind::Dict,
+			Rule::arg_assign => {
+				let mut inner = pair.into_inner();
+				let var = inner.next().unpack()?.as_str().to_string();
+				let val = match inner.next() {
+					Some(val) => Some(Box::new(self.build_word(val)?)),
+					None => None
+				};
+				WordKind::Assign { var, val }
+			}
+			_ => WordKind::Plain
+		};
+		Ok(Word { kind, expand, span })
+	}
+
+	fn build_words(&self, pair: Pair<Rule>) -> SlashResult<Vec<Word>> {
+		let mut words = vec![];
+		for word in pair.filter(Rule::word) {
+			words.push(self.build_word(word)?);
+		}
+		Ok(words)
+	}
+
+	fn build_compound(&self, pair: Pair<Rule>) -> SlashResult<Compound> {
+		let span = self.span(&pair);
+		match pair.as_rule() {
+			Rule::if_cmd => {
+				let mut cond = None;
+				let mut body = None;
+				let mut elifs = vec![];
+				let mut else_body = None;
+				for part in pair.into_inner() {
+					match part.as_rule() {
+						Rule::if_cond => cond = Some(self.build_block(part)?),
+						Rule::if_body => body = Some(self.build_block(part)?),
+						Rule::elif_block => {
+							let elif_cond = self.build_block(part.scry(Rule::if_cond).unpack()?)?;
+							let elif_body = self.build_block(part.scry(Rule::if_body).unpack()?)?;
+							elifs.push((elif_cond,elif_body));
+						}
+						Rule::else_block => else_body = Some(self.build_block(part)?),
+						Rule::heredoc_body => {}
+						_ => unreachable!()
+					}
+				}
+				let cond = cond.ok_or_else(|| Low(SlashErrLow::InternalErr("Missing condition in if statement".into())))?;
+				let body = body.ok_or_else(|| Low(SlashErrLow::InternalErr("Missing body in if statement".into())))?;
+				Ok(Compound::If(IfCmd { cond, body, elifs, else_body, span }))
+			}
+			Rule::try_cmd => {
+				let body = self.build_block(pair.filter(Rule::brace_grp).pop_front().unpack()?)?;
+				let mut arms = vec![];
+				for arm in pair.filter(Rule::except_arm) {
+					arms.push(self.build_except_arm(arm)?);
+				}
+				Ok(Compound::Try(TryCmd { body, arms, span }))
+			}
+			Rule::arith_cmd => {
+				let expr = pair.scry(Rule::arith_body).unpack()?.as_str().to_string();
+				Ok(Compound::Arith(ArithCmd { expr, span }))
+			}
+			Rule::loop_cmd => {
+				let kind = match pair.scry(Rule::loop_kind).unpack()?.as_str() {
+					"while" => LoopKind::While,
+					"until" => LoopKind::Until,
+					_ => unreachable!()
+				};
+				let cond = self.build_block(pair.scry(Rule::loop_cond).unpack()?)?;
+				let body = self.build_block(pair.scry(Rule::loop_body).unpack()?)?;
+				Ok(Compound::Loop(LoopCmd { kind, cond, body, span }))
+			}
+			Rule::for_cmd => {
+				// Only look at direct children here, since the body may contain other for loops
+				let vars = match pair.filter(Rule::for_vars).pop_front() {
+					Some(vars) => vars.filter(Rule::for_var).into_iter().map(|var| var.as_str().to_string()).collect(),
+					None => vec![]
+				};
+				let kind = if let Some(arith) = pair.filter(Rule::for_arith).pop_front() {
+					let mut parts = arith.filter(Rule::for_arith_part);
+					let init = parts.pop_front().unpack()?.as_str().to_string();
+					let cond = parts.pop_front().unpack()?.as_str().to_string();
+					let step = parts.pop_front().unpack()?.as_str().to_string();
+					ForKind::Arith { init, cond, step }
+				} else if let Some(range) = pair.filter(Rule::for_range).pop_front() {
+					let mut bounds = range.filter(Rule::for_bound);
+					let start = bounds.pop_front().unpack()?.as_str().to_string();
+					let end = bounds.pop_front().unpack()?.as_str().to_string();
+					let step = bounds.pop_front().map(|step| step.as_str().to_string());
+					let inclusive = range.scry(Rule::for_range_op).unpack()?.as_str() == "..=";
+					ForKind::Range { start, end, inclusive, step }
+				} else {
+					ForKind::Words(self.build_words(pair.filter(Rule::for_arr).pop_front().unpack()?)?)
+				};
+				let body = self.build_block(pair.scry(Rule::loop_body).unpack()?)?;
+				Ok(Compound::For(ForCmd { vars, kind, body, span }))
+			}
+			Rule::select_cmd => {
+				let var = pair.scry(Rule::var_ident_plain).unpack()?.as_str().trim().to_string();
+				let arr = pair.scry(Rule::select_arr).map(|arr| self.build_words(arr)).transpose()?;
+				let body = self.build_block(pair.scry(Rule::loop_body).unpack()?)?;
+				Ok(Compound::Select(SelectCmd { var, arr, body, span }))
+			}
+			Rule::match_cmd => {
+				let subject = self.build_word(pair.scry(Rule::word).unpack()?)?;
+				let mut arms = vec![];
+				for arm in pair.filter(Rule::match_arm) {
+					arms.push(self.build_match_arm(arm)?);
+				}
+				Ok(Compound::Match(MatchCmd { subject, arms, span }))
+			}
+			Rule::case_cmd => {
+				let subject = self.build_word(pair.scry(Rule::word).unpack()?)?;
+				let mut items = vec![];
+				for item in pair.filter(Rule::case_item) {
+					items.push(self.build_case_item(item)?);
+				}
+				Ok(Compound::Case(CaseCmd { subject, items, span }))
+			}
+			Rule::subshell => {
+				let mut shebang = None;
+				let mut body = None;
+				let mut args = vec![];
+				let mut redirs = vec![];
+				for part in pair.into_inner() {
+					match part.as_rule() {
+						Rule::subshebang => shebang = Some(part.as_str().to_string()),
+						Rule::subsh_body => body = Some(part.as_str().to_string()),
+						Rule::word | Rule::arg_assign => args.push(self.build_word(part)?),
+						Rule::redir => redirs.push(self.build_redir(part)?),
+						_ => unreachable!()
+					}
+				}
+				let body = body.unwrap_or_default();
+				let body = if shebang.is_some() {
+					SubshBody::Foreign(body)
+				} else {
+					SubshBody::Slash(parse(&body.consume_escapes())?)
+				};
+				Ok(Compound::Subshell(Subshell { shebang, body, args, redirs, span }))
+			}
+			Rule::brace_grp => Ok(Compound::BraceGrp(self.build_block(pair)?)),
+			Rule::assignment => Ok(Compound::Assign(self.build_assignment(pair.step(1).unpack()?)?)),
+			Rule::func_def => {
+				// Only look at direct children here, the body may contain other function definitions
+				let name = pair.filter(&[Rule::func_name,Rule::word][..])
+					.pop_front()
+					.unpack()?
+					.as_str()
+					.trim_end_matches("()")
+					.to_string();
+				let body = self.build_block(pair.filter(Rule::brace_grp).pop_front().unpack()?)?;
+				Ok(Compound::FuncDef(FuncDef { name, body: Arc::new(body), span }))
+			}
+			_ => Err(Low(SlashErrLow::InternalErr(format!("Unexpected rule in shell command: {:?}",pair.as_rule()))))
+		}
+	}
+
+	fn build_match_arm(&self, pair: Pair<Rule>) -> SlashResult<MatchArm> {
+		let span = self.span(&pair);
+		let mut pats = vec![];
+		for alt in pair.scry(Rule::match_pat).unpack()?.filter(Rule::match_alt) {
+			let alt = alt.step(1).unpack()?;
+			let pat = match alt.as_rule() {
+				Rule::match_type => MatchPat::Type(alt.as_str().trim_matches(['<', '>']).to_string()),
+				Rule::match_regex => MatchPat::Regex(alt.as_str()[2..alt.as_str().len() - 1].to_string()),
+				_ if alt.as_str().trim() == "_" => MatchPat::Wildcard,
+				_ => MatchPat::Glob(self.build_words(alt)?)
+			};
+			pats.push(pat);
+		}
+		let body = pair.scry(Rule::match_body).unpack()?;
+		let body = if let Some(brace_grp) = body.scry(Rule::brace_grp) {
+			self.build_block(brace_grp)?
+		} else {
+			// Unbraced arm bodies are not parsed by the grammar, so we do that here
+			parse(body.as_str().trim_end_matches(','))?
+		};
+		Ok(MatchArm { pats, body, span })
+	}
+
+	fn build_case_item(&self, pair: Pair<Rule>) -> SlashResult<CaseItem> {
+		let span = self.span(&pair);
+		let mut pats = vec![];
+		for glob in pair.scry(Rule::case_pats).unpack()?.filter(Rule::match_glob) {
+			pats.push(MatchPat::Glob(self.build_words(glob)?));
+		}
+		let body = self.build_block(pair.scry(Rule::case_body).unpack()?)?;
+		let term = match pair.scry(Rule::case_term).map(|term| term.as_str()) {
+			Some(";&") => CaseTerm::FallThrough,
+			Some(";;&") => CaseTerm::Continue,
+			_ => CaseTerm::Break
+		};
+		Ok(CaseItem { pats, body, term, span })
+	}
+
+	fn build_except_arm(&self, pair: Pair<Rule>) -> SlashResult<ExceptArm> {
+		let span = self.span(&pair);
+		// Only look at direct children here, the body may contain other try blocks
+		let kind = pair.filter(Rule::except_kind).pop_front().map(|kind| kind.as_str().to_string());
+		let var = pair.filter(Rule::except_var).pop_front().map(|var| var.as_str().trim_start_matches("as").trim().to_string());
+		let body = self.build_block(pair.filter(Rule::brace_grp).pop_front().unpack()?)?;
+		Ok(ExceptArm { kind, var, body, span })
+	}
+
+	fn build_assignment(&self, pair: Pair<Rule>) -> SlashResult<Assignment> {
+		let span = self.span(&pair);
+		let kind = match pair.as_rule() {
+			Rule::std_assign => AssignKind::Std,
+			Rule::plus_assign => AssignKind::Plus,
+			Rule::minus_assign => AssignKind::Minus,
+			Rule::times_assign => AssignKind::Times,
+			Rule::div_assign => AssignKind::Div,
+			Rule::mod_assign => AssignKind::Mod,
+			Rule::pow_assign => AssignKind::Pow,
+			Rule::increment => AssignKind::Increment,
+			Rule::decrement => AssignKind::Decrement,
+			_ => unreachable!()
+		};
+		if matches!(kind, AssignKind::Increment | AssignKind::Decrement) {
+			// These two are atomic rules, so we have to pick them apart by hand
+			let op = if kind == AssignKind::Increment { "++" } else { "--" };
+			let (var,cmd) = pair.as_str().split_once(op)
+				.ok_or_else(|| Low(SlashErrLow::InternalErr("Failed to split atomic assignment".into())))?;
+			let var = var.trim().trim_start_matches('{').trim_end_matches('}').to_string();
+			let cmd = if cmd.trim().is_empty() { None } else { Some(parse(cmd.trim())?) };
+			return Ok(Assignment { var, index: vec![], kind, val: None, cmd, span })
+		}
+		let var = pair.scry(Rule::var_ident).unpack()?.as_str().to_string();
+		let index = match pair.filter(Rule::index_target).pop_front() {
+			Some(target) => target.filter(&[Rule::key,Rule::index][..]).into_iter().map(|key| key.as_str().to_string()).collect(),
+			None => vec![]
+		};
+		let val = match pair.filter(&[Rule::word,Rule::array][..]).pop_front() {
+			Some(word) => Some(self.build_word(word)?),
+			None => None
+		};
+		let cmd = match pair.filter(Rule::cmd_list).pop_front() {
+			Some(list) => {
+				let span = self.span(&list);
+				Some(Block { lists: vec![self.build_list(list)?], span })
+			}
+			None => None
+		};
+		Ok(Assignment { var, index, kind, val, cmd, span })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{execute, shellenv::SlashVal};
+
+	use super::*;
+
+	#[test]
+	fn test_parse_list() {
+		let block = parse("false && echo foo || echo bar; echo baz").unwrap();
+		assert_eq!(block.lists.len(), 2);
+		let ops = block.lists[0].rest.iter().map(|(op,_)| *op).collect::<Vec<_>>();
+		assert_eq!(ops, vec![ListOp::And,ListOp::Or]);
+		assert!(matches!(block.lists[1].first, Command::Simple(ref cmd) if cmd.name() == Some("echo")));
+	}
+	#[test]
+	fn test_func_body_parsed_once() {
+		let mut slash = Slash::new();
+		let input = "foo() { int var=5; }; foo";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert!(slash.logic().get_func("foo").is_some_and(|body| body.lists.len() == 1));
+	}
+	#[test]
+	fn test_increment() {
+		let mut slash = Slash::new();
+		let input = "int var=5; var++";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("var"), Some(SlashVal::Int(6)))
+	}
+	#[test]
+	fn test_heredoc_parse() {
+		// A heredoc without its closing delimiter is incomplete
+		assert!(parse("cat <<EOF\nhello").is_err());
+		let block = parse("cat <<-EOF\n\thello\n\tEOF\necho done").unwrap();
+		assert_eq!(block.lists.len(), 2);
+	}
+	#[test]
+	fn test_heredoc_two_per_line() {
+		// Only the first heredoc on a line could get a body, so the line is refused instead of reading the bodies wrong
+		let Err(High(err)) = parse("cat <<A; cat <<B\none\nA\ntwo\nB\necho after") else { panic!() };
+		assert!(matches!(err.get_err(), SlashErrLow::InvalidSyntax(_)));
+		assert!(parse("cat <<A\none\nA\ncat <<B\ntwo\nB").is_ok());
+	}
+	#[test]
+	fn test_heredoc_expanded() {
+		let mut slash = Slash::new();
+		let input = "name=world\nout=\"$(cat <<EOF\nhello $name\nEOF\n)\"";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::String("hello world".into())))
+	}
+	#[test]
+	fn test_heredoc_quoted_delim() {
+		let mut slash = Slash::new();
+		let input = "name=world\nout=\"$(cat <<'EOF'\nhello $name\nEOF\n)\"";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::String("hello $name".into())))
+	}
+	#[test]
+	fn test_herestring() {
+		let mut slash = Slash::new();
+		let input = "name=world; out=$(tr a-z A-Z <<< \"$name\")";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::String("WORLD".into())))
+	}
+}
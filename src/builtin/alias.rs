@@ -1,14 +1,13 @@
-use crate::{helper, pest_ext::ARG_RULES, prelude::*, utils};
+use crate::{ast::{SimpleCmd, WordKind}, helper, prelude::*, utils};
 
 /// Creates a new alias from the given arguments
 /// Can create more than one alias at a time
-/// Expects the "arg_assign" rule in the inner pairs
-/// "arg_assign" appears in argument positions and looks like this: foo=bar
-pub fn execute<'a>(alias_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
+/// Expects assignment words in the arguments
+/// These appear in argument positions and look like this: foo=bar
+pub fn execute(alias_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
 	let mut stdout = utils::SmartFD::new(STDOUT_FILENO)?;
 
-	let mut args = alias_call.filter(&ARG_RULES[..]);
-	let redirs = helper::prepare_redirs(alias_call)?;
+	let redirs = helper::prepare_redirs(&alias_call.redirs);
 
 	slash.ctx_mut().extend_redirs(redirs);
 
@@ -18,29 +17,26 @@ pub fn execute<'a>(alias_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<
 		redirs.activate()?;
 	}
 
-	while let Some(arg) = args.pop_front() {
-		match arg.as_rule() {
-			Rule::arg_assign => {
-				let mut assign_inner = arg.into_inner();
-				let alias = assign_inner.next().unpack()?.as_str();
-				let body = assign_inner.next().map(|pair| pair.as_str()).unwrap_or_default();
-				helper::write_alias(slash, alias, &body.trim_quotes())?;
+	for arg in &alias_call.args {
+		match &arg.kind {
+			WordKind::Assign { var, val } => {
+				let body = val.as_ref().map(|word| word.as_str()).unwrap_or_default();
+				helper::write_alias(slash, var, &body.trim_quotes())?;
 			}
-			Rule::word => {
+			_ => {
 				let alias = slash.logic().get_alias(arg.as_str());
 				if let Some(alias) = alias {
 					write!(stdout,"{alias}\n")?;
 				}
 			}
-			_ => unreachable!()
 		}
 	}
 	Ok(())
 }
 
 /// Removes an alias from the logic table
-pub fn unalias<'a>(pair: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let mut argv = helper::prepare_argv(pair, slash)?;
+pub fn unalias(call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let mut argv = helper::prepare_argv(call.words(), slash)?;
 	argv.pop_front();
 	while let Some(arg) = argv.pop_front() {
 		if slash.logic().get_alias(&arg).is_some() {
@@ -0,0 +1,74 @@
+use crate::{ast::{ArithCmd, SimpleCmd}, error::SlashErrExt, expand::arithmetic, helper, prelude::*, shellenv::SlashVal, utils};
+
+/// Evaluates each argument as an arithmetic expression
+/// Returns 1 if the last expression evaluated to zero, and 0 otherwise
+pub fn let_expr(let_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<i32> {
+	let blame = &let_call.span;
+	let mut argv = helper::prepare_argv(let_call.words(), slash)?;
+	argv.pop_front();
+	let redirs = helper::prepare_redirs(&let_call.redirs);
+	slash.consume_redirs(redirs)?;
+
+	if argv.is_empty() {
+		return Err(High(SlashErrHigh::syntax_err("Expected an expression in `let' call", blame)))
+	}
+	let mut result = SlashVal::Int(0);
+	for arg in argv {
+		result = arithmetic::eval(&arg, slash).blame(blame)?;
+	}
+	Ok(if arithmetic::is_truthy(&result) { 0 } else { 1 })
+}
+
+/// Evaluates its arguments as a single arithmetic expression and prints the result
+/// Returns 1 if the result is zero, and 0 otherwise
+pub fn expr(expr_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<i32> {
+	let blame = &expr_call.span;
+	let mut argv = helper::prepare_argv(expr_call.words(), slash)?;
+	argv.pop_front();
+	let redirs = helper::prepare_redirs(&expr_call.redirs);
+	slash.consume_redirs(redirs)?;
+
+	let input = argv.into_iter().collect::<Vec<String>>().join(" ");
+	let result = arithmetic::eval(&input, slash).blame(blame)?;
+	let mut stdout = utils::SmartFD::new(1)?;
+	writeln!(stdout,"{}",result)?;
+	Ok(if arithmetic::is_truthy(&result) { 0 } else { 1 })
+}
+
+/// Runs `(( expr ))`, which succeeds if the expression evaluates to something other than zero
+pub fn exec_arith_cmd(cmd: &ArithCmd, slash: &mut Slash) -> SlashResult<()> {
+	let result = arithmetic::eval(&cmd.expr, slash).blame(&cmd.span)?;
+	slash.set_code(if arithmetic::is_truthy(&result) { 0 } else { 1 });
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::execute;
+
+	use super::*;
+
+	#[test]
+	fn test_let_and_expr() {
+		let mut slash = Slash::new();
+		let input = "let a=2+3 'b = a * 2'; int let_status=$?; let 'a - 5'; int zero_status=$?; expr 1 + 1; int expr_status=$?";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("a"), Some(SlashVal::Int(5)));
+		assert_eq!(slash.vars().get_var("b"), Some(SlashVal::Int(10)));
+		assert_eq!(slash.vars().get_var("let_status"), Some(SlashVal::Int(0)));
+		assert_eq!(slash.vars().get_var("zero_status"), Some(SlashVal::Int(1)));
+		assert_eq!(slash.vars().get_var("expr_status"), Some(SlashVal::Int(0)));
+	}
+	#[test]
+	fn test_nested_subshell_fallback() {
+		let mut slash = Slash::new();
+		let input = "(( x = 2 )); ((y=1); (z=2)); ((x=5; true) )";
+
+		// The last two run as subshells, so nothing they assign is kept
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("x"), Some(SlashVal::Int(2)));
+		assert_eq!(slash.vars().get_var("y"), None);
+		assert_eq!(slash.vars().get_var("z"), None);
+	}
+}
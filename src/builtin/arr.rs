@@ -0,0 +1,113 @@
+use crate::{ast::{SimpleCmd, WordKind}, helper, prelude::*, shellenv::SlashVal, utils};
+
+/// `arr a=[1,2]` declares arrays the same way as the other type builtins
+/// `arr push a x y` appends each value to the end of the array, creating it if it is unset,
+/// and `arr pop a` removes the last element and prints it, failing if the array is empty
+/// `arr pop a x` stores the element in `x` instead, since a command substitution can't change the array
+pub fn execute(arr_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<i32> {
+	let blame = &arr_call.span;
+	if arr_call.args.first().is_some_and(|arg| matches!(arg.kind, WordKind::Assign { .. })) {
+		super::assign::execute(arr_call, slash)?;
+		return Ok(0)
+	}
+	let mut argv = helper::prepare_argv(arr_call.words(), slash)?;
+	argv.pop_front();
+	let redirs = helper::prepare_redirs(&arr_call.redirs);
+	slash.consume_redirs(redirs)?;
+
+	let (Some(op), Some(var_name)) = (argv.pop_front(), argv.pop_front()) else {
+		return Err(High(SlashErrHigh::syntax_err("Expected an operation and a variable name in `arr' call", blame)))
+	};
+	slash.vars().check_writable(&var_name).blame(blame)?;
+	let mut arr = slash.vars().get_var(&var_name).unwrap_or(SlashVal::Array(vec![]));
+	if !matches!(arr, SlashVal::Array(_)) {
+		let msg = format!("Expected `{}' to be an array, found a value of type {}",var_name,arr.fmt_type());
+		return Err(High(SlashErrHigh::exec_err(msg, blame)))
+	}
+
+	match op.as_str() {
+		"push" => {
+			for arg in argv {
+				arr.push(SlashVal::parse(&arg)?).blame(blame)?;
+			}
+			slash.vars_mut().set_var(&var_name, arr).blame(blame)?;
+		}
+		"pop" => {
+			let Some(val) = arr.pop().blame(blame)? else {
+				return Ok(1)
+			};
+			slash.vars_mut().set_var(&var_name, arr).blame(blame)?;
+			if let Some(target) = argv.pop_front() {
+				slash.vars_mut().set_var(&target, val).blame(blame)?;
+			} else {
+				let mut stdout = utils::SmartFD::new(1)?;
+				writeln!(stdout,"{}",val)?;
+			}
+		}
+		_ => return Err(High(SlashErrHigh::syntax_err(format!("Unknown operation in `arr' call: {}",op), blame)))
+	}
+	Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::execute;
+
+	use super::*;
+
+	#[test]
+	fn test_arr_push_pop() {
+		let mut slash = Slash::new();
+		let input = "arr a=[1,\"x y\",3]; arr push a 4 5; arr pop a last";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("last"), Some(SlashVal::Int(5)));
+		let expected = vec![SlashVal::Int(1), SlashVal::String("x y".into()), SlashVal::Int(3), SlashVal::Int(4)];
+		assert_eq!(slash.vars().get_var("a"), Some(SlashVal::Array(expected)));
+	}
+	#[test]
+	fn test_arr_pop_empty() {
+		let mut slash = Slash::new();
+		let input = "arr b=[]; arr pop b";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 1)
+	}
+	#[test]
+	fn test_arr_index() {
+		let mut slash = Slash::new();
+		let input = "arr a=[1,\"x y\",3,4]; tail=$a[-1]; mid=$a[1..-1]";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("tail"), Some(SlashVal::Int(4)));
+		assert_eq!(slash.vars().get_var("mid"), Some(SlashVal::String("x y 3".into())));
+	}
+	#[test]
+	fn test_arr_elements() {
+		let mut slash = Slash::new();
+		let input = "arr a=[1,\"x y\",3]; int count=0; for e in $a[@]; do count=$((count + 1)); done";
+
+		// Each element is one word, even if it has spaces in it
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("count"), Some(SlashVal::Int(3)))
+	}
+	#[test]
+	fn test_arr_len() {
+		let mut slash = Slash::new();
+		let input = "arr l=[1,2,3]; int len=$#l; int bracketed=${#l[@]}; int unset_len=${#nothing[@]}";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("len"), Some(SlashVal::Int(3)));
+		assert_eq!(slash.vars().get_var("bracketed"), Some(SlashVal::Int(3)));
+		assert_eq!(slash.vars().get_var("unset_len"), Some(SlashVal::Int(0)));
+	}
+	#[test]
+	fn test_len_of_unset_name() {
+		let mut slash = Slash::new();
+		let input = "set -- a b; args=\"$#args\"";
+
+		// `$#` followed by a name that isn't set is the parameter count, and then the text
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("args"), Some(SlashVal::String("2args".into())))
+	}
+}
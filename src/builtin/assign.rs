@@ -1,66 +1,23 @@
-use crate::pest_ext::ARG_RULES;
+use crate::ast::{SimpleCmd, SrcSpan, Word, WordKind};
 use crate::prelude::*;
 
-use crate::{error::{SlashErr::*, SlashErrHigh}, helper::{self}, shellenv::{HashFloat, Slash, SlashVal}, SlashResult};
-
-pub fn execute<'a>(assign: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let blame = assign.clone();
-	let mut argv = assign.filter(&ARG_RULES[..]);
-	let cmd_name = assign.scry(Rule::cmd_name).unpack()?;
-	while let Some(arg) = argv.pop_front() {
-		match arg.as_rule() {
-			Rule::arg_assign => {
-				let var_name = arg.scry(Rule::var_ident).unpack()?;
-				if let Some(val) = arg.scry(&[Rule::word,Rule::array][..]) {
-					let rule = val.as_rule();
-					let val = helper::try_expansion(slash,val)?;
-					let slash_val = match cmd_name.as_str() {
-						"string" => {
-							SlashVal::String(val.trim_quotes().to_string())
-						}
-						"int" => {
-							let slash_int = val.as_str().parse::<i32>();
-							if slash_int.is_err() {
-								let msg = format!("Expected an integer in `int` assignment");
-								return Err(High(SlashErrHigh::syntax_err(msg, blame)))
-							}
-							SlashVal::Int(slash_int.unwrap())
-						}
-						"bool" => {
-							let slash_bool = val.as_str().parse::<bool>();
-							if slash_bool.is_err() {
-								let msg = format!("Expected a boolean in `bool` assignment");
-								return Err(High(SlashErrHigh::syntax_err(msg, blame)))
-							}
-							SlashVal::Bool(slash_bool.unwrap())
-						}
-						"float" => {
-							let slash_float = val.as_str().parse::<f64>();
-							if slash_float.is_err() {
-								let msg = format!("Expected a floating point value in `float` assignment");
-								return Err(High(SlashErrHigh::syntax_err(msg, blame)))
-							}
-							SlashVal::Float(HashFloat(slash_float.unwrap()))
-						}
-						"arr" => {
-							if let Rule::array = rule {
-								let val = SlashVal::parse(val.as_str())?;
-								val
-							} else {
-								let msg = format!("Expected an array in `array` assignment");
-								return Err(High(SlashErrHigh::syntax_err(msg, blame)))
-							}
-						}
-						_ => unimplemented!("Have not yet implemented var type builtin '{}'",cmd_name.as_str())
-					};
-					slash.vars_mut().set_var(var_name.as_str(), slash_val);
+use crate::{error::{SlashErr::*, SlashErrHigh}, expand, helper::{self}, shellenv::{HashFloat, Slash, SlashVal}, SlashResult};
+
+pub fn execute(assign: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &assign.span;
+	let cmd_name = assign.name().unwrap_or_default();
+	for arg in &assign.args {
+		match &arg.kind {
+			WordKind::Assign { var: var_name, val } => {
+				if let Some(val) = val {
+					let slash_val = build_typed_val(cmd_name, val, slash, blame)?;
+					slash.vars_mut().set_var(var_name, slash_val).blame(blame)?;
 				} else {
-					slash.vars_mut().unset_var(var_name.as_str());
+					slash.vars_mut().unset_var(var_name).blame(blame)?;
 				}
 			}
-			Rule::redir => { /* Do nothing */ }
 			_ => {
-				let msg = format!("Expected assignment in '{}' args, found this: '{}'",cmd_name.as_str(),arg.as_str());
+				let msg = format!("Expected assignment in '{}' args, found this: '{}'",cmd_name,arg.as_str());
 				return Err(High(SlashErrHigh::syntax_err(msg, blame)))
 			}
 		}
@@ -68,6 +25,89 @@ pub fn execute<'a>(assign: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()>
 	Ok(())
 }
 
+/// Declares variables that only exist for the duration of the current function call
+/// `local x=1` infers the type of the value like a normal assignment, while `local int n=3` gives it a type explicitly
+pub fn local(local_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &local_call.span;
+	let mut args = local_call.args.iter().peekable();
+	let type_name = args.next_if(|arg| matches!(arg.kind, WordKind::Plain) && TYPE_NAMES.contains(&arg.as_str())).map(|arg| arg.as_str());
+
+	for arg in args {
+		match &arg.kind {
+			WordKind::Assign { var: var_name, val } => {
+				let slash_val = match (val, type_name) {
+					(Some(val), Some(type_name)) => build_typed_val(type_name, val, slash, blame)?,
+					(Some(val), None) => expand::split::expand_value(val,slash)?,
+					(None, _) => SlashVal::String(String::new())
+				};
+				slash.vars_mut().set_local(var_name, slash_val).blame(blame)?;
+			}
+			_ => {
+				// A bare name is declared local without a value
+				let var_name = helper::try_expansion(slash,arg)?;
+				slash.vars_mut().set_local(&var_name, SlashVal::String(String::new())).blame(blame)?;
+			}
+		}
+	}
+	Ok(())
+}
+
+const TYPE_NAMES: [&str;6] = ["string", "int", "bool", "float", "arr", "dict"];
+
+fn build_typed_val(type_name: &str, val: &Word, slash: &mut Slash, blame: &SrcSpan) -> SlashResult<SlashVal> {
+	let is_array = matches!(val.kind, WordKind::Array);
+	let is_dict = matches!(val.kind, WordKind::Dict);
+	if type_name == "string" {
+		return Ok(SlashVal::String(expand::split::expand_value(val,slash)?.to_string()))
+	}
+	let val = helper::try_expansion(slash,val)?;
+	let slash_val = match type_name {
+		"int" => {
+			let slash_int = val.as_str().parse::<i64>();
+			if slash_int.is_err() {
+				let msg = format!("Expected an integer in `int` assignment");
+				return Err(High(SlashErrHigh::syntax_err(msg, blame)))
+			}
+			SlashVal::Int(slash_int.unwrap())
+		}
+		"bool" => {
+			let slash_bool = val.as_str().parse::<bool>();
+			if slash_bool.is_err() {
+				let msg = format!("Expected a boolean in `bool` assignment");
+				return Err(High(SlashErrHigh::syntax_err(msg, blame)))
+			}
+			SlashVal::Bool(slash_bool.unwrap())
+		}
+		"float" => {
+			let slash_float = val.as_str().parse::<f64>();
+			if slash_float.is_err() {
+				let msg = format!("Expected a floating point value in `float` assignment");
+				return Err(High(SlashErrHigh::syntax_err(msg, blame)))
+			}
+			SlashVal::Float(HashFloat(slash_float.unwrap()))
+		}
+		"arr" => {
+			if is_array {
+				let val = SlashVal::parse(val.as_str())?;
+				val
+			} else {
+				let msg = format!("Expected an array in `array` assignment");
+				return Err(High(SlashErrHigh::syntax_err(msg, blame)))
+			}
+		}
+		"dict" => {
+			if is_dict {
+				SlashVal::parse(val.as_str())?
+			} else {
+				let msg = "Expected a dict in `dict` assignment";
+				return Err(High(SlashErrHigh::syntax_err(msg, blame)))
+			}
+		}
+		_ => unimplemented!("Have not yet implemented var type builtin '{}'",type_name)
+	};
+	Ok(slash_val)
+}
+
 #[cfg(test)]
 mod tests {
 	use crate::execute;
@@ -128,4 +168,23 @@ use super::*;
 			assert_eq!(list.remove(0), SlashVal::Int(1));
 		} else { panic!() }
 	}
+	#[test]
+	fn test_local_scope() {
+		let mut slash = Slash::new();
+		let input = "int x=1; int y=1; f() { local int x=2; y=$x; g; }; g() { x=3; }; f";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		// g() sees f()'s local x, and the global y is written through
+		assert_eq!(slash.vars().get_var("x"), Some(SlashVal::Int(1)));
+		assert_eq!(slash.vars().get_var("y"), Some(SlashVal::Int(2)));
+	}
+	#[test]
+	fn test_positional_params() {
+		let mut slash = Slash::new();
+		let input = "f() { first=$1; count=$#; }; f foo bar";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("first"), Some(SlashVal::String("foo".into())));
+		assert_eq!(slash.vars().get_var("count"), Some(SlashVal::Int(2)));
+	}
 }
@@ -1,10 +1,9 @@
 use crate::prelude::*;
 
-use crate::{helper::{self}, shellenv::Slash, SlashResult, pest_ext::Rule};
+use crate::{ast::SimpleCmd, helper::{self}, shellenv::Slash, SlashResult};
 
-pub fn execute<'a>(cd_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let blame = cd_call.clone();
-	let mut argv = helper::prepare_argv(cd_call,slash)?;
+pub fn execute(cd_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let mut argv = helper::prepare_argv(cd_call.words(),slash)?;
 	argv.pop_front();
 	let new_pwd;
 	match argv.pop_front() {
@@ -19,8 +18,9 @@ pub fn execute<'a>(cd_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()>
 			new_pwd = env::var("HOME").unwrap_or("/".into());
 		}
 	}
-	slash.vars_mut().export_var("OLDPWD", &env::var("PWD").unwrap_or_default());
+	let blame = &cd_call.span;
+	slash.vars_mut().export_var("OLDPWD", &env::var("PWD").unwrap_or_default()).blame(blame)?;
 	env::set_current_dir(new_pwd)?;
-	slash.vars_mut().export_var("PWD", env::current_dir().unwrap().to_str().unwrap());
+	slash.vars_mut().export_var("PWD", env::current_dir().unwrap().to_str().unwrap()).blame(blame)?;
 	Ok(())
 }
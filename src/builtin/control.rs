@@ -1,7 +1,7 @@
-use crate::{helper, prelude::*};
+use crate::{ast::SimpleCmd, helper, prelude::*};
 
-pub fn exit<'a>(pair: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let mut argv = helper::prepare_argv(pair, slash)?;
+pub fn exit(call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let mut argv = helper::prepare_argv(call.words(), slash)?;
 	argv.pop_front();
 	let code = if let Some(arg) = argv.pop_front() {
 		let word = arg.as_str();
@@ -16,8 +16,8 @@ pub fn exit<'a>(pair: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
 	Err(Low(SlashErrLow::CleanExit(code)))
 }
 
-pub fn func_return<'a>(pair: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let mut argv = helper::prepare_argv(pair, slash)?;
+pub fn func_return(call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let mut argv = helper::prepare_argv(call.words(), slash)?;
 	argv.pop_front();
 	let code = if let Some(arg) = argv.pop_front() {
 		let word = arg.as_str();
@@ -32,22 +32,27 @@ pub fn func_return<'a>(pair: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()
 	Err(Low(SlashErrLow::FuncReturn(code)))
 }
 
-pub fn loop_break<'a>(pair: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let mut argv = helper::prepare_argv(pair, slash)?;
-	argv.pop_front();
-	let code = if let Some(arg) = argv.pop_front() {
-		let word = arg.as_str();
-		if let Ok(code) = word.parse::<i32>() {
-			code
-		} else {
-			0
-		}
-	} else {
-		0
-	};
-	Err(Low(SlashErrLow::LoopBreak(code)))
+/// `break N` gets out of N enclosing loops, and just the innermost one by default
+pub fn loop_break(call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let levels = loop_levels(call, slash)?;
+	Err(Low(SlashErrLow::LoopBreak(levels)))
 }
 
-pub fn loop_continue<'a>() -> SlashResult<()> {
-	Err(Low(SlashErrLow::LoopCont))
+/// `continue N` skips to the next pass of the Nth enclosing loop, and the innermost one by default
+pub fn loop_continue(call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let levels = loop_levels(call, slash)?;
+	Err(Low(SlashErrLow::LoopCont(levels)))
+}
+
+fn loop_levels(call: &SimpleCmd, slash: &mut Slash) -> SlashResult<usize> {
+	let mut argv = helper::prepare_argv(call.words(), slash)?;
+	argv.pop_front();
+	match argv.pop_front() {
+		Some(arg) => match arg.parse::<usize>() {
+			// Asking for more loops than there are leaves all of them, like in bash
+			Ok(levels) if levels > 0 => Ok(levels.min(slash.ctx().loop_depth().max(1))),
+			_ => Err(High(SlashErrHigh::exec_err(format!("Loop count out of range: {}",arg), &call.span)))
+		}
+		None => Ok(1)
+	}
 }
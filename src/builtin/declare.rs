@@ -0,0 +1,230 @@
+use crate::{ast::{SimpleCmd, SrcSpan, Word, WordKind}, expand, helper, prelude::*, shellenv::{SlashVal, VarFlags}, utils};
+
+/// Declares variables with attributes, or prints them with `-p`
+/// Inside of a function, variables are declared local unless `-g` is given
+pub fn declare(declare_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	execute(declare_call, VarFlags::empty(), slash)
+}
+
+/// Marks variables as readonly, optionally assigning them first
+pub fn readonly(readonly_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	execute(readonly_call, VarFlags::READONLY, slash)
+}
+
+fn execute(call: &SimpleCmd, forced: VarFlags, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &call.span;
+	let cmd_name = call.name().unwrap_or_default();
+	let redirs = helper::prepare_redirs(&call.redirs);
+	slash.consume_redirs(redirs)?;
+
+	let mut add = forced;
+	let mut remove = VarFlags::empty();
+	let mut print = false;
+	let mut global = !forced.is_empty();
+	let mut args = call.args.iter().peekable();
+	while let Some(arg) = args.next_if(|arg| !arg.is_assign() && arg.as_str().starts_with(['-','+'])) {
+		if arg.as_str() == "--" {
+			break
+		}
+		let (sign, letters) = arg.as_str().split_at(1);
+		for ch in letters.chars() {
+			let flag = match ch {
+				'p' => { print = true; continue }
+				'g' => { global = true; continue }
+				'i' => VarFlags::INTEGER,
+				'a' => VarFlags::ARRAY,
+				'A' => VarFlags::DICT,
+				'r' => VarFlags::READONLY,
+				'x' => VarFlags::EXPORT,
+				'l' => VarFlags::LOWER,
+				'u' => VarFlags::UPPER,
+				_ => return Err(High(SlashErrHigh::syntax_err(format!("Invalid flag in `{}' call: {}{}",cmd_name,sign,ch), &arg.span)))
+			};
+			if sign == "-" {
+				add |= flag
+			} else {
+				remove |= flag
+			}
+		}
+	}
+	let args = args.collect::<Vec<&Word>>();
+
+	if print || (args.is_empty() && remove.is_empty()) {
+		return print_vars(&args, add, slash, blame)
+	}
+
+	let local = slash.vars().in_func_scope() && !global;
+	for arg in args {
+		let (var_name, val) = match &arg.kind {
+			WordKind::Assign { var, val } => (var.clone(), val.as_deref()),
+			_ => (helper::try_expansion(slash,arg)?, None)
+		};
+		let val = match val {
+			Some(val) => Some(expand::split::expand_value(val,slash)?),
+			None => None
+		};
+		// Arrays and dicts start out empty instead of unset
+		let default = if add.contains(VarFlags::ARRAY) {
+			Some(SlashVal::Array(vec![]))
+		} else if add.contains(VarFlags::DICT) {
+			Some(SlashVal::Dict(BTreeMap::new()))
+		} else {
+			None
+		};
+		let exists = slash.vars().get_var(&var_name).is_some();
+
+		let vars = slash.vars_mut();
+		if local && !vars.is_local(&var_name) {
+			let init = val.clone().or(default.clone()).unwrap_or(SlashVal::String(String::new()));
+			vars.set_local(&var_name, init).blame(blame)?;
+		}
+		vars.remove_attrs(&var_name, remove).blame(blame)?;
+		vars.add_attrs(&var_name, add.difference(VarFlags::READONLY));
+		if let Some(val) = val {
+			vars.set_var(&var_name, val).blame(blame)?;
+		} else if let Some(default) = default.filter(|_| !exists) {
+			vars.set_var(&var_name, default).blame(blame)?;
+		} else if add.contains(VarFlags::EXPORT) {
+			if let Some(val) = vars.get_var(&var_name) {
+				vars.export_var(&var_name, &val.to_string()).blame(blame)?;
+			}
+		}
+		if add.contains(VarFlags::READONLY) {
+			vars.add_attrs(&var_name, VarFlags::READONLY);
+		}
+	}
+	Ok(())
+}
+
+/// Prints variables in a form that can be used as input again
+/// If no names are given, every variable with the given attributes is printed
+fn print_vars(names: &[&Word], filter: VarFlags, slash: &mut Slash, blame: &SrcSpan) -> SlashResult<()> {
+	let mut stdout = utils::SmartFD::new(1)?;
+	let names = if names.is_empty() {
+		slash.vars().visible_vars()
+			.into_keys()
+			.filter(|name| slash.vars().get_attrs(name).contains(filter))
+			.map(|name| name.to_string())
+			.collect::<Vec<String>>()
+	} else {
+		let mut expanded = vec![];
+		for name in names {
+			expanded.push(helper::try_expansion(slash,name)?);
+		}
+		expanded
+	};
+
+	for name in names {
+		let Some(val) = slash.vars().get_var(&name) else {
+			return Err(High(SlashErrHigh::exec_err(format!("Variable `{}' not found",name), blame)))
+		};
+		let mut attrs = slash.vars().get_attrs(&name);
+		if !slash.vars().visible_vars().contains_key(name.as_str()) && slash.vars().get_evar(&name).is_some() {
+			attrs |= VarFlags::EXPORT;
+		}
+		writeln!(stdout,"{}",fmt_declaration(&name, attrs, &val))?;
+	}
+	Ok(())
+}
+
+fn fmt_declaration(name: &str, attrs: VarFlags, val: &SlashVal) -> String {
+	let flags = [
+		(VarFlags::ARRAY,'a'),
+		(VarFlags::DICT,'A'),
+		(VarFlags::INTEGER,'i'),
+		(VarFlags::LOWER,'l'),
+		(VarFlags::READONLY,'r'),
+		(VarFlags::UPPER,'u'),
+		(VarFlags::EXPORT,'x'),
+	].iter()
+		.filter(|(flag,_)| attrs.contains(*flag))
+		.map(|(_,ch)| *ch)
+		.collect::<String>();
+	let flags = if flags.is_empty() { "-".to_string() } else { flags };
+	format!("declare -{} {}={}",flags,name,fmt_val(val))
+}
+
+/// Formats a value so that parsing it again gives back the same type
+fn fmt_val(val: &SlashVal) -> String {
+	match val {
+		SlashVal::String(string) => {
+			// Single quotes keep the value from being expanded again, so a quote inside of it has to step outside of them
+			format!("'{}'",string.replace('\'', "'\\''"))
+		}
+		SlashVal::Float(float) => format!("{:?}",float.0),
+		SlashVal::Int(_) | SlashVal::Bool(_) => val.to_string(),
+		// There is no literal for null, so the closest thing is an empty value
+		SlashVal::Null => "''".to_string(),
+		SlashVal::Array(array) => {
+			let elements = array.iter().map(fmt_val).collect::<Vec<String>>();
+			format!("[{}]",elements.join(", "))
+		}
+		SlashVal::Dict(dict) => {
+			let entries = dict.iter().map(|(key,val)| format!("{}: {}",key,fmt_val(val))).collect::<Vec<String>>();
+			format!("{{{}}}",entries.join(", "))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::execute;
+
+	use super::*;
+
+	#[test]
+	fn test_declare_integer() {
+		let mut slash = Slash::new();
+		let input = "declare -i n=5";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("n"), Some(SlashVal::Int(5)));
+		assert!(execute::dispatch::exec_input("n=abc".to_string(), &mut slash).is_err());
+	}
+	#[test]
+	fn test_declare_case() {
+		let mut slash = Slash::new();
+		let input = "declare -u s=hello; declare -l t; t=LOUD";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("s"), Some(SlashVal::String("HELLO".into())));
+		assert_eq!(slash.vars().get_var("t"), Some(SlashVal::String("loud".into())));
+	}
+	#[test]
+	fn test_declare_array() {
+		let mut slash = Slash::new();
+		let input = "declare -a list";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("list"), Some(SlashVal::Array(vec![])))
+	}
+	#[test]
+	fn test_readonly() {
+		let mut slash = Slash::new();
+		let input = "readonly x=1";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert!(execute::dispatch::exec_input("x=2".to_string(), &mut slash).is_err());
+		assert!(execute::dispatch::exec_input("int x=2".to_string(), &mut slash).is_err());
+		assert!(execute::dispatch::exec_input("export x=2".to_string(), &mut slash).is_err());
+		assert!(execute::dispatch::exec_input("declare +r x".to_string(), &mut slash).is_err());
+		assert_eq!(slash.vars().get_var("x"), Some(SlashVal::Int(1)));
+	}
+	#[test]
+	fn test_declare_print() {
+		let mut slash = Slash::new();
+		let input = "declare -i n=5; declare -u s='say \"hi\"'; declare q='it'\\''s $HOME'; arr list=[1, \"two\", 3.0]";
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("q"), Some(SlashVal::String("it's $HOME".into())));
+
+		// Feeding the printed declarations back into a new shell should recreate the variables
+		let mut copy = Slash::new();
+		for name in ["n","s","q","list"] {
+			let val = slash.vars().get_var(name).unwrap();
+			let declaration = fmt_declaration(name, slash.vars().get_attrs(name), &val);
+			execute::dispatch::exec_input(declaration, &mut copy).unwrap();
+			assert_eq!(copy.vars().get_var(name), Some(val));
+			assert_eq!(copy.vars().get_attrs(name), slash.vars().get_attrs(name));
+		}
+	}
+}
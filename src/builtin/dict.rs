@@ -0,0 +1,140 @@
+use crate::{ast::{SimpleCmd, WordKind}, helper::{self, StrExtension}, prelude::*, shellenv::SlashVal, utils};
+
+/// `dict d={a: 1, b: [1,2]}` declares dicts the same way as the other type builtins
+/// Otherwise the first argument is an operation on the dict named by the second, and any arguments after that are keys leading into it:
+/// `keys` and `values` print one entry per line, `len` prints the number of entries,
+/// `has` succeeds if the last key exists, and `del` removes it
+pub fn execute(dict_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<i32> {
+	let blame = &dict_call.span;
+	if dict_call.args.first().is_some_and(|arg| matches!(arg.kind, WordKind::Assign { .. })) {
+		super::assign::execute(dict_call, slash)?;
+		return Ok(0)
+	}
+	let mut argv = helper::prepare_argv(dict_call.words(), slash)?;
+	argv.pop_front();
+	let redirs = helper::prepare_redirs(&dict_call.redirs);
+	slash.consume_redirs(redirs)?;
+
+	let (Some(op), Some(var_name)) = (argv.pop_front(), argv.pop_front()) else {
+		return Err(High(SlashErrHigh::syntax_err("Expected an operation and a variable name in `dict' call", blame)))
+	};
+	let keys = argv.into_iter().map(|key| key.trim_quotes().to_string()).collect::<Vec<String>>();
+	let Some(mut root) = slash.vars().get_var(&var_name) else {
+		return Err(High(SlashErrHigh::exec_err(format!("`{}' is not set",var_name), blame)))
+	};
+
+	let mut stdout = utils::SmartFD::new(1)?;
+	match op.as_str() {
+		"keys" | "values" | "len" => {
+			let Some(target) = root.get_element(&keys) else {
+				return Ok(1)
+			};
+			match (op.as_str(), target) {
+				("len", SlashVal::Dict(map)) => writeln!(stdout,"{}",map.len())?,
+				("len", SlashVal::Array(arr)) => writeln!(stdout,"{}",arr.len())?,
+				("keys", SlashVal::Dict(map)) => {
+					for key in map.keys() {
+						writeln!(stdout,"{}",key)?;
+					}
+				}
+				("values", SlashVal::Dict(map)) => {
+					for val in map.values() {
+						writeln!(stdout,"{}",val)?;
+					}
+				}
+				(_, val) => {
+					let msg = format!("Expected a dict in `dict {}' call, found a value of type {}",op,val.fmt_type());
+					return Err(High(SlashErrHigh::exec_err(msg, blame)))
+				}
+			}
+		}
+		"has" | "del" => {
+			let Some((last,path)) = keys.split_last() else {
+				return Err(High(SlashErrHigh::syntax_err(format!("Expected a key in `dict {}' call",op), blame)))
+			};
+			if op == "has" {
+				let found = root.get_element(path).and_then(|val| val.get_element(std::slice::from_ref(last))).is_some();
+				return Ok(if found { 0 } else { 1 })
+			}
+			let Some(mut target) = root.get_element(path).cloned() else {
+				return Ok(1)
+			};
+			if target.try_remove(last).blame(blame)?.is_none() {
+				return Ok(1)
+			}
+			root.set_element(path, target).blame(blame)?;
+			slash.vars_mut().set_var(&var_name, root).blame(blame)?;
+		}
+		_ => return Err(High(SlashErrHigh::syntax_err(format!("Unknown operation in `dict' call: {}",op), blame)))
+	}
+	Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::execute;
+
+	use super::*;
+
+	#[test]
+	fn test_dict_index() {
+		let mut slash = Slash::new();
+		let input = "dict d={a: 1, b: [1,2]}; int one=$d[\"a\"]; second=$d['b'][1]";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("one"), Some(SlashVal::Int(1)));
+		assert_eq!(slash.vars().get_var("second"), Some(SlashVal::Int(2)));
+	}
+	#[test]
+	fn test_dict_expanded_key() {
+		let mut slash = Slash::new();
+		let input = "dict d={c: {name: \"foo bar\"}}; key=c; name=$d[\"$key\"][\"name\"]";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("name"), Some(SlashVal::String("foo bar".into())))
+	}
+	#[test]
+	fn test_dict_assign_element() {
+		let mut slash = Slash::new();
+		let input = "dict d={b: [1,2]}; d[\"b\"][0]=7; d[\"c\"][\"n\"]=5";
+
+		// Keys that don't exist yet are created as dicts
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		let d = slash.vars().get_var("d").unwrap();
+		assert_eq!(d.get_element(&["b".into()]), Some(&SlashVal::Array(vec![SlashVal::Int(7), SlashVal::Int(2)])));
+		assert_eq!(d.get_element(&["c".into(), "n".into()]), Some(&SlashVal::Int(5)));
+	}
+	#[test]
+	fn test_dict_assign_new_var() {
+		let mut slash = Slash::new();
+		let input = "new[\"x\"]=1";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("new").unwrap().get_element(&["x".into()]), Some(&SlashVal::Int(1)))
+	}
+	#[test]
+	fn test_dict_len_and_keys() {
+		let mut slash = Slash::new();
+		let input = "dict d={a: 1, c: {name: x, n: 5}}; count=$(dict len d); keys=$(dict keys d c)";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("count"), Some(SlashVal::Int(2)));
+		assert_eq!(slash.vars().get_var("keys"), Some(SlashVal::String("n\nname".into())));
+	}
+	#[test]
+	fn test_dict_has() {
+		let mut slash = Slash::new();
+		let input = "dict d={c: {n: 5}}; dict has d c n";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 0)
+	}
+	#[test]
+	fn test_dict_del() {
+		let mut slash = Slash::new();
+		let input = "dict d={a: 1}; dict del d a; dict has d a";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 1)
+	}
+}
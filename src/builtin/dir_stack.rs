@@ -1,9 +1,10 @@
 use crate::prelude::*;
 
-use crate::{helper, shellenv::Slash, SlashResult};
+use crate::{ast::SimpleCmd, helper, shellenv::Slash, SlashResult};
 
-pub fn popd<'a>(popd_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let mut argv = helper::prepare_argv(popd_call.clone(),slash)?;
+pub fn popd(popd_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &popd_call.span;
+	let mut argv = helper::prepare_argv(popd_call.words(),slash)?;
 	argv.pop_front();
 	let arg = argv.pop_front();
 	let mut path = None;
@@ -19,7 +20,7 @@ pub fn popd<'a>(popd_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()>
 			}
 			Err(_) => {
 				let msg = "`popd` expects a positive integer";
-				return Err(High(SlashErrHigh::syntax_err(msg, popd_call)))
+				return Err(High(SlashErrHigh::syntax_err(msg, blame)))
 			}
 		}
 	} else {
@@ -31,23 +32,23 @@ pub fn popd<'a>(popd_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()>
 				if path.is_dir() {
 					slash.change_dir(&path)?;
 				} else {
-					return Err(High(SlashErrHigh::syntax_err("Path is not a directory", popd_call)))
+					return Err(High(SlashErrHigh::syntax_err("Path is not a directory", blame)))
 				}
 			} else {
-				return Err(High(SlashErrHigh::syntax_err("Path does not exist", popd_call)))
+				return Err(High(SlashErrHigh::syntax_err("Path does not exist", blame)))
 			}
 		}
 		None => {
 			let msg = "`popd` called with an empty directory stack";
-			return Err(High(SlashErrHigh::exec_err(msg, popd_call)))
+			return Err(High(SlashErrHigh::exec_err(msg, blame)))
 		}
 	}
 	Ok(())
 }
 
-pub fn pushd<'a>(pushd_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let blame = pushd_call.clone();
-	let mut argv = helper::prepare_argv(pushd_call,slash)?;
+pub fn pushd(pushd_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &pushd_call.span;
+	let mut argv = helper::prepare_argv(pushd_call.words(),slash)?;
 	argv.pop_front();
 	match argv.pop_front() {
 		Some(arg) => {
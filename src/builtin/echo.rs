@@ -1,6 +1,6 @@
-use crate::{prelude::*, utils};
+use crate::{ast::SimpleCmd, prelude::*, utils};
 
-use crate::{error::{SlashErr::*, SlashErrHigh}, helper::{self, StrExtension}, shellenv::{write_jobs, ChildProc, JobBuilder, Slash}, SlashResult, pest_ext::Rule};
+use crate::{error::{SlashErr::*, SlashErrHigh}, helper, shellenv::{write_jobs, ChildProc, JobBuilder, Slash}, SlashResult, pest_ext::Rule};
 
 bitflags! {
 	#[derive(Debug)]
@@ -12,13 +12,13 @@ bitflags! {
 		const EXPAND_OX_ESC = 0b10000;
 	}
 }
-pub fn execute<'a>(echo_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
+pub fn execute(echo_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
 	let mut flags = EchoFlags::empty();
-	let blame = echo_call.clone();
-	let mut argv = helper::prepare_argv(echo_call.clone(),slash)?;
+	let blame = &echo_call.span;
+	let mut argv = helper::prepare_argv(echo_call.words(),slash)?;
 	argv.pop_front();
 	let mut arg_buffer = vec![];
-	let redirs = helper::prepare_redirs(echo_call)?;
+	let redirs = helper::prepare_redirs(&echo_call.redirs);
 
 	while let Some(arg) = argv.pop_front() {
 		if arg.as_str().starts_with('-') {
@@ -45,12 +45,12 @@ pub fn execute<'a>(echo_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<(
 				}
 			}
 			if new_flags.is_empty() {
-				arg_buffer.push(arg.as_str().trim_quotes().to_string());
+				arg_buffer.push(arg);
 			} else {
 				flags |= new_flags;
 			}
 		} else {
-			arg_buffer.push(arg.as_str().trim_quotes().to_string());
+			arg_buffer.push(arg);
 		}
 	}
 
@@ -74,6 +74,15 @@ pub fn execute<'a>(echo_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<(
 		}
 		std::process::exit(0);
 	}
+	if !slash.ctx().flags().contains(utils::ExecFlags::BACKGROUND) {
+		// Only a background echo needs a process of its own
+		if newline {
+			writeln!(target_fd,"{}",output)?;
+		} else {
+			write!(target_fd,"{}",output)?;
+		}
+		return Ok(())
+	}
 	match unsafe { fork() } {
 		Ok(ForkResult::Child) => {
 			if newline {
@@ -84,7 +93,7 @@ pub fn execute<'a>(echo_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<(
 			std::process::exit(0);
 		}
 		Ok(ForkResult::Parent { child }) => {
-			setpgid(child, child).map_err(|_| High(SlashErrHigh::io_err(blame.clone())))?;
+			setpgid(child, child).map_err(|_| High(SlashErrHigh::io_err(blame)))?;
 			let children = vec![
 				ChildProc::new(child, Some("echo"), None)?
 			];
@@ -1,22 +1,13 @@
-use crate::{execute, helper, prelude::*, utils};
+use crate::{ast::{Command, SimpleCmd}, execute, prelude::*, utils};
 
 
-pub fn run_exec<'a>(exec_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let mut inner = exec_call.clone().into_inner();
-	let exec = inner.next().unwrap().as_str();
-	if let Some(pair) = inner.next() {
-		match pair.as_rule() {
-			Rule::word => {
-				// Exec a command
-				let new_input = &exec_call.as_str()[exec.len()..].trim(); // slice off 'exec'
-				*slash.ctx_mut().flags_mut() |= utils::ExecFlags::NO_FORK; // we ain't comin back
-				execute::dispatch::exec_input(new_input.to_string(), slash).blame(exec_call)?;
-			}
-			Rule::redir => {
-				// Mess with file descriptors
-			}
-			_ => unreachable!()
-		}
+pub fn run_exec(exec_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	if let Some(new_cmd) = exec_call.shift() {
+		// Exec a command
+		*slash.ctx_mut().flags_mut() |= utils::ExecFlags::NO_FORK; // we ain't comin back
+		execute::dispatch::dispatch_exec(&Command::Simple(new_cmd), slash).blame(&exec_call.span)?;
+	} else if !exec_call.redirs.is_empty() {
+		// Mess with file descriptors
 	}
 
 
@@ -1,24 +1,20 @@
 use crate::prelude::*;
 
-use crate::{helper, pest_ext::ARG_RULES, shellenv::Slash, SlashResult};
-
-pub fn execute<'a>(export_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let mut argv = export_call.filter(&ARG_RULES[..]);
-	while let Some(arg) = argv.pop_front() {
-		match arg.as_rule() {
-			Rule::cmd_name => continue,
-			Rule::arg_assign => {
-				let mut assign_inner = arg.into_inner();
-				let var_name = assign_inner.next().unpack()?.as_str();
-				let val = match assign_inner.next() {
-					Some(pair) => helper::try_expansion(slash,pair)?,
+use crate::{ast::{SimpleCmd, WordKind}, helper, shellenv::Slash, SlashResult};
+
+pub fn execute(export_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	for arg in &export_call.args {
+		match &arg.kind {
+			WordKind::Assign { var: var_name, val } => {
+				let val = match val {
+					Some(word) => helper::try_expansion(slash,word)?,
 					None => String::new()
 				};
-				slash.vars_mut().export_var(var_name, &val);
+				slash.vars_mut().export_var(var_name, &val).blame(&arg.span)?;
 			}
 			_ => {
 				let msg = String::from("Expected an assignment in export args, got this");
-				return Err(High(SlashErrHigh::syntax_err(msg, arg)))
+				return Err(High(SlashErrHigh::syntax_err(msg, &arg.span)))
 			}
 		}
 	}
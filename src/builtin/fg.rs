@@ -1,9 +1,9 @@
 use crate::prelude::*;
 
-use crate::{helper::{self}, shellenv::{read_jobs, Slash}, SlashResult};
+use crate::{ast::SimpleCmd, helper::{self}, shellenv::{read_jobs, Slash}, SlashResult};
 
-pub fn execute<'a>(fg_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let mut argv = helper::prepare_argv(fg_call,slash)?;
+pub fn execute(fg_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let mut argv = helper::prepare_argv(fg_call.words(),slash)?;
 	argv.pop_front();
 	let jobs = read_jobs(|j| j.clone())?;
 
@@ -1,10 +1,13 @@
-use crate::{helper, prelude::*, shellenv::{read_jobs, write_jobs, JobCmdFlags, JobID}, utils};
+use crate::{ast::{SimpleCmd, SrcSpan}, helper, prelude::*, shellenv::{read_jobs, write_jobs, JobCmdFlags, JobID}, utils};
+use std::os::fd::OwnedFd;
 
-pub fn continue_job<'a>(fg_call: Pair<'a,Rule>,slash: &mut Slash, fg: bool) -> SlashResult<()> {
+use nix::{poll::{poll, PollFd, PollFlags, PollTimeout}, sys::wait::{waitpid, WaitPidFlag, WaitStatus}};
+
+pub fn continue_job(fg_call: &SimpleCmd,slash: &mut Slash, fg: bool) -> SlashResult<()> {
 	let mut stdout = utils::SmartFD::new(1)?;
-	let mut argv = helper::prepare_argv(fg_call.clone(), slash)?;
-	let blame = fg_call.clone();
-	let redirs = helper::prepare_redirs(fg_call)?;
+	let mut argv = helper::prepare_argv(fg_call.words(), slash)?;
+	let blame = &fg_call.span;
+	let redirs = helper::prepare_redirs(&fg_call.redirs);
 	argv.pop_front();
 	slash.consume_redirs(redirs)?;
 
@@ -19,7 +22,7 @@ pub fn continue_job<'a>(fg_call: Pair<'a,Rule>,slash: &mut Slash, fg: bool) -> S
 	};
 
 	let job_id = match argv.pop_front() {
-		Some(arg) => parse_job_id(&arg, blame.clone())?,
+		Some(arg) => parse_job_id(&arg, blame)?,
 		None => curr_job_id
 	};
 
@@ -47,12 +50,12 @@ pub fn continue_job<'a>(fg_call: Pair<'a,Rule>,slash: &mut Slash, fg: bool) -> S
 	Ok(())
 }
 
-pub fn jobs<'a>(jobs_call: Pair<'a,Rule>,slash: &mut Slash) -> SlashResult<()> {
-	let mut argv = helper::prepare_argv(jobs_call.clone(), slash)?;
-	let mut redirs = helper::prepare_redirs(jobs_call.clone())?;
+pub fn jobs(jobs_call: &SimpleCmd,slash: &mut Slash) -> SlashResult<()> {
+	let mut argv = helper::prepare_argv(jobs_call.words(), slash)?;
+	let mut redirs = helper::prepare_redirs(&jobs_call.redirs);
 	let mut stdout = utils::SmartFD::new(1)?;
 	slash.consume_redirs(redirs)?;
-	let blame = jobs_call;
+	let blame = &jobs_call.span;
 	argv.pop_front();
 
 	let mut flags = JobCmdFlags::empty();
@@ -81,11 +84,14 @@ pub fn jobs<'a>(jobs_call: Pair<'a,Rule>,slash: &mut Slash) -> SlashResult<()> {
 	Ok(())
 }
 
-fn parse_job_id<'a>(arg: &str, blame: Pair<'a,Rule>) -> SlashResult<usize> {
+fn parse_job_id(arg: &str, blame: &SrcSpan) -> SlashResult<usize> {
 	if arg.starts_with('%') {
 		let arg = arg.strip_prefix('%').unwrap();
-		if arg.chars().all(|ch| ch.is_ascii_digit()) {
-			Ok(arg.parse::<usize>().unwrap())
+		if !arg.is_empty() && arg.chars().all(|ch| ch.is_ascii_digit()) {
+			// Job numbers are displayed starting from 1
+			arg.parse::<usize>().unwrap()
+				.checked_sub(1)
+				.ok_or_else(|| High(SlashErrHigh::syntax_err("Job numbers start at 1", blame)))
 		} else {
 			let result = write_jobs(|j| {
 				let query_result = j.query(JobID::Command(arg.into()));
@@ -104,7 +110,7 @@ fn parse_job_id<'a>(arg: &str, blame: Pair<'a,Rule>) -> SlashResult<usize> {
 			}
 
 			if arg.parse::<i32>().unwrap() > 0 {
-				let table_id_query_result = j.query(JobID::TableID(arg.parse::<usize>().unwrap()));
+				let table_id_query_result = j.query(JobID::TableID(arg.parse::<usize>().unwrap() - 1));
 				return table_id_query_result.map(|job| job.table_id().unwrap());
 			}
 
@@ -119,3 +125,229 @@ fn parse_job_id<'a>(arg: &str, blame: Pair<'a,Rule>) -> SlashResult<usize> {
 		Err(High(SlashErrHigh::syntax_err(format!("Invalid fg argument: {}",arg), blame)))
 	}
 }
+
+/// Waits for background jobs and returns the exit status of the last one waited on
+/// `wait -n` returns as soon as any one of the given jobs finishes,
+/// and `wait -f` keeps waiting on jobs that get stopped instead of returning
+pub fn wait(wait_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<i32> {
+	let mut argv = helper::prepare_argv(wait_call.words(), slash)?;
+	let blame = &wait_call.span;
+	let redirs = helper::prepare_redirs(&wait_call.redirs);
+	argv.pop_front();
+	slash.consume_redirs(redirs)?;
+
+	let mut any = false;
+	let mut force = false;
+	while argv.front().is_some_and(|arg| arg.starts_with('-')) {
+		let arg = argv.pop_front().unwrap();
+		for ch in arg.chars().skip(1) {
+			match ch {
+				'n' => any = true,
+				'f' => force = true,
+				_ => return Err(High(SlashErrHigh::syntax_err(format!("Invalid flag in `wait' call: -{}",ch), blame)))
+			}
+		}
+	}
+
+	wait_for_jobs(argv, any, force)
+}
+
+fn wait_for_jobs(argv: VecDeque<String>, any: bool, force: bool) -> SlashResult<i32> {
+	let mut code = 0;
+	let mut targets = vec![];
+	let wait_all = argv.is_empty();
+	if wait_all {
+		targets = write_jobs(|j| j.mut_jobs().iter().flatten().filter_map(|job| job.table_id()).collect::<Vec<usize>>())?;
+	}
+	for arg in argv {
+		match find_wait_target(&arg)? {
+			Some(id) => targets.push(id),
+			None => {
+				eprintln!("wait: {}: no such job",arg);
+				code = 127;
+			}
+		}
+	}
+
+	if any {
+		return wait_any(&targets, force)
+	}
+	for id in targets {
+		code = wait_job(id, force)?.unwrap_or(127);
+	}
+	// Waiting for every job succeeds no matter how the jobs exited
+	if wait_all {
+		return Ok(0)
+	}
+	Ok(code)
+}
+
+/// Resolves a `%job` spec or a pid to a job table id
+fn find_wait_target(arg: &str) -> SlashResult<Option<usize>> {
+	if let Some(spec) = arg.strip_prefix('%') {
+		read_jobs(|j| {
+			match spec {
+				"" | "%" | "+" => j.curr_job(),
+				"-" => j.prev_job(),
+				_ if spec.chars().all(|ch| ch.is_ascii_digit()) => {
+					let id = spec.parse::<usize>().ok()?.checked_sub(1)?;
+					j.query(JobID::TableID(id)).and_then(|job| job.table_id())
+				}
+				_ => j.query(JobID::Command(spec.into())).and_then(|job| job.table_id())
+			}
+		})
+	} else if let Ok(pid) = arg.parse::<i32>() {
+		read_jobs(|j| j.query(JobID::Pid(Pid::from_raw(pid))).and_then(|job| job.table_id()))
+	} else {
+		Ok(None)
+	}
+}
+
+/// Waits for a single job to finish, and removes it from the job table once it has
+/// Returns None if the job does not exist
+fn wait_job(id: usize, force: bool) -> SlashResult<Option<i32>> {
+	let job = write_jobs(|j| {
+		j.query(JobID::TableID(id))?;
+		j.remove_job(JobID::TableID(id))
+	})?;
+	let Some(mut job) = job else {
+		return Ok(None)
+	};
+
+	if force || !job.is_stopped() {
+		let flags = if force { None } else { Some(WaitPidFlag::WUNTRACED) };
+		job.wait_pgrp(flags)?;
+	}
+	let code = job.exit_code();
+	if !job.is_finished() {
+		// Stopped jobs stay in the table so that they can be resumed later
+		write_jobs(|j| j.insert_job(job, true))??;
+	}
+	Ok(Some(code))
+}
+
+/// Waits until any one of the given jobs finishes
+fn wait_any(targets: &[usize], force: bool) -> SlashResult<i32> {
+	let flags = if force {
+		WaitPidFlag::WNOHANG
+	} else {
+		WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED
+	};
+	loop {
+		let pids = read_jobs(|j| {
+			targets.iter()
+				.filter_map(|id| j.query(JobID::TableID(*id)))
+				.flat_map(|job| job.get_children())
+				.filter(|chld| !chld.is_finished())
+				.map(|chld| chld.pid())
+				.collect::<Vec<Pid>>()
+		})?;
+		for pid in &pids {
+			let status = match waitpid(*pid, Some(flags)) {
+				Ok(WaitStatus::StillAlive) | Err(Errno::ECHILD) => continue,
+				Ok(status) => status,
+				Err(_) => return Err(Low(SlashErrLow::from_io()))
+			};
+			write_jobs(|j| {
+				match j.query_mut(JobID::Pid(*pid)) {
+					Some(job) => job.update_by_id(JobID::Pid(*pid), status),
+					None => Ok(())
+				}
+			})??;
+		}
+
+		// A job that has already finished wins immediately
+		let done = read_jobs(|j| {
+			targets.iter().copied().find(|id| {
+				j.query(JobID::TableID(*id)).is_some_and(|job| job.is_finished() || (!force && job.is_stopped()))
+			})
+		})?;
+		if let Some(id) = done {
+			return Ok(wait_job(id, force)?.unwrap_or(127))
+		}
+		if pids.is_empty() {
+			return Ok(127)
+		}
+		block_until_exit(&pids)?;
+	}
+}
+
+/// Blocks until one of the given processes exits, or a signal arrives
+/// The processes are watched through pidfds rather than with `waitpid(-1)`,
+/// so that a child which some other part of the shell is waiting on is never reaped out from under it
+fn block_until_exit(pids: &[Pid]) -> SlashResult<()> {
+	let mut pidfds = vec![];
+	for pid in pids {
+		let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) };
+		if fd < 0 {
+			// The process is already gone, so the caller can go collect it
+			return Ok(())
+		}
+		pidfds.push(unsafe { OwnedFd::from_raw_fd(fd as RawFd) });
+	}
+	let mut poll_fds = pidfds.iter()
+		.map(|fd| PollFd::new(fd.as_fd(), PollFlags::POLLIN))
+		.collect::<Vec<PollFd>>();
+	match poll(&mut poll_fds, PollTimeout::NONE) {
+		// SIGCHLD also arrives when a job is stopped, which interrupts the poll so that the caller can check for it
+		Ok(_) | Err(Errno::EINTR) => Ok(()),
+		Err(e) => Err(Low(SlashErrLow::ErrNo(e)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{execute, shellenv::SlashVal};
+
+	use super::*;
+
+	#[test]
+	fn test_wait_pid() {
+		let mut slash = Slash::new();
+		let input = "sh -c 'exit 3' &; wait $!";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 3)
+	}
+	#[test]
+	fn test_wait_any() {
+		let mut slash = Slash::new();
+		let input = "sleep 1 &; sh -c 'exit 7' &; wait -n";
+
+		// The job that finishes first is the one that gets reported
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 7)
+	}
+	#[test]
+	fn test_wait_all() {
+		let mut slash = Slash::new();
+		let input = "sh -c 'exit 7' &; sleep 0.1 &; wait";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 0)
+	}
+	#[test]
+	fn test_wait_all_last_fails() {
+		let mut slash = Slash::new();
+		let input = "sleep 0.1 &; sh -c 'exit 7' &; wait";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 0)
+	}
+	#[test]
+	fn test_fg_job_spec() {
+		let mut slash = Slash::new();
+		let input = "sh -c 'sleep 0.1; exit 5' &; fg %1";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("PIPESTATUS"), Some(SlashVal::Array(vec![SlashVal::Int(5)])));
+		assert!(read_jobs(|j| j.query(JobID::TableID(0)).is_none()).unwrap());
+	}
+	#[test]
+	fn test_fg_job_zero() {
+		let mut slash = Slash::new();
+		let input = "sh -c 'exit 0' &; fg %0";
+
+		assert!(execute::dispatch::exec_input(input.to_string(), &mut slash).is_err());
+	}
+}
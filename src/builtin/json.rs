@@ -0,0 +1,187 @@
+use crate::{ast::{SimpleCmd, Word}, helper::{self, StrExtension}, prelude::*, shellenv::SlashVal, utils};
+
+/// Converts between JSON and shell values
+/// `json parse VAR` reads JSON from stdin into a variable, or from a string given after the name,
+/// and prints it back out as compact JSON if no variable is given, which assignments read as a dict or array
+/// `json dump [--pretty] VALUE` prints a value as JSON, or the JSON or lines read from stdin if no value is given
+/// `json get VALUE PATH` prints the part of a value found at a path like `.items[0].name`, failing if there is nothing there
+/// Values given as `$var` keep the type of the variable
+pub fn execute(json_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<i32> {
+	let blame = &json_call.span;
+	let redirs = helper::prepare_redirs(&json_call.redirs);
+	slash.consume_redirs(redirs)?;
+
+	let mut pretty = false;
+	let mut args = vec![];
+	for arg in &json_call.args {
+		if arg.as_str() == "--pretty" {
+			pretty = true;
+		} else {
+			args.push(arg);
+		}
+	}
+	let mut args = args.into_iter();
+	let Some(op) = args.next() else {
+		return Err(High(SlashErrHigh::syntax_err("Expected an operation in `json' call", blame)))
+	};
+	let op = helper::try_expansion(slash, op)?.trim_quotes().to_string();
+
+	let output = match op.as_str() {
+		"parse" => {
+			let var_name = match args.next() {
+				Some(word) => Some(helper::try_expansion(slash, word)?.trim_quotes().to_string()),
+				None => None
+			};
+			let input = match args.next() {
+				Some(word) => helper::try_expansion(slash, word)?.trim_quotes().to_string(),
+				None => super::structured::read_stdin()?
+			};
+			let json = serde_json::from_str::<serde_json::Value>(&input)
+				.map_err(|err| High(SlashErrHigh::exec_err(format!("Invalid JSON: {}",err), blame)))?;
+			match var_name {
+				Some(var_name) => {
+					let val = SlashVal::from_json(json).blame(blame)?;
+					slash.vars_mut().set_var(&var_name, val).blame(blame)?;
+					return Ok(0)
+				}
+				None => json.to_string()
+			}
+		}
+		"dump" => {
+			let val = match args.next() {
+				Some(word) => word_val(word, slash)?,
+				None => super::structured::deserialize(&super::structured::read_stdin()?)
+			};
+			format_json(&val, pretty)
+		}
+		"get" => {
+			let (Some(word), Some(path)) = (args.next(), args.next()) else {
+				return Err(High(SlashErrHigh::syntax_err("Expected a value and a path in `json get' call", blame)))
+			};
+			let val = word_val(word, slash)?;
+			let path = helper::try_expansion(slash, path)?.trim_quotes().to_string();
+			let keys = parse_path(&path).blame(blame)?;
+			match val.get_element(&keys) {
+				// Strings are printed as they are, so that they can be used as arguments
+				Some(SlashVal::String(string)) => string.clone(),
+				Some(val) => format_json(val, pretty),
+				None => return Ok(1)
+			}
+		}
+		_ => return Err(High(SlashErrHigh::syntax_err(format!("Unknown operation in `json' call: {}",op), blame)))
+	};
+	let mut stdout = utils::SmartFD::new(1)?;
+	writeln!(stdout,"{}",output)?;
+	Ok(0)
+}
+
+/// A word that is nothing but `$var` gives the variable itself, and anything else is parsed like an assignment
+fn word_val(word: &Word, slash: &mut Slash) -> SlashResult<SlashVal> {
+	match helper::word_var(word.as_str(), slash) {
+		Some(val) => Ok(val),
+		None => SlashVal::parse(&helper::try_expansion(slash, word)?)
+	}
+}
+
+pub fn format_json(val: &SlashVal, pretty: bool) -> String {
+	let json = val.to_json();
+	if pretty {
+		serde_json::to_string_pretty(&json).unwrap_or_default()
+	} else {
+		json.to_string()
+	}
+}
+
+/// Splits a path like `.items[0].name` or `.["some key"]` into the keys that lead to the value
+/// The path `.` is the whole value
+fn parse_path(path: &str) -> SlashResult<Vec<String>> {
+	let bad_path = || Low(SlashErrLow::InvalidSyntax(format!("Invalid JSON path: {}",path)));
+	let mut keys = vec![];
+	let mut chars = path.chars().peekable();
+	while let Some(ch) = chars.next() {
+		match ch {
+			'.' => {
+				let mut key = String::new();
+				while let Some(ch) = chars.next_if(|ch| !matches!(ch, '.' | '[')) {
+					key.push(ch);
+				}
+				if !key.is_empty() {
+					keys.push(key);
+				}
+			}
+			'[' => {
+				let mut key = String::new();
+				loop {
+					match chars.next() {
+						Some(']') => break,
+						Some(ch) => key.push(ch),
+						None => return Err(bad_path())
+					}
+				}
+				keys.push(key.trim_quotes().to_string());
+			}
+			_ => return Err(bad_path())
+		}
+	}
+	Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{execute, shellenv::HashFloat};
+
+	use super::*;
+
+	#[test]
+	fn test_json_parse() {
+		let mut slash = Slash::new();
+		let input = "json parse resp '{\"items\": [{\"name\": \"first\"}], \"count\": 2, \"ratio\": 0.5}'";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		let resp = slash.vars().get_var("resp").unwrap();
+		assert_eq!(resp.get_element(&["count".into()]), Some(&SlashVal::Int(2)));
+		assert_eq!(resp.get_element(&["ratio".into()]), Some(&SlashVal::Float(HashFloat(0.5))));
+		assert_eq!(resp.get_element(&["items".into(), "0".into(), "name".into()]), Some(&SlashVal::String("first".into())));
+	}
+	#[test]
+	fn test_json_parse_stdin() {
+		let mut slash = Slash::new();
+		let input = "nested=$(printf '{\"x\": [1, {\"y\": 2}]}' | json parse)";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("nested").unwrap().get_element(&["x".into(), "1".into(), "y".into()]), Some(&SlashVal::Int(2)))
+	}
+	#[test]
+	fn test_json_get() {
+		let mut slash = Slash::new();
+		let input = "json parse resp '{\"items\": [{\"tags\": [\"a\", \"b\"]}]}'; tag=$(json get $resp '.items[-1][\"tags\"][1]')";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("tag"), Some(SlashVal::String("b".into())))
+	}
+	#[test]
+	fn test_json_get_missing() {
+		let mut slash = Slash::new();
+		let input = "json parse resp '{\"count\": 2}'; json get $resp .missing";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 1)
+	}
+	#[test]
+	fn test_json_dump() {
+		let mut slash = Slash::new();
+		let input = "dict d={a: 1, b: [1, 2], c: {d: true}}; copy=$(json dump $d)";
+
+		// Compact JSON reads back in as the same value
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("copy"), slash.vars().get_var("d"))
+	}
+	#[test]
+	fn test_json_path() {
+		assert_eq!(parse_path(".").unwrap(), Vec::<String>::new());
+		assert_eq!(parse_path(".items[0].name").unwrap(), vec!["items", "0", "name"]);
+		assert_eq!(parse_path(".[\"a b\"][-1]").unwrap(), vec!["a b", "-1"]);
+		assert!(parse_path("items").is_err());
+		assert!(parse_path(".items[0").is_err());
+	}
+}
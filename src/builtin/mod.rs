@@ -1,5 +1,7 @@
 pub mod alias;
+pub mod arr;
 pub mod assign;
+pub mod dict;
 pub mod cd;
 pub mod echo;
 pub mod export;
@@ -13,7 +15,13 @@ pub mod control;
 pub mod job;
 pub mod cmd_override;
 pub mod exec;
+pub mod trap;
+pub mod declare;
+pub mod set;
+pub mod arith;
+pub mod json;
+pub mod structured;
 
-pub const BUILTINS: [&str; 43] = [
-	"try", "except", "return", "break", "continue", "exit", "command", "pushd", "popd", "setopt", "getopt", "type", "string", "int", "bool", "arr", "float", "dict", "expr", "echo", "jobs", "unset", "fg", "bg", "set", "builtin", "test", "[", "shift", "unalias", "alias", "export", "cd", "readonly", "declare", "local", "unset", "trap", "node", "exec", "source", "read_func", "wait",
+pub const BUILTINS: [&str; 48] = [
+	"try", "except", "return", "break", "continue", "exit", "command", "pushd", "popd", "setopt", "getopt", "type", "string", "int", "bool", "arr", "float", "dict", "expr", "echo", "jobs", "unset", "fg", "bg", "set", "builtin", "test", "[", "shift", "unalias", "alias", "export", "cd", "readonly", "declare", "local", "unset", "trap", "node", "exec", "source", "read_func", "wait", "let", "json", "where", "sort-by", "pick",
 ];
@@ -1,10 +1,10 @@
-use crate::{prelude::*, utils};
+use crate::{ast::SimpleCmd, prelude::*, utils};
 
 use crate::{error::{SlashErr::*, SlashErrHigh}, helper::{self}, shellenv::Slash, SlashResult};
 
-pub fn execute<'a>(pwd_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let blame = pwd_call.clone();
-	let redirs = helper::prepare_redirs(pwd_call)?;
+pub fn execute(pwd_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &pwd_call.span;
+	let redirs = helper::prepare_redirs(&pwd_call.redirs);
 
 	slash.ctx_mut().extend_redirs(redirs);
 
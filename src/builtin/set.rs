@@ -0,0 +1,193 @@
+use crate::{ast::SimpleCmd, helper, prelude::*, shellenv::EnvFlags, utils};
+
+/// The short flag and long name of each option that `set` can toggle
+const SET_OPTS: [(Option<char>, &str, EnvFlags); 8] = [
+	(Some('e'), "errexit", EnvFlags::EXIT_ON_ERROR),
+	(Some('f'), "noglob", EnvFlags::NO_GLOB),
+	(Some('n'), "noexec", EnvFlags::NO_EXECUTE),
+	(Some('u'), "nounset", EnvFlags::UNSET_IS_ERROR),
+	(Some('v'), "verbose", EnvFlags::PRINT_INPUT),
+	(Some('x'), "xtrace", EnvFlags::STACK_TRACE),
+	(Some('C'), "noclobber", EnvFlags::NO_OVERWRITE),
+	(None, "pipefail", EnvFlags::PIPEFAIL),
+];
+
+/// Options from other shells that the shell doesn't do anything with yet, which are refused instead of being ignored
+const UNSUPPORTED_OPTS: [(char, &str); 11] = [
+	('a', "allexport"),
+	('b', "notify"),
+	('h', "hashall"),
+	('k', "keyword"),
+	('m', "monitor"),
+	('t', "onecmd"),
+	('B', "braceexpand"),
+	('E', "errtrace"),
+	('H', "histexpand"),
+	('P', "physical"),
+	('T', "functrace"),
+];
+
+/// Toggles shell options and sets positional parameters
+/// `set -e` turns an option on and `set +e` turns it off, `set -o name` does the same using the long name
+/// `set -o` and `set +o` print the state of every option, and any arguments left over become the positional parameters
+pub fn execute(set_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &set_call.span;
+	let mut argv = helper::prepare_argv(set_call.words(), slash)?;
+	argv.pop_front();
+	let redirs = helper::prepare_redirs(&set_call.redirs);
+	slash.consume_redirs(redirs)?;
+
+	if argv.is_empty() {
+		return print_vars(slash)
+	}
+
+	let mut set_params = false;
+	while let Some(arg) = argv.front() {
+		if arg == "--" {
+			argv.pop_front();
+			set_params = true;
+			break
+		}
+		let enable = match arg.chars().next() {
+			Some('-') => true,
+			Some('+') => false,
+			_ => {
+				set_params = true;
+				break
+			}
+		};
+		let arg = argv.pop_front().unwrap();
+		for ch in arg.chars().skip(1) {
+			let flag = if ch == 'o' {
+				let Some(name) = argv.pop_front() else {
+					return print_opts(enable, slash)
+				};
+				match SET_OPTS.iter().find(|(_,opt_name,_)| *opt_name == name) {
+					Some((_,_,flag)) => *flag,
+					None if UNSUPPORTED_OPTS.iter().any(|(_,opt_name)| *opt_name == name) => {
+						return Err(High(SlashErrHigh::exec_err(format!("Option is not supported in `set' call: {}",name), blame)))
+					}
+					None => return Err(High(SlashErrHigh::syntax_err(format!("Invalid option name in `set' call: {}",name), blame)))
+				}
+			} else {
+				match SET_OPTS.iter().find(|(opt_ch,_,_)| *opt_ch == Some(ch)) {
+					Some((_,_,flag)) => *flag,
+					None if UNSUPPORTED_OPTS.iter().any(|(opt_ch,_)| *opt_ch == ch) => {
+						return Err(High(SlashErrHigh::exec_err(format!("Flag is not supported in `set' call: {}{}",&arg[..1],ch), blame)))
+					}
+					None => return Err(High(SlashErrHigh::syntax_err(format!("Invalid flag in `set' call: {}{}",&arg[..1],ch), blame)))
+				}
+			};
+			slash.meta_mut().mod_flags(|flags| flags.set(flag, enable));
+		}
+	}
+
+	if set_params {
+		slash.vars_mut().set_pos_params(argv);
+	}
+	let flags = slash.meta().flags();
+	let enabled = SET_OPTS.iter()
+		.filter(|(_,_,flag)| flags.contains(*flag))
+		.filter_map(|(ch,_,_)| *ch)
+		.collect::<String>();
+	slash.vars_mut().set_param("-", &enabled);
+	Ok(())
+}
+
+/// `set -o` prints a table of options, `set +o` prints them as commands that restore their current state
+fn print_opts(human_readable: bool, slash: &mut Slash) -> SlashResult<()> {
+	let mut stdout = utils::SmartFD::new(1)?;
+	let flags = slash.meta().flags();
+	for (_,name,flag) in SET_OPTS {
+		let enabled = flags.contains(flag);
+		if human_readable {
+			writeln!(stdout,"{:<15}{}",name,if enabled { "on" } else { "off" })?;
+		} else {
+			writeln!(stdout,"set {}o {}",if enabled { '-' } else { '+' },name)?;
+		}
+	}
+	Ok(())
+}
+
+fn print_vars(slash: &mut Slash) -> SlashResult<()> {
+	let mut stdout = utils::SmartFD::new(1)?;
+	for (name,val) in slash.vars().visible_vars() {
+		writeln!(stdout,"{}={}",name,val)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{execute, shellenv::SlashVal};
+
+	use super::*;
+
+	#[test]
+	fn test_set_flags() {
+		let mut slash = Slash::new();
+		let input = "set -eu -o noclobber; set +e";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		let flags = slash.meta().flags();
+		assert!(flags.contains(EnvFlags::UNSET_IS_ERROR | EnvFlags::NO_OVERWRITE));
+		assert!(!flags.contains(EnvFlags::EXIT_ON_ERROR));
+		assert_eq!(slash.vars().get_param("-"), Some("uC".into()));
+	}
+	#[test]
+	fn test_set_positional_params() {
+		let mut slash = Slash::new();
+		let input = "set -- foo bar; first=$1; count=$#";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("first"), Some(SlashVal::String("foo".into())));
+		assert_eq!(slash.vars().get_var("count"), Some(SlashVal::Int(2)));
+	}
+	#[test]
+	fn test_set_errexit() {
+		let mut slash = Slash::new();
+		let input = "set -e; int before=1; false; int after=1";
+
+		let result = execute::dispatch::exec_input(input.to_string(), &mut slash);
+		assert!(matches!(result, Err(Low(SlashErrLow::CleanExit(1)))));
+		assert_eq!(slash.vars().get_var("before"), Some(SlashVal::Int(1)));
+		assert_eq!(slash.vars().get_var("after"), None);
+	}
+	#[test]
+	fn test_set_nounset() {
+		let mut slash = Slash::new();
+		let input = "set -u; echo $not_a_var";
+
+		assert!(execute::dispatch::exec_input(input.to_string(), &mut slash).is_err());
+	}
+	#[test]
+	fn test_set_noexec() {
+		let mut slash = Slash::new();
+		let input = "set -n; int skipped=1";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("skipped"), None);
+	}
+	#[test]
+	fn test_set_unsupported() {
+		let mut slash = Slash::new();
+
+		// Options that nothing reads are refused instead of being turned on and ignored
+		let Err(High(err)) = execute::dispatch::exec_input("set -a".to_string(), &mut slash) else { panic!() };
+		assert!(err.to_string().contains("not supported"));
+		assert!(execute::dispatch::exec_input("set -o monitor".to_string(), &mut slash).is_err());
+		assert!(!slash.meta().flags().intersects(EnvFlags::EXPORT_ALL_VARS | EnvFlags::ENABLE_JOB_CTL));
+	}
+	#[test]
+	fn test_set_print_opts() {
+		let mut slash = Slash::new();
+		let input = "set -o pipefail; opts=\"$(set +o)\"";
+
+		// Only the options that do something are listed
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		let Some(SlashVal::String(opts)) = slash.vars().get_var("opts") else { panic!() };
+		assert_eq!(opts.lines().count(), 8);
+		assert!(opts.contains("set -o pipefail"));
+		assert!(!opts.contains("allexport"));
+	}
+}
@@ -1,10 +1,11 @@
+use crate::ast::SimpleCmd;
 use crate::prelude::*;
 
-use crate::{error::{SlashErr::*, SlashErrHigh}, helper::{self}, shellenv::Slash, SlashResult};
+use crate::{error::{SlashErr::*, SlashErrHigh}, helper::{self}, shellenv::Slash, signal::{self, TrapTarget}, SlashResult};
 
-pub fn execute<'a>(src_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let blame = src_call.clone();
-	let mut argv = helper::prepare_argv(src_call,slash)?;
+pub fn execute(src_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &src_call.span;
+	let mut argv = helper::prepare_argv(src_call.words(),slash)?;
 	argv.pop_front();
 	while let Some(arg) = argv.pop_front() {
 		let path = PathBuf::from(arg.as_str());
@@ -15,5 +16,6 @@ pub fn execute<'a>(src_call: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()
 			return Err(High(SlashErrHigh::exec_err(msg, blame)))
 		}
 	}
+	signal::run_trap(slash, TrapTarget::Return)?;
 	Ok(())
 }
@@ -0,0 +1,419 @@
+use std::{cmp::Ordering, fs, io::Read, path::Path, time::UNIX_EPOCH};
+
+use regex::Regex;
+
+use crate::{ast::SimpleCmd, helper, prelude::*, shellenv::SlashVal, utils};
+
+/// Builtins that pass values to each other when they are next to each other in a pipeline
+/// Stages next to each other run together in one process and hand their values straight to each other, so records keep their types instead of being turned into text
+/// `ls --val [-a] [PATH...]` lists files as records with a name, type, size, and modification time
+/// `where FIELD OP VALUE` keeps the records whose field compares true, where OP is one of
+/// `-eq -ne -lt -le -gt -ge -match -contains`, or the quoted `'==' '!=' '<' '<=' '>' '>=' '=~'`
+/// `sort-by FIELD [-r]` sorts records by a field, and `pick FIELD...` keeps only the given fields of each record
+/// `json parse` and `json dump [--pretty]` with no other arguments turn text into values and back
+/// Fields can be nested like `a.b`, and the field `it` is the record itself
+pub fn execute(stage: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let redirs = helper::prepare_redirs(&stage.redirs);
+	slash.consume_redirs(redirs)?;
+	let input = read_input(stage)?;
+	let val = run_stage(stage, input, slash)?;
+	write_output(&val)
+}
+
+/// Runs stages that are next to each other in a pipeline one after another, handing the value from each one to the next
+/// Each stage gets its own status, like any other command in a pipeline, and a stage that fails hands on nothing
+pub fn exec_stages(stages: &[&SimpleCmd], slash: &mut Slash) -> SlashResult<Vec<i32>> {
+	// The pipes on either end belong to all of the stages
+	slash.activate_redirs()?;
+	let mut codes = vec![];
+	let mut val = None;
+	for (i,stage) in stages.iter().enumerate() {
+		let is_last = i + 1 == stages.len();
+		let saved_fds = utils::save_fds()?;
+		let result = exec_stage(stage, val.take(), is_last, slash);
+		utils::restore_fds(saved_fds, slash)?;
+		let code = match result {
+			Ok(output) => {
+				val = Some(output);
+				0
+			}
+			Err(err) => {
+				eprintln!("{}",err);
+				val = Some(SlashVal::Array(vec![]));
+				match &err {
+					Low(low_err) => low_err.code(),
+					High(high_err) => high_err.get_err().code()
+				}
+			}
+		};
+		codes.push(code);
+	}
+	Ok(codes)
+}
+
+/// Runs one of the stages from exec_stages(), returning the value for the next stage
+/// A stage's own redirections take the place of the value on that side, the same way they would take the place of a pipe,
+/// so it reads text from a redirected input, and writes its value out as text to a redirected output and hands on nothing
+fn exec_stage(stage: &SimpleCmd, input: Option<SlashVal>, is_last: bool, slash: &mut Slash) -> SlashResult<SlashVal> {
+	let redirected = |fd: i32| stage.redirs.iter().any(|redir| redir.our_fd() == fd);
+	let reads_text = redirected(0);
+	let writes_text = is_last || redirected(1);
+	let redirs = helper::prepare_redirs(&stage.redirs);
+	slash.consume_redirs(redirs)?;
+
+	let input = match input {
+		Some(val) if !reads_text => val,
+		// The first stage reads from the pipeline's input
+		_ => read_input(stage)?
+	};
+	let val = run_stage(stage, input, slash)?;
+	if writes_text {
+		write_output(&val)?;
+		Ok(SlashVal::Array(vec![]))
+	} else {
+		Ok(val)
+	}
+}
+
+/// Text from stdin for a stage to work on, which `ls` doesn't read
+fn read_input(stage: &SimpleCmd) -> SlashResult<SlashVal> {
+	if stage.name() == Some("ls") {
+		Ok(SlashVal::Array(vec![]))
+	} else {
+		Ok(SlashVal::String(read_stdin()?))
+	}
+}
+
+fn write_output(val: &SlashVal) -> SlashResult<()> {
+	let output = serialize(val);
+	if !output.is_empty() {
+		let mut stdout = utils::SmartFD::new(1)?;
+		writeln!(stdout,"{}",output)?;
+	}
+	Ok(())
+}
+
+/// Checks whether a command can take and produce values
+pub fn is_stage(cmd: &SimpleCmd) -> bool {
+	let args = cmd.args.iter().map(|arg| arg.as_str()).collect::<Vec<&str>>();
+	match cmd.name() {
+		Some("where" | "sort-by" | "pick") => true,
+		Some("ls") => args.contains(&"--val"),
+		Some("json") => matches!(args.iter().filter(|arg| **arg != "--pretty").collect::<Vec<_>>()[..], [&"parse"] | [&"dump"]),
+		_ => false
+	}
+}
+
+fn run_stage(stage: &SimpleCmd, input: SlashVal, slash: &mut Slash) -> SlashResult<SlashVal> {
+	let blame = &stage.span;
+	let mut argv = helper::prepare_argv(stage.words(), slash)?;
+	let name = argv.pop_front().unwrap_or_default();
+	match name.as_str() {
+		"ls" => list_files(argv).blame(blame),
+		"where" => {
+			let (Some(field), Some(op), Some(target)) = (argv.pop_front(), argv.pop_front(), argv.pop_front()) else {
+				return Err(High(SlashErrHigh::syntax_err("Expected a field, an operator, and a value in `where' call", blame)))
+			};
+			let target = SlashVal::parse(&target)?;
+			let regex = match op.as_str() {
+				"-match" | "=~" => Some(Regex::new(&target.to_string()).map_err(|_| High(SlashErrHigh::exec_err(format!("Invalid regex in `where' call: {}",target), blame)))?),
+				"-eq" | "==" | "-ne" | "!=" | "-lt" | "<" | "-le" | "<=" | "-gt" | ">" | "-ge" | ">=" | "-contains" => None,
+				_ => return Err(High(SlashErrHigh::syntax_err(format!("Unknown operator in `where' call: {}",op), blame)))
+			};
+			let records = to_records(input).into_iter()
+				.filter(|record| {
+					let Some(val) = get_field(record, &field) else {
+						return false
+					};
+					match op.as_str() {
+						"-match" | "=~" => regex.as_ref().is_some_and(|regex| regex.is_match(&val.to_string())),
+						"-contains" => match val {
+							SlashVal::Array(arr) => arr.iter().any(|element| compare(element, &target) == Ordering::Equal),
+							SlashVal::Dict(dict) => dict.contains_key(&target.to_string()),
+							_ => val.to_string().contains(&target.to_string())
+						}
+						"-eq" | "==" => compare(val, &target) == Ordering::Equal,
+						"-ne" | "!=" => compare(val, &target) != Ordering::Equal,
+						"-lt" | "<" => compare(val, &target) == Ordering::Less,
+						"-le" | "<=" => compare(val, &target) != Ordering::Greater,
+						"-gt" | ">" => compare(val, &target) == Ordering::Greater,
+						_ => compare(val, &target) != Ordering::Less
+					}
+				})
+				.collect();
+			Ok(SlashVal::Array(records))
+		}
+		"sort-by" => {
+			let reverse = argv.iter().any(|arg| arg == "-r");
+			let Some(field) = argv.into_iter().find(|arg| arg != "-r") else {
+				return Err(High(SlashErrHigh::syntax_err("Expected a field in `sort-by' call", blame)))
+			};
+			let mut records = to_records(input);
+			// Records without the field go at the end either way
+			records.sort_by(|a, b| {
+				match (get_field(a, &field), get_field(b, &field)) {
+					(Some(a), Some(b)) if reverse => compare(b, a),
+					(Some(a), Some(b)) => compare(a, b),
+					(Some(_), None) => Ordering::Less,
+					(None, Some(_)) => Ordering::Greater,
+					(None, None) => Ordering::Equal
+				}
+			});
+			Ok(SlashVal::Array(records))
+		}
+		"pick" => {
+			if argv.is_empty() {
+				return Err(High(SlashErrHigh::syntax_err("Expected at least one field in `pick' call", blame)))
+			}
+			let mut records = vec![];
+			for record in to_records(input) {
+				let mut picked = BTreeMap::new();
+				for field in &argv {
+					if let Some(val) = get_field(&record, field) {
+						picked.insert(field.clone(), val.clone());
+					}
+				}
+				records.push(SlashVal::Dict(picked));
+			}
+			Ok(SlashVal::Array(records))
+		}
+		"json" => {
+			let pretty = argv.iter().any(|arg| arg == "--pretty");
+			let op = argv.iter().find(|arg| *arg != "--pretty").map(|op| op.as_str());
+			match (op, input) {
+				(Some("parse"), SlashVal::String(text)) => {
+					let json = serde_json::from_str::<serde_json::Value>(&text)
+						.map_err(|err| High(SlashErrHigh::exec_err(format!("Invalid JSON: {}",err), blame)))?;
+					SlashVal::from_json(json).blame(blame)
+				}
+				// Values that came from another stage are already parsed
+				(Some("parse"), val) => Ok(val),
+				(_, SlashVal::String(text)) => Ok(SlashVal::String(super::json::format_json(&deserialize(&text), pretty))),
+				(_, val) => Ok(SlashVal::String(super::json::format_json(&val, pretty)))
+			}
+		}
+		_ => Err(High(SlashErrHigh::exec_err(format!("`{}' can't be used as a structured pipeline stage",name), blame)))
+	}
+}
+
+/// Reads all of stdin straight from the file descriptor
+/// `std::io::stdin()` is buffered, and a forked stage would see whatever the shell had already buffered from its own input
+pub fn read_stdin() -> SlashResult<String> {
+	let mut input = String::new();
+	utils::SmartFD::from_stdin()?.read_to_string(&mut input).map_err(|_| Low(SlashErrLow::from_io()))?;
+	Ok(input)
+}
+
+/// Turns a value back into text for whatever comes after the stages
+/// Arrays give one element per line, with records, nested arrays and nulls written as JSON, and dicts are written as JSON
+pub fn serialize(val: &SlashVal) -> String {
+	match val {
+		SlashVal::Array(arr) => arr.iter().map(|element| {
+			match element {
+				SlashVal::Array(_) | SlashVal::Dict(_) | SlashVal::Null => element.to_json().to_string(),
+				_ => element.to_string()
+			}
+		}).collect::<Vec<String>>().join("\n"),
+		SlashVal::Dict(_) => val.to_json().to_string(),
+		_ => val.to_string()
+	}
+}
+
+/// Reads text coming from a command into a value
+/// The whole text is tried as JSON first, then each line is read as JSON, and lines that aren't JSON are kept as strings
+pub fn deserialize(text: &str) -> SlashVal {
+	let text = text.trim_end_matches('\n');
+	if let Ok(json) = serde_json::from_str::<serde_json::Value>(text) {
+		// JSON with a number that doesn't fit in a value is kept as text instead
+		if let Ok(val) = SlashVal::from_json(json) {
+			return val
+		}
+	}
+	let lines = text.lines()
+		.filter(|line| !line.trim().is_empty())
+		.map(|line| {
+			serde_json::from_str::<serde_json::Value>(line).ok()
+				.and_then(|json| SlashVal::from_json(json).ok())
+				.unwrap_or_else(|| SlashVal::String(line.to_string()))
+		})
+		.collect();
+	SlashVal::Array(lines)
+}
+
+/// The records that a stage works on, where text is deserialized first and a single value is one record
+fn to_records(val: SlashVal) -> Vec<SlashVal> {
+	let val = match val {
+		SlashVal::String(text) => deserialize(&text),
+		val => val
+	};
+	match val {
+		SlashVal::Array(arr) => arr,
+		val => vec![val]
+	}
+}
+
+fn get_field<'a>(record: &'a SlashVal, field: &str) -> Option<&'a SlashVal> {
+	if field == "it" {
+		return Some(record)
+	}
+	let keys = field.split('.').map(|key| key.to_string()).collect::<Vec<String>>();
+	record.get_element(&keys)
+}
+
+/// Numbers compare by value, and anything else compares as text
+fn compare(lhs: &SlashVal, rhs: &SlashVal) -> Ordering {
+	let as_num = |val: &SlashVal| match val {
+		SlashVal::Int(int) => Some(*int as f64),
+		SlashVal::Float(float) => Some(float.0),
+		_ => None
+	};
+	match (as_num(lhs), as_num(rhs)) {
+		(Some(lhs), Some(rhs)) => lhs.partial_cmp(&rhs).unwrap_or(Ordering::Equal),
+		_ => lhs.to_string().cmp(&rhs.to_string())
+	}
+}
+
+/// Lists directories and files as records, leaving out hidden files unless `-a` is given
+fn list_files(args: VecDeque<String>) -> SlashResult<SlashVal> {
+	let show_hidden = args.iter().any(|arg| arg == "-a" || arg == "--all");
+	let mut paths = args.into_iter().filter(|arg| !arg.starts_with('-')).collect::<Vec<String>>();
+	if paths.is_empty() {
+		paths.push(".".into());
+	}
+	let mut records = vec![];
+	for path in paths {
+		let metadata = fs::symlink_metadata(&path)
+			.map_err(|err| Low(SlashErrLow::ExecFailed(format!("ls: cannot access `{}': {}",path,err))))?;
+		if !metadata.is_dir() {
+			records.push(file_record(path.clone(), Path::new(&path))?);
+			continue
+		}
+		let entries = fs::read_dir(&path)
+			.map_err(|err| Low(SlashErrLow::ExecFailed(format!("ls: cannot read `{}': {}",path,err))))?;
+		let mut entries = entries.filter_map(|entry| entry.ok()).collect::<Vec<_>>();
+		entries.sort_by_key(|entry| entry.file_name());
+		for entry in entries {
+			let name = entry.file_name().to_string_lossy().to_string();
+			if !show_hidden && name.starts_with('.') {
+				continue
+			}
+			records.push(file_record(name, &entry.path())?);
+		}
+	}
+	Ok(SlashVal::Array(records))
+}
+
+fn file_record(name: String, path: &Path) -> SlashResult<SlashVal> {
+	let metadata = fs::symlink_metadata(path)
+		.map_err(|err| Low(SlashErrLow::ExecFailed(format!("ls: cannot access `{}': {}",path.display(),err))))?;
+	let file_type = if metadata.is_symlink() {
+		"symlink"
+	} else if metadata.is_dir() {
+		"dir"
+	} else {
+		"file"
+	};
+	let modified = metadata.modified().ok()
+		.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+		.map(|time| time.as_secs() as i64)
+		.unwrap_or_default();
+	let mut record = BTreeMap::new();
+	record.insert("name".to_string(), SlashVal::String(name));
+	record.insert("type".to_string(), SlashVal::String(file_type.to_string()));
+	record.insert("size".to_string(), SlashVal::Int(metadata.len() as i64));
+	record.insert("modified".to_string(), SlashVal::Int(modified));
+	Ok(SlashVal::Dict(record))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::execute;
+
+	use super::*;
+
+	#[test]
+	fn test_structured_sort() {
+		let mut slash = Slash::new();
+		let input = "arr data=[{n: 3, s: c}, {n: 1, s: a}, {n: 2, s: b}]; sorted=$(json dump $data | sort-by n -r | pick s | json dump)";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		let Some(SlashVal::Array(sorted)) = slash.vars().get_var("sorted") else { panic!() };
+		let names = sorted.iter().filter_map(|record| record.get_element(&["s".into()])).map(|name| name.to_string()).collect::<Vec<String>>();
+		assert_eq!(names, vec!["c", "b", "a"]);
+	}
+	#[test]
+	fn test_structured_output() {
+		let mut slash = Slash::new();
+		let input = "arr data=[{n: 3, s: c}, {n: 1, s: a}, {n: 2, s: b}]; big=$(json dump $data | where n -ge 2 | sort-by s | pick s)";
+
+		// Records leave the pipeline as JSON lines
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("big"), Some(SlashVal::String("{\"s\":\"b\"}\n{\"s\":\"c\"}".into())))
+	}
+	#[test]
+	fn test_structured_into_text() {
+		let mut slash = Slash::new();
+		let input = "arr data=[{s: a}, {s: b}, {s: c}]; int count=$(json dump $data | where s '!=' a | wc -l)";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("count"), Some(SlashVal::Int(2)))
+	}
+	#[test]
+	fn test_stage_status() {
+		let mut slash = Slash::new();
+		let input = "arr data=[{n: 1}, {n: 2}]; json dump $data | where n -bogus 1 | pick n";
+
+		// The stages share a process, but each one still gets its own status
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		let pipestatus = vec![SlashVal::Int(0),SlashVal::Int(1),SlashVal::Int(0)];
+		assert_eq!(slash.vars().get_var("PIPESTATUS"), Some(SlashVal::Array(pipestatus)));
+	}
+	#[test]
+	fn test_stage_redirs() {
+		let mut slash = Slash::new();
+		let path = env::temp_dir().join(format!("slash_stage_redirs_{}", std::process::id()));
+		let input = format!("arr data=[{{n: 1}}, {{n: 2}}]; json dump $data | where n -ge 2 > {0} | pick n; from_file=$(where n -eq 2 < {0} | pick n)", path.display());
+
+		// A redirection only applies to the stage it is written on, and replaces the pipe on that side
+		execute::dispatch::exec_input(input, &mut slash).unwrap();
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "{\"n\":2}\n");
+		let record = BTreeMap::from([("n".to_string(), SlashVal::Int(2))]);
+		assert_eq!(slash.vars().get_var("from_file"), Some(SlashVal::Dict(record)));
+		std::fs::remove_file(&path).unwrap();
+	}
+	#[test]
+	fn test_stage_null() {
+		let mut slash = Slash::new();
+		let path = env::temp_dir().join(format!("slash_stage_null_{}", std::process::id()));
+		let input = format!(r#"echo '[{{"a": null, "n": 1}}, {{"a": 2, "n": 2}}]' | json parse | where n -ge 1 | json dump > {}"#, path.display());
+
+		// Values are handed straight to the next stage, so nothing is lost on the way
+		execute::dispatch::exec_input(input, &mut slash).unwrap();
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "[{\"a\":null,\"n\":1},{\"a\":2,\"n\":2}]\n");
+		std::fs::remove_file(&path).unwrap();
+	}
+	#[test]
+	fn test_stage_big_int() {
+		let mut slash = Slash::new();
+		let input = r#"echo '{"id": 10000000000000000000}' | json parse | where id -gt 0 | json dump > /dev/null"#;
+
+		// A number that doesn't fit in an int is refused instead of being rounded
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		let pipestatus = vec![SlashVal::Int(0),SlashVal::Int(1),SlashVal::Int(0),SlashVal::Int(0)];
+		assert_eq!(slash.vars().get_var("PIPESTATUS"), Some(SlashVal::Array(pipestatus)));
+	}
+	#[test]
+	fn test_serialize() {
+		let val = deserialize("{\"a\": 1}\nplain text\n[1, 2]\n");
+		let mut record = BTreeMap::new();
+		record.insert("a".to_string(), SlashVal::Int(1));
+		let expected = SlashVal::Array(vec![
+			SlashVal::Dict(record),
+			SlashVal::String("plain text".into()),
+			SlashVal::Array(vec![SlashVal::Int(1), SlashVal::Int(2)])
+		]);
+		assert_eq!(val, expected);
+		assert_eq!(serialize(&val), "{\"a\":1}\nplain text\n[1,2]");
+		assert_eq!(deserialize("[1, 2]"), SlashVal::Array(vec![SlashVal::Int(1), SlashVal::Int(2)]));
+	}
+}
@@ -163,4 +163,12 @@ mod tests {
 
 use super::*;
 
+	#[test]
+	fn test_spaced_string_cmp() {
+		let mut slash = Slash::new();
+		execute::dispatch::exec_input("[ foo = foo ]".to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 0);
+		execute::dispatch::exec_input("[ foo = bar ]".to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 1);
+	}
 }
@@ -0,0 +1,105 @@
+use crate::{ast::{self, SimpleCmd, SrcSpan}, error::SlashErrExt, helper, prelude::*, signal::{self, TrapTarget}, utils};
+
+/// Sets, resets, or prints traps
+/// `trap 'cmd' SIG...` sets a trap, `trap - SIG...` removes one, and `trap` or `trap -p [SIG...]` prints them
+/// An empty command causes the signal to be ignored
+pub fn execute(trap_call: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &trap_call.span;
+	let mut argv = helper::prepare_argv(trap_call.words(),slash)?;
+	argv.pop_front();
+	let redirs = helper::prepare_redirs(&trap_call.redirs);
+	slash.consume_redirs(redirs)?;
+
+	let action = match argv.pop_front() {
+		None => return print_traps(argv, slash, blame),
+		Some(arg) if arg == "-p" => return print_traps(argv, slash, blame),
+		Some(arg) => arg
+	};
+	if argv.is_empty() {
+		return Err(High(SlashErrHigh::syntax_err("Expected a signal name in trap args", blame)))
+	}
+
+	while let Some(arg) = argv.pop_front() {
+		let target = arg.parse::<TrapTarget>().blame(blame)?;
+		if action == "-" {
+			slash.logic_mut().remove_trap(target);
+			if let TrapTarget::Signal(sig) = target {
+				signal::reset_trap_handler(sig).blame(blame)?;
+			}
+		} else {
+			if target == TrapTarget::Signal(Signal::SIGCHLD) {
+				// The shell relies on SIGCHLD to keep track of its background jobs
+				return Err(High(SlashErrHigh::exec_err("Traps can't be set on SIGCHLD", blame)))
+			}
+			let body = ast::parse(&action).blame(blame)?;
+			if let TrapTarget::Signal(sig) = target {
+				signal::set_trap_handler(sig, body.lists.is_empty()).blame(blame)?;
+			}
+			slash.logic_mut().new_trap(target, Arc::new(body));
+		}
+	}
+	Ok(())
+}
+
+/// Prints traps in a form that can be used as input again
+/// If no signals are given, every trap is printed
+fn print_traps(argv: VecDeque<String>, slash: &mut Slash, blame: &SrcSpan) -> SlashResult<()> {
+	let mut stdout = utils::SmartFD::new(1)?;
+	let mut targets = vec![];
+	for arg in &argv {
+		targets.push(arg.parse::<TrapTarget>().blame(blame)?);
+	}
+	for (target,body) in slash.logic().borrow_traps() {
+		if !targets.is_empty() && !targets.contains(target) {
+			continue
+		}
+		let body = body.span.as_str().replace('\'', "'\\''");
+		writeln!(stdout,"trap -- '{}' {}",body,target)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{execute, shellenv::SlashVal};
+
+	use super::*;
+
+	#[test]
+	fn test_trap_set_and_reset() {
+		let mut slash = Slash::new();
+		let input = "trap 'int caught=1' USR1";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert!(slash.logic().get_trap(TrapTarget::Signal(Signal::SIGUSR1)).is_some());
+
+		let input = "trap - SIGUSR1";
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert!(slash.logic().get_trap(TrapTarget::Signal(Signal::SIGUSR1)).is_none());
+	}
+	#[test]
+	fn test_trap_chld_rejected() {
+		let mut slash = Slash::new();
+		let input = "trap 'true' CHLD";
+
+		assert!(execute::dispatch::exec_input(input.to_string(), &mut slash).is_err());
+		assert!(slash.logic().get_trap(TrapTarget::Signal(Signal::SIGCHLD)).is_none());
+	}
+	#[test]
+	fn test_trap_err() {
+		let mut slash = Slash::new();
+		let input = "int count=0; trap 'count++' ERR; false && true; if false; then true; fi; false";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("count"), Some(SlashVal::Int(1)));
+		assert_eq!(slash.get_status(), 1);
+	}
+	#[test]
+	fn test_trap_return() {
+		let mut slash = Slash::new();
+		let input = "trap 'export TRAP_RETURNED=yes' RETURN; foo() { true; }; foo";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(env::var("TRAP_RETURNED").unwrap(), "yes".to_string());
+	}
+}
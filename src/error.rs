@@ -6,6 +6,24 @@ use crate::helper;
 
 pub type SlashResult<T> = Result<T,SlashErr>;
 
+/// Anything that can have an error blamed on it.
+/// Implemented for pest pairs and for the spans held by AST nodes.
+pub trait Blame {
+	fn blame_span(&self) -> Span<'_>;
+}
+
+impl Blame for Pair<'_,Rule> {
+	fn blame_span(&self) -> Span<'_> {
+		self.as_span()
+	}
+}
+
+impl<T: Blame + ?Sized> Blame for &T {
+	fn blame_span(&self) -> Span<'_> {
+		(**self).blame_span()
+	}
+}
+
 pub trait SlashErrExt<T> {
 	/// Transforms a SlashResult into an Option
 	/// If SlashResult is an error, this function will display it before returning None
@@ -14,34 +32,34 @@ pub trait SlashErrExt<T> {
 	/// It takes a pair to blame the error on, if the contained error is SlashErrLow,
 	/// then it will be converted to a SlashErrHigh
 	/// If the contained error is SlashErrHigh, the blamed pair will be replaced
-	fn blame(self, pair: Pair<Rule>) -> Result<T,SlashErr>;
+	fn blame<B: Blame>(self, blame: B) -> Result<T,SlashErr>;
 	/// The same as blame(), though does not overwrite the contained pair in a SlashErrHigh.
 	/// Will still transform a SlashErrLow into a SlashErrHigh
-	fn blame_no_overwrite(self, pair: Pair<Rule>) -> Result<T,SlashErr>;
+	fn blame_no_overwrite<B: Blame>(self, blame: B) -> Result<T,SlashErr>;
 }
 
 impl<T> SlashErrExt<T> for Result<T,SlashErr> {
-	fn blame(self, pair: Pair<Rule>) -> Result<T,SlashErr> {
+	fn blame<B: Blame>(self, blame: B) -> Result<T,SlashErr> {
 		match self {
 			Ok(thing) => Ok(thing),
 			Err(err) => {
 				let new_err = match err {
-					Low(low) => SlashErrHigh::blame(pair, low),
+					Low(low) => SlashErrHigh::blame(blame, low),
 					High(high) => {
 						let low = high.get_err();
-						SlashErrHigh::blame(pair, low.clone())
+						SlashErrHigh::blame(blame, low.clone())
 					}
 				};
 				Err(High(new_err))
 			}
 		}
 	}
-	fn blame_no_overwrite(self, pair: Pair<Rule>) -> Result<T,SlashErr> {
+	fn blame_no_overwrite<B: Blame>(self, blame: B) -> Result<T,SlashErr> {
 		match self {
 			Ok(thing) => Ok(thing),
 			Err(err) => {
 				let new_err = match err {
-					Low(low) => SlashErrHigh::blame(pair, low),
+					Low(low) => SlashErrHigh::blame(blame, low),
 					High(high) => high
 				};
 				Err(High(new_err))
@@ -89,6 +107,26 @@ impl Display for SlashErr {
 	}
 }
 
+/// Defines SlashErrLow::KINDS and SlashErrLow::kind() from one list of variants, so that the two can't disagree
+macro_rules! catchable_kinds {
+	($($kind:ident),*) => {
+		/// The names that `except` arms can use to catch a specific kind of error
+		pub const KINDS: &'static [&'static str] = &[$(stringify!($kind)),*];
+
+		/// The name of this kind of error, as used by `except` arms
+		/// Returns None for the variants that only carry control flow, since those can't be caught
+		pub fn kind(&self) -> Option<&'static str> {
+			match self {
+				$(SlashErrLow::$kind(_) => Some(stringify!($kind)),)*
+				SlashErrLow::CleanExit(_) |
+				SlashErrLow::FuncReturn(_) |
+				SlashErrLow::LoopCont(_) |
+				SlashErrLow::LoopBreak(_) => None
+			}
+		}
+	};
+}
+
 /// Simple errors
 #[derive(Debug,Clone)]
 pub enum SlashErrLow {
@@ -102,18 +140,44 @@ pub enum SlashErrLow {
 	InternalErr(String),
 	IndexErr(String),
 	ExecFailed(String),
+	ExitStatus(i32),
 
 	// Not actual errors, used to propagate logic from commands like `exit` and `return`
 	CleanExit(i32),
 	FuncReturn(i32),
-	LoopCont,
-	LoopBreak(i32),
+	/// `continue` and `break` carry the number of enclosing loops that they still have to get out of
+	LoopCont(usize),
+	LoopBreak(usize),
 }
 
 impl SlashErrLow {
+	catchable_kinds!(Parse, IoError, ErrNo, CmdNotFound, BadPermission, BadFD, InvalidSyntax, InternalErr, IndexErr, ExecFailed, ExitStatus);
+
 	pub fn from_io() -> Self {
 		Self::IoError(std::io::Error::last_os_error().to_string())
 	}
+	/// Turns the non-zero exit status of a command into an error
+	/// External commands that can't be found or executed exit with 127 or 126, so those are reported as such
+	pub fn from_status(status: i32, cmd_name: &str) -> Self {
+		match status {
+			127 => Self::CmdNotFound(cmd_name.to_string()),
+			126 => Self::BadPermission(cmd_name.to_string()),
+			_ => Self::ExitStatus(status)
+		}
+	}
+	/// The exit status that this error would leave behind
+	pub fn code(&self) -> i32 {
+		match self {
+			SlashErrLow::CmdNotFound(_) => 127,
+			SlashErrLow::BadPermission(_) => 126,
+			SlashErrLow::ExitStatus(code) |
+			SlashErrLow::CleanExit(code) |
+			SlashErrLow::FuncReturn(code) => *code,
+			SlashErrLow::LoopCont(_) |
+			SlashErrLow::LoopBreak(_) => 0,
+			_ => 1
+		}
+	}
 }
 
 impl Display for SlashErrLow {
@@ -129,8 +193,9 @@ impl Display for SlashErrLow {
 			SlashErrLow::ExecFailed(msg) => write!(f,"Execution Failed: {}",msg),
 			SlashErrLow::CmdNotFound(name) => write!(f,"Command not found: {}",name),
 			SlashErrLow::BadPermission(name) => write!(f,"Permission denied: {}",name),
+			SlashErrLow::ExitStatus(code) => write!(f,"Command exited with status {}",code),
 			SlashErrLow::FuncReturn(_) => write!(f, "Found return outside of function"),
-			SlashErrLow::LoopCont => write!(f, "Found continue outside of loop"),
+			SlashErrLow::LoopCont(_) => write!(f, "Found continue outside of loop"),
 			SlashErrLow::LoopBreak(_) => write!(f, "Found break outside of loop"),
 			SlashErrLow::CleanExit(_) => write!(f, ""),
 		}
@@ -140,7 +205,8 @@ impl Display for SlashErrLow {
 #[derive(Debug,Clone)]
 pub struct SlashErrHigh {
 	pest_err: String,
-	low_err: SlashErrLow
+	low_err: SlashErrLow,
+	line: usize
 }
 
 impl Display for SlashErrHigh {
@@ -150,47 +216,54 @@ impl Display for SlashErrHigh {
 }
 
 impl SlashErrHigh {
-	pub fn blame(pair: Pair<Rule>, low_err: SlashErrLow) -> Self {
+	pub fn blame<B: Blame>(blame: B, low_err: SlashErrLow) -> Self {
 		let message = low_err.to_string();
-		let pest_err = helper::build_slash_err::<Rule>(pair, message);
-		Self { pest_err, low_err }
+		let span = blame.blame_span();
+		let (line,_) = span.start_pos().line_col();
+		let pest_err = helper::build_slash_err::<Rule>(span, message);
+		Self { pest_err, low_err, line }
 	}
 
-	pub fn parse_err(msg: impl Into<String>, pair: Pair<Rule>) -> Self {
-		Self::blame(pair, SlashErrLow::Parse(msg.into()))
+	pub fn parse_err<B: Blame>(msg: impl Into<String>, blame: B) -> Self {
+		Self::blame(blame, SlashErrLow::Parse(msg.into()))
 	}
 
-	pub fn io_err(pair: Pair<Rule>) -> Self {
-		Self::blame(pair, SlashErrLow::IoError(std::io::Error::last_os_error().to_string()))
+	pub fn io_err<B: Blame>(blame: B) -> Self {
+		Self::blame(blame, SlashErrLow::IoError(std::io::Error::last_os_error().to_string()))
 	}
 
-	pub fn bad_fd(msg: impl Into<String>, pair: Pair<Rule>) -> Self {
-		Self::blame(pair, SlashErrLow::BadFD(msg.into()))
+	pub fn bad_fd<B: Blame>(msg: impl Into<String>, blame: B) -> Self {
+		Self::blame(blame, SlashErrLow::BadFD(msg.into()))
 	}
 
-	pub fn cmd_not_found(name: impl Into<String>, pair: Pair<Rule>) -> Self {
-		Self::blame(pair, SlashErrLow::CmdNotFound(name.into()))
+	pub fn cmd_not_found<B: Blame>(name: impl Into<String>, blame: B) -> Self {
+		Self::blame(blame, SlashErrLow::CmdNotFound(name.into()))
 	}
 
-	pub fn no_permission(name: impl Into<String>, pair: Pair<Rule>) -> Self {
-		Self::blame(pair, SlashErrLow::BadPermission(name.into()))
+	pub fn no_permission<B: Blame>(name: impl Into<String>, blame: B) -> Self {
+		Self::blame(blame, SlashErrLow::BadPermission(name.into()))
 	}
 
-	pub fn syntax_err(msg: impl Into<String>, pair: Pair<Rule>) -> Self {
-		Self::blame(pair, SlashErrLow::InvalidSyntax(msg.into()))
+	pub fn syntax_err<B: Blame>(msg: impl Into<String>, blame: B) -> Self {
+		Self::blame(blame, SlashErrLow::InvalidSyntax(msg.into()))
 	}
 
-	pub fn internal_err(msg: impl Into<String>, pair: Pair<Rule>) -> Self {
-		Self::blame(pair, SlashErrLow::InternalErr(msg.into()))
+	pub fn internal_err<B: Blame>(msg: impl Into<String>, blame: B) -> Self {
+		Self::blame(blame, SlashErrLow::InternalErr(msg.into()))
 	}
 
-	pub fn exec_err(msg: impl Into<String>, pair: Pair<Rule>) -> Self {
-		Self::blame(pair, SlashErrLow::ExecFailed(msg.into()))
+	pub fn exec_err<B: Blame>(msg: impl Into<String>, blame: B) -> Self {
+		Self::blame(blame, SlashErrLow::ExecFailed(msg.into()))
 	}
 
 	pub fn get_err(&self) -> &SlashErrLow {
 		&self.low_err
 	}
+
+	/// The line of input that the error was blamed on
+	pub fn line(&self) -> usize {
+		self.line
+	}
 }
 
 pub fn infer_parse_err(input: &str, err: pest::error::Error<Rule>) -> String {
@@ -1,85 +1,144 @@
-use crate::{error::{SlashErr::*, SlashErrExt}, expand, helper, prelude::*, shellenv::SlashVal};
+use crate::{ast::{AssignKind, Assignment}, error::{SlashErr::*, SlashErrExt}, expand, helper, prelude::*, shellenv::SlashVal};
 
 use super::dispatch;
 
-pub fn exec_assignment<'a>(ass: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let cmd = ass.scry(Rule::cmd_list);
-	let blame = ass.clone();
-	let var_name: String = ass.scry(Rule::var_ident).unpack()?.as_str().to_string();
-	let assign_type = ass.scry(&[
-		Rule::increment,
-		Rule::decrement,
-		Rule::plus_assign,
-		Rule::minus_assign,
-		Rule::std_assign][..]).unpack()?;
-	let val = ass.scry(Rule::word).map(|pr| helper::try_expansion(slash,pr).unwrap_or_default()).unwrap_or_default();
+pub fn exec_assignment(ass: &Assignment, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &ass.span;
+	let var_name = ass.var.as_str();
+	let val = match &ass.val {
+		Some(word) => expand::split::expand_value(word,slash).blame(blame)?,
+		None => SlashVal::String(String::new())
+	};
+	match ass.kind {
+		AssignKind::Std if ass.index.is_empty() => helper::trace_cmd(&format!("{}={}",var_name,val), slash),
+		_ => helper::trace_cmd(ass.span.as_str(), slash)
+	}
+	let mut keys = vec![];
+	for key in &ass.index {
+		keys.push(expand::index::expand_key(key, slash)?);
+	}
 	let vars = slash.vars_mut();
-	match assign_type.as_rule() {
-		Rule::increment => {
-			if let Some(val) = vars.get_var_mut(&var_name) {
+	vars.check_writable(var_name).blame(blame)?;
+	match ass.kind {
+		AssignKind::Increment => {
+			if let Some(val) = vars.get_var_mut(var_name) {
 				val.increment().blame(blame)?;
 			}
 		}
-		Rule::decrement => {
-			if let Some(val) = vars.get_var_mut(&var_name) {
+		AssignKind::Decrement => {
+			if let Some(val) = vars.get_var_mut(var_name) {
 				val.decrement().blame(blame)?;
 			}
 		}
-		Rule::plus_assign => {
-			let rhs = SlashVal::parse(ass.scry(Rule::word).unpack()?.as_str())?;
-			let var_val = vars.get_var(&var_name);
-			if var_val.clone().is_some_and(|val| &val.fmt_type() == "int") {
-				if let SlashVal::Int(lhs) = var_val.unwrap() {
-					if let SlashVal::Int(rhs) = rhs {
-						let value = SlashVal::Int(lhs + rhs);
-						vars.set_var(&var_name, value);
-					} else {
-						let msg = "The right side of this assignment is invalid; expected an integer";
-						return Err(High(SlashErrHigh::syntax_err(msg, blame)))
-					}
-				} else {
-					let msg = "The left side of this assignment is invalid; expected an integer";
-					return Err(High(SlashErrHigh::syntax_err(msg, blame)))
-				}
-			} else {
+		AssignKind::Plus | AssignKind::Minus | AssignKind::Times | AssignKind::Div | AssignKind::Mod | AssignKind::Pow => {
+			let Some(lhs) = vars.get_var(var_name) else {
 				let msg = "The variable in this assignment is unset";
 				return Err(High(SlashErrHigh::syntax_err(msg, blame)))
-			}
-		}
-		Rule::minus_assign => {
-			let rhs = SlashVal::parse(ass.scry(Rule::word).unpack()?.as_str())?;
-			let var_val = vars.get_var(&var_name);
-			if var_val.clone().is_some_and(|val| &val.fmt_type() == "int") {
-				if let SlashVal::Int(lhs) = var_val.unwrap() {
-					if let SlashVal::Int(rhs) = rhs {
-						vars.set_var(&var_name, SlashVal::Int(lhs - rhs));
-					} else {
-						let msg = "The right side of this assignment is invalid; expected an integer";
+			};
+			let rhs = val.clone();
+			let result = match ass.kind {
+				// Adding and subtracting also work on strings, arrays, and dicts
+				AssignKind::Plus => helper::add_vars(lhs, rhs),
+				AssignKind::Minus => helper::subtract_vars(lhs, rhs),
+				kind => {
+					if !matches!(lhs, SlashVal::Int(_) | SlashVal::Float(_)) || !matches!(rhs, SlashVal::Int(_) | SlashVal::Float(_)) {
+						let msg = format!("Expected numbers on both sides of this assignment, found {} and {}",lhs.fmt_type(),rhs.fmt_type());
 						return Err(High(SlashErrHigh::syntax_err(msg, blame)))
 					}
-				} else {
-					let msg = "The left side of this assignment is invalid; expected an integer";
-					return Err(High(SlashErrHigh::syntax_err(msg, blame)))
+					let op = match kind {
+						AssignKind::Times => "*=",
+						AssignKind::Div => "/=",
+						AssignKind::Mod => "%=",
+						_ => "**="
+					};
+					expand::arithmetic::apply_op(op, lhs, rhs)
 				}
-			} else {
-				let msg = "The variable in this assignment is unset";
-				return Err(High(SlashErrHigh::syntax_err(msg, blame)))
-			}
+			};
+			vars.set_var(var_name, result.blame(blame)?).blame(blame)?;
 		}
-		Rule::std_assign => {
-			vars.set_var(&var_name, SlashVal::parse(&val.clone())?);
+		AssignKind::Std if !keys.is_empty() => {
+			// Assigning to a key of an unset variable makes it a dict
+			let mut root = vars.get_var(var_name).unwrap_or_else(|| SlashVal::Dict(BTreeMap::new()));
+			root.set_element(&keys, val.clone()).blame(blame)?;
+			vars.set_var(var_name, root).blame(blame)?;
+		}
+		AssignKind::Std => {
+			vars.set_var(var_name, val.clone()).blame(blame)?;
 		}
-		Rule::cmd_list => {}
-		_ => unreachable!()
 	}
 
 	// TODO: cleanup this logic, it currently doesn't isolate the variable setting to the execution context
-	if let Some(cmd) = cmd {
+	if let Some(cmd) = &ass.cmd {
 		// If there are commands attached, export the variables, then execute, then restore environment state
 		let mut slash_clone = slash.clone();
-		slash_clone.vars_mut().export_var(&var_name, &val.to_string());
-		dispatch::exec_input(cmd.as_str().to_string(), &mut slash_clone)?;
+		slash_clone.vars_mut().export_var(var_name, &val.to_string()).blame(blame)?;
+		dispatch::exec_block(cmd, &mut slash_clone)?;
 	}
 	slash.set_code(0);
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::{execute, shellenv::HashFloat};
+
+	use super::*;
+
+	#[test]
+	fn test_compound_assign_int() {
+		let mut slash = Slash::new();
+		let input = "x=5; x*=3; x/=2; x%=4; x**=3";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("x"), Some(SlashVal::Int(27)))
+	}
+	#[test]
+	fn test_compound_assign_float() {
+		let mut slash = Slash::new();
+		let input = "y=27; y+=0.5; y++";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("y"), Some(SlashVal::Float(HashFloat(28.5))))
+	}
+	#[test]
+	fn test_compound_assign_string() {
+		let mut slash = Slash::new();
+		let input = "s=foo; s+=bar";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("s"), Some(SlashVal::String("foobar".into())))
+	}
+	#[test]
+	fn test_compound_assign_array() {
+		let mut slash = Slash::new();
+		let input = "arr a=[1]; a+=2; a+=[3,4]; a-=1";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("a"), Some(SlashVal::Array(vec![SlashVal::Int(2), SlashVal::Int(3), SlashVal::Int(4)])))
+	}
+	#[test]
+	fn test_compound_assign_invalid() {
+		let mut slash = Slash::new();
+		let input = "x=5; x*=abc";
+
+		assert!(execute::dispatch::exec_input(input.to_string(), &mut slash).is_err());
+	}
+	#[test]
+	fn test_int_overflow() {
+		let mut slash = Slash::new();
+		let input = "int big=4294967296; big*=2; int max=9223372036854775807";
+
+		// Integers are 64 bits wide, and going past that is an error instead of wrapping around
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("big"), Some(SlashVal::Int(8589934592)));
+		assert!(execute::dispatch::exec_input("max+=1".to_string(), &mut slash).is_err());
+	}
+	#[test]
+	fn test_assign_expansion_error() {
+		let mut slash = Slash::new();
+		let input = "x=$((1/0))";
+
+		assert!(execute::dispatch::exec_input(input.to_string(), &mut slash).is_err());
+		assert_eq!(slash.vars().get_var("x"), None);
+	}
+}
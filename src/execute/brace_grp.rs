@@ -0,0 +1,63 @@
+use crate::{ast::Block, prelude::*, utils};
+
+use super::dispatch;
+
+/// Runs the body of `{ ... }` in the current shell
+/// The redirections of the group are applied once for the whole body, so `{ a; b; } > out` writes the output of both commands,
+/// and they are undone once the body is done
+pub fn exec_brace_grp(body: &Block, slash: &mut Slash) -> SlashResult<()> {
+	let saved_fds = utils::save_fds()?;
+	let result = exec_body(body, slash);
+	utils::restore_fds(saved_fds, slash)?;
+	result
+}
+
+fn exec_body(body: &Block, slash: &mut Slash) -> SlashResult<()> {
+	slash.activate_redirs()?;
+	// Inside of a pipeline, the group gets a process of its own, but the commands in it still have to fork
+	slash.ctx_mut().flags_mut().remove(utils::ExecFlags::NO_FORK);
+	dispatch::exec_block(body, slash)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{execute, shellenv::SlashVal};
+
+	use super::*;
+
+	#[test]
+	fn test_brace_grp_redir() {
+		let mut slash = Slash::new();
+		let path = env::temp_dir().join(format!("slash_brace_grp_{}",std::process::id()));
+		let input = format!("{{ echo one; echo two; }} > {0}; output=$(cat {0})", path.display());
+
+		// Both commands write to the same file
+		execute::dispatch::exec_input(input, &mut slash).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(slash.vars().get_var("output"), Some(SlashVal::String("one\ntwo".into())))
+	}
+	#[test]
+	fn test_brace_grp_current_shell() {
+		let mut slash = Slash::new();
+		let input = "false || { int ran=1; }";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("ran"), Some(SlashVal::Int(1)))
+	}
+	#[test]
+	fn test_brace_grp_in_pipeline() {
+		let mut slash = Slash::new();
+		let input = "lines=$({ echo a; echo b; } | wc -l)";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("lines"), Some(SlashVal::Int(2)))
+	}
+	#[test]
+	fn test_brace_grp_match_arm() {
+		let mut slash = Slash::new();
+		let input = "match two in one => { int arm=1; } two => { int arm=2; } done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("arm"), Some(SlashVal::Int(2)))
+	}
+}
@@ -1,13 +1,14 @@
-use crate::{helper, prelude::*};
+use crate::{ast::SimpleCmd, helper, prelude::*};
 
 use crate::utils;
 
-pub fn exec_cmd<'a>(cmd: Pair<Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let blame = cmd.clone();
-	let mut argv = helper::prepare_argv(cmd.clone(),slash)?;
-	let mut redirs = helper::prepare_redirs(cmd)?;
+use super::dispatch;
+
+pub fn exec_cmd(cmd: &SimpleCmd, slash: &mut Slash) -> SlashResult<()> {
+	let blame = cmd.span.clone();
+	let mut argv = helper::prepare_argv(cmd.words(),slash)?;
+	let mut redirs = helper::prepare_redirs(&cmd.redirs);
 	slash.ctx_mut().extend_redirs(redirs);
-	argv.retain(|arg| !arg.is_empty() && arg != "\"\"" && arg != "''");
 
 	if helper::validate_autocd(slash,&argv)? {
 		let arg = argv.pop_front().unwrap();
@@ -28,15 +29,19 @@ pub fn exec_cmd<'a>(cmd: Pair<Rule>, slash: &mut Slash) -> SlashResult<()> {
 	let env_vars = env::vars().into_iter().collect::<Vec<(String,String)>>();
 	let envp = env_vars.iter().map(|var| CString::new(format!("{}={}",var.0,var.1)).unwrap()).collect::<Vec<_>>();
 
-	slash.ctx_mut().activate_redirs()?;
+	slash.activate_redirs()?;
+	let report = !dispatch::raises_failure(slash);
 
 	if slash.ctx_mut().flags().contains(utils::ExecFlags::NO_FORK) {
-		utils::exec_external(command, argv, envp, blame);
+		utils::exec_external(command, argv, envp, report, &blame);
 	}
 
 	match unsafe { fork() } {
 		Ok(ForkResult::Child) => {
-			utils::exec_external(command, argv, envp, blame);
+			// The parent does this too, but the command might already have been exec'd by then,
+			// and `fg` or `kill %1` would find no process group to signal
+			setpgid(Pid::from_raw(0), Pid::from_raw(0)).ok();
+			utils::exec_external(command, argv, envp, report, &blame);
 		}
 		Ok(ForkResult::Parent { child }) => {
 			utils::handle_parent_process(child, command.to_str().unwrap().to_string(),slash)?;
@@ -1,128 +1,160 @@
-use crate::{builtin::{self, BUILTINS}, error::SlashErrExt, expand, helper, prelude::*, script, utils::{ExecFlags, Redir}};
+use crate::{ast::{self, Block, CmdList, Command, Compound, ListOp, SimpleCmd}, builtin::{self, BUILTINS}, error::SlashErrExt, expand, helper, prelude::*, script, shellenv::{self, EnvFlags}, signal::{self, TrapTarget}, utils::{self, ExecFlags}};
 
 use super::{pipeline, command, func};
 
-pub fn dispatch_exec<'a>(node: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-		match node.as_rule() {
-			Rule::simple_cmd => {
-				let command_name = node.clone().into_inner().find(|pair| pair.as_rule() == Rule::cmd_name).unpack()?.as_str();
-				if !slash.ctx().flags().contains(ExecFlags::IGN_FUNC) && slash.is_func(command_name)? {
-					func::exec_func(node,slash)?;
-				} else if BUILTINS.contains(&command_name) {
-					exec_builtin(node,command_name,slash)?;
-				} else {
-					command::exec_cmd(node, slash)?;
-				}
-			}
-			Rule::shell_cmd => {
-				let mut shell_cmd_inner = node.to_deque();
-				let shell_cmd = shell_cmd_inner.pop_front().unpack()?;
-				while shell_cmd_inner.front().is_some_and(|pair| pair.as_rule() == Rule::redir) {
-					let redir = Redir::from_pair(shell_cmd_inner.pop_front().unpack()?)?;
-					slash.ctx_mut().push_redir(redir);
-				}
-				match shell_cmd.as_rule() {
-					Rule::for_cmd => script::fordo::exec_for_cmd(shell_cmd, slash)?,
-					Rule::match_cmd => script::matchdo::exec_match_cmd(shell_cmd, slash)?,
-					Rule::loop_cmd => script::loopdo::exec_loop_cmd(shell_cmd, slash)?,
-					Rule::if_cmd => script::ifthen::exec_if_cmd(shell_cmd, slash)?,
-					Rule::subshell => super::subshell::exec_subshell(shell_cmd, slash)?,
-					Rule::brace_grp => todo!(),
-					Rule::assignment => super::assignment::exec_assignment(shell_cmd, slash)?,
-					Rule::func_def => super::func::exec_func_def(shell_cmd, slash)?,
-					_ => unreachable!()
-				};
+pub fn dispatch_exec(node: &Command, slash: &mut Slash) -> SlashResult<()> {
+	match node {
+		Command::Simple(cmd) => {
+			let Some(command_name) = cmd.name() else {
+				return Err(High(SlashErrHigh::exec_err("Expected a command name here", &cmd.span)))
+			};
+			// Traced as written, since builtins expand their own arguments and expanding them here would run substitutions twice
+			helper::trace_cmd(cmd.span.as_str(), slash);
+			if !slash.ctx().flags().contains(ExecFlags::IGN_FUNC) && slash.is_func(command_name)? {
+				func::exec_func(cmd,slash)?;
+			} else if BUILTINS.contains(&command_name) || builtin::structured::is_stage(cmd) {
+				// Builtins point the shell's own file descriptors at their redirections, so those are put back afterwards
+				let saved_fds = utils::save_fds()?;
+				let result = exec_builtin(cmd,command_name,slash);
+				utils::restore_fds(saved_fds, slash)?;
+				result?;
+			} else {
+				command::exec_cmd(cmd, slash)?;
 			}
-			Rule::pipeline => { pipeline::exec_pipeline(node, slash)?; },
-			Rule::EOI => { /* Do nothing */ }
-			_ => todo!("Support for rule '{:?}' is unimplemented",node.as_rule())
 		}
-		Ok(())
+		Command::Shell(shell_cmd) => {
+			slash.ctx_mut().extend_redirs(helper::prepare_redirs(&shell_cmd.redirs));
+			match &shell_cmd.kind {
+				Compound::For(for_cmd) => script::fordo::exec_for_cmd(for_cmd, slash)?,
+				Compound::Select(select_cmd) => script::select::exec_select_cmd(select_cmd, slash)?,
+				Compound::Match(match_cmd) => script::matchdo::exec_match_cmd(match_cmd, slash)?,
+				Compound::Case(case_cmd) => script::matchdo::exec_case_cmd(case_cmd, slash)?,
+				Compound::Loop(loop_cmd) => script::loopdo::exec_loop_cmd(loop_cmd, slash)?,
+				Compound::If(if_cmd) => script::ifthen::exec_if_cmd(if_cmd, slash)?,
+				Compound::Try(try_cmd) => script::trydo::exec_try_cmd(try_cmd, slash)?,
+				Compound::Arith(arith_cmd) => builtin::arith::exec_arith_cmd(arith_cmd, slash)?,
+				Compound::Subshell(subsh) => super::subshell::exec_subshell(subsh, slash)?,
+				Compound::BraceGrp(body) => super::brace_grp::exec_brace_grp(body, slash)?,
+				Compound::Assign(ass) => super::assignment::exec_assignment(ass, slash)?,
+				Compound::FuncDef(func_def) => super::func::exec_func_def(func_def, slash)?,
+			};
+		}
+		Command::Pipeline(cmds) => { pipeline::exec_pipeline(cmds, slash)?; },
+		Command::Background(cmd) => {
+			*slash.ctx_mut().flags_mut() |= ExecFlags::BACKGROUND;
+			dispatch_exec(cmd, slash)?
+		}
+	}
+	Ok(())
 }
 
-pub fn descend(mut node_stack: VecDeque<Pair<Rule>>, slash: &mut Slash) -> SlashResult<()> {
+/// Executes a single command one level deeper in the execution context
+/// Any changes made to the context by the command are discarded afterwards
+/// This is also the safe point where traps get to run
+pub fn descend(cmd: &Command, slash: &mut Slash) -> SlashResult<()> {
+	let env_flags = slash.meta().flags();
+	if env_flags.contains(EnvFlags::NO_EXECUTE) && !env_flags.contains(EnvFlags::INTERACTIVE) {
+		// `set -n` reads commands without running them
+		return Ok(())
+	}
+	signal::run_pending_traps(slash)?;
+	signal::run_trap(slash, TrapTarget::Debug)?;
 	slash.ctx_mut().descend()?; // Increment depth counter
-	while let Some(node) = node_stack.pop_front() {
-		match node.as_rule() {
-			Rule::main | Rule::cmd_list => {
-				let inner = node.to_deque();
-				node_stack.extend(inner);
-			}
-			Rule::op => {
-				let option = node.step(1);
-				if let Some(op) = option {
-					match op.as_rule() {
-						Rule::and => {
-							let is_success = slash.get_status() == 0;
-							if !is_success {
-								break
-							}
-						}
-						Rule::or => {
-							let is_success = slash.get_status() == 0;
-							if is_success {
-								break
-							}
-						}
-						_ => unreachable!()
-					}
-				}
-			}
-			Rule::bg_cmd => {
-				if let Some(cmd) = node.step(1) {
-					let flags = slash.ctx_mut().flags_mut();
-					*flags |= ExecFlags::BACKGROUND;
-					dispatch_exec(cmd, slash)?
-				}
+	let depth = slash.ctx().depth();
+	let result = dispatch_exec(cmd, slash).blame_no_overwrite(cmd.span());
+	shellenv::reap_proc_subs(depth)?; // The command is done with any process substitutions it expanded
+	slash.ctx_mut().ascend()?; // Decrement depth counter
+	result?;
+	signal::run_pending_traps(slash)
+}
+
+pub fn exec_list(list: &CmdList, slash: &mut Slash) -> SlashResult<()> {
+	descend_in_list(&list.first, list.rest.is_empty(), slash)?;
+	let mut last = &list.first;
+	let mut ran_last = true;
+	for (i,(op,cmd)) in list.rest.iter().enumerate() {
+		let is_success = slash.get_status() == 0;
+		match op {
+			ListOp::And if !is_success => ran_last = false,
+			ListOp::Or if is_success => ran_last = false,
+			_ => {
+				descend_in_list(cmd, i + 1 == list.rest.len(), slash)?;
+				last = cmd;
+				ran_last = true;
 			}
-			_ => dispatch_exec(node, slash)?
 		}
 	}
-	slash.ctx_mut().ascend()?; // Decrement depth counter
+	// Like bash, only a failure in the final command of a list fires the ERR trap or exits under `set -e`
+	let in_cond = slash.ctx().flags().contains(ExecFlags::IN_COND);
+	let status = slash.get_status();
+	if ran_last && !in_cond && status != 0 && fires_err_trap(last) {
+		signal::run_trap(slash, TrapTarget::Err)?;
+		if slash.meta().flags().contains(EnvFlags::EXIT_ON_ERROR) {
+			return Err(Low(SlashErrLow::CleanExit(status)))
+		}
+		if raises_failure(slash) {
+			// Inside of a try block, the failure is raised so that an except arm can catch it
+			let cmd_name = match last {
+				Command::Simple(cmd) => cmd.name().unwrap_or_default(),
+				_ => last.span().as_str()
+			};
+			return Err(High(SlashErrHigh::blame(last.span(), SlashErrLow::from_status(status, cmd_name))))
+		}
+	}
 	Ok(())
 }
 
-pub fn exec_input(mut input: String, slash: &mut Slash) -> SlashResult<()> {
-	input = expand::dispatch::expand_aliases(input, 0, vec![],slash)?;
-	let mut lists = SlashParse::parse(Rule::main, &input).map_err(|e| Low(SlashErrLow::Parse(e.to_string())))?.next().unwrap().into_inner().collect::<VecDeque<_>>();
-	lists.pop_back();
+/// Only the final command of a list can fail it, so the ones before it run as they would outside of a try block
+fn descend_in_list(cmd: &Command, is_final: bool, slash: &mut Slash) -> SlashResult<()> {
+	let in_try = slash.ctx().flags().contains(ExecFlags::IN_TRY);
+	if is_final || !in_try {
+		return descend(cmd, slash)
+	}
+	slash.ctx_mut().flags_mut().remove(ExecFlags::IN_TRY);
+	let result = descend(cmd, slash);
+	slash.ctx_mut().flags_mut().insert(ExecFlags::IN_TRY);
+	result
+}
+
+/// Whether a failing command is raised as an error instead of just setting the exit status
+/// The error is reported wherever it ends up, so the command itself doesn't need to report it
+pub fn raises_failure(slash: &Slash) -> bool {
+	let flags = slash.ctx().flags();
+	flags.contains(ExecFlags::IN_TRY) && !flags.contains(ExecFlags::IN_COND) && !slash.meta().flags().contains(EnvFlags::EXIT_ON_ERROR)
+}
+
+/// Compound commands don't fire the ERR trap themselves,
+/// since the command inside of them that actually failed has already done so
+fn fires_err_trap(cmd: &Command) -> bool {
+	match cmd {
+		Command::Shell(shell_cmd) => matches!(shell_cmd.kind, Compound::Subshell(_) | Compound::Assign(_) | Compound::Arith(_)),
+		Command::Background(_) => false,
+		_ => true
+	}
+}
+
+pub fn exec_block(block: &Block, slash: &mut Slash) -> SlashResult<()> {
 	// Chew through the input one list at a time
-	while let Some(list) = lists.pop_front() {
-		let mut cmds = list.into_inner();
-		while let Some(cmd) = cmds.next() {
-			if cmd.as_rule() == Rule::op {
-				let op = cmd.scry(&[Rule::and,Rule::or][..]).unpack()?;
-				match op.as_rule() {
-					Rule::and => {
-						if slash.get_status() != 0 {
-							break
-						} else {
-							continue
-						}
-					}
-					Rule::or => {
-						if slash.get_status() == 0 {
-							break
-						} else {
-							continue
-						}
-					}
-					_ => unreachable!()
-				}
-			}
-			let blame = cmd.clone();
-			let node_stack = VecDeque::from([cmd]);
-			descend(node_stack, slash).blame_no_overwrite(blame)?;
-		}
+	for list in &block.lists {
+		exec_list(list, slash)?;
 	}
 	Ok(())
 }
 
-pub fn exec_builtin(cmd: Pair<Rule>, name: &str, slash: &mut Slash) -> SlashResult<()> {
-	let blame = cmd.clone();
+pub fn exec_input(mut input: String, slash: &mut Slash) -> SlashResult<()> {
+	if slash.meta().flags().contains(EnvFlags::PRINT_INPUT) {
+		eprintln!("{}",input);
+	}
+	input = expand::dispatch::expand_aliases(input, 0, vec![],slash)?;
+	let block = ast::parse(&input)?;
+	exec_block(&block, slash)
+}
+
+pub fn exec_builtin(cmd: &SimpleCmd, name: &str, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &cmd.span;
 	match name {
 		"test" | "[" => {
-			let mut argv = helper::prepare_argv(cmd,slash)?;
+			let mut argv = helper::prepare_argv(cmd.words(),slash)?;
 			argv.pop_front(); // Ignore the command name
 			let result = builtin::test::test(&mut argv, slash).blame(blame)?;
 			if result {
@@ -133,14 +165,42 @@ pub fn exec_builtin(cmd: Pair<Rule>, name: &str, slash: &mut Slash) -> SlashResu
 				return Ok(())
 			}
 		}
-		"string" | "float" | "int" | "arr" | "bool" => builtin::assign::execute(cmd, slash)?,
+		"wait" => {
+			let code = builtin::job::wait(cmd, slash)?;
+			slash.set_code(code);
+			return Ok(())
+		}
+		"let" | "expr" => {
+			let code = if name == "let" {
+				builtin::arith::let_expr(cmd, slash)?
+			} else {
+				builtin::arith::expr(cmd, slash)?
+			};
+			slash.set_code(code);
+			return Ok(())
+		}
+		"dict" | "arr" | "json" => {
+			let code = match name {
+				"dict" => builtin::dict::execute(cmd, slash)?,
+				"arr" => builtin::arr::execute(cmd, slash)?,
+				_ => builtin::json::execute(cmd, slash)?
+			};
+			slash.set_code(code);
+			return Ok(())
+		}
+		"where" | "sort-by" | "pick" | "ls" => builtin::structured::execute(cmd, slash)?,
+		"string" | "float" | "int" | "bool" => builtin::assign::execute(cmd, slash)?,
+		"local" => builtin::assign::local(cmd, slash)?,
+		"declare" => builtin::declare::declare(cmd, slash)?,
+		"set" => builtin::set::execute(cmd, slash)?,
+		"readonly" => builtin::declare::readonly(cmd, slash)?,
 		"exec" => builtin::exec::run_exec(cmd, slash)?,
 		"fg" => builtin::job::continue_job(cmd, slash, true)?,
 		"bg" => builtin::job::continue_job(cmd, slash, false)?,
 		"jobs" => builtin::job::jobs(cmd, slash)?,
 		"return" => builtin::control::func_return(cmd, slash)?,
 		"break" => builtin::control::loop_break(cmd, slash)?,
-		"continue" => builtin::control::loop_continue()?,
+		"continue" => builtin::control::loop_continue(cmd, slash)?,
 		"pushd" => builtin::dir_stack::pushd(cmd, slash)?,
 		"source" => builtin::source::execute(cmd, slash)?,
 		"popd" => builtin::dir_stack::popd(cmd, slash)?,
@@ -155,6 +215,7 @@ pub fn exec_builtin(cmd: Pair<Rule>, name: &str, slash: &mut Slash) -> SlashResu
 		"echo" => builtin::echo::execute(cmd, slash)?,
 		"builtin" => builtin::cmd_override::execute(cmd, slash, true)?,
 		"command" => builtin::cmd_override::execute(cmd, slash, false)?,
+		"trap" => builtin::trap::execute(cmd, slash)?,
 		_ => return Err(High(SlashErrHigh::exec_err(format!("Have not implemented support for builtin `{}` yet",name),blame)))
 	};
 	slash.set_code(0);
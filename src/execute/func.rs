@@ -1,36 +1,29 @@
-use crate::{helper, prelude::*};
+use crate::{ast::{FuncDef, SimpleCmd}, helper, prelude::*, signal::{self, TrapTarget}};
 
 use super::dispatch;
 
-pub fn exec_func_def<'a>(func_def: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let blame = func_def.clone();
-	let func_name = func_def.scry(Rule::func_name).unpack()?;
-	let body = func_def.scry(Rule::brace_grp).unpack()?;
-	helper::write_func(
-		slash,
-		func_name.as_str().trim_end_matches("()"),
-		body.as_str().trim_matches(['{','}']).trim()
-	)?;
+pub fn exec_func_def(func_def: &FuncDef, slash: &mut Slash) -> SlashResult<()> {
+	helper::write_func(slash, &func_def.name, func_def.body.clone())?;
 	slash.set_code(0);
 	Ok(())
 }
 
-pub fn exec_func(cmd: Pair<Rule>,slash: &mut Slash) -> SlashResult<()> {
-	let blame = cmd.clone();
-	let mut argv = helper::prepare_argv(cmd,slash)?;
+pub fn exec_func(cmd: &SimpleCmd,slash: &mut Slash) -> SlashResult<()> {
+	let mut argv = helper::prepare_argv(cmd.words(),slash)?;
 	let func_name = argv.pop_front().unwrap();
 	let body = slash.logic().get_func(&func_name).unwrap();
-	let mut var_table = slash.vars().clone();
-	let snapshot = slash.clone();
 
-	var_table.reset_params();
-	for arg in argv {
-		var_table.pos_param_pushback(&arg);
-	}
-	*slash.vars_mut() = var_table;
-	let result = dispatch::exec_input(body, slash);
-	*slash = snapshot;
+	// Assignments write through to the caller's variables unless shadowed with `local`
+	slash.vars_mut().push_scope(argv);
+	let result = dispatch::exec_block(&body, slash);
+	let trap_result = signal::run_trap(slash, TrapTarget::Return);
+	slash.vars_mut().pop_scope();
+	trap_result?;
 
+	if result.is_ok() {
+		// The function's status is the status of the last command it ran
+		return Ok(())
+	}
 	let code = helper::extract_return(&result);
 	if let Ok(code) = code {
 		slash.set_code(code);
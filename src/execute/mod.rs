@@ -1,4 +1,5 @@
 pub mod assignment;
+pub mod brace_grp;
 pub mod command;
 pub mod func;
 pub mod subshell;
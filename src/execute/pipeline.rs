@@ -1,20 +1,38 @@
-use crate::{helper, utils, prelude::*, shellenv::{ChildProc, JobBuilder}};
+use crate::{ast::{Command, Pipeline}, builtin, helper, utils, prelude::*, shellenv::{ChildProc, EnvFlags, JobBuilder}};
 
 use super::dispatch;
 
-pub fn exec_pipeline<'a>(pipeline: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let blame = pipeline.clone();
+pub fn exec_pipeline(pipeline: &Pipeline, slash: &mut Slash) -> SlashResult<()> {
+	let blame = &pipeline.span;
 	let (in_redirs,out_redirs) = slash.ctx_mut().sort_redirs();
 	let _ = slash.ctx_mut().take_redirs();
 
-	let mut inner = pipeline.into_inner().peekable();
+	// Structured stages next to each other run together in one process, so that they pass values to each other instead of text
+	let mut units: Vec<Vec<&Command>> = vec![];
+	let mut prev_is_stage = false;
+	for node in &pipeline.cmds {
+		let is_stage = match node {
+			Command::Simple(cmd) => builtin::structured::is_stage(cmd) && !slash.is_func(cmd.name().unwrap_or_default())?,
+			_ => false
+		};
+		match units.last_mut() {
+			Some(unit) if is_stage && prev_is_stage => unit.push(node),
+			_ => units.push(vec![node])
+		}
+		prev_is_stage = is_stage;
+	}
+
+	let mut inner = units.iter().peekable();
 	let mut prev_read_pipe: Option<utils::SmartFD> = None;
 	let mut pgid: Option<Pid> = None;
 	let mut cmds: Vec<String> = vec![];
 	let mut pids: Vec<Pid> = vec![];
+	// A process that runs several stages sends back the status of each one through its own pipe
+	let mut status_pipes: Vec<Option<utils::SmartFD>> = vec![];
 
+	let mut codes = vec![];
 	let mut first = true;
-	while let Some(node) = inner.next() {
+	while let Some(unit) = inner.next() {
 		let (r_pipe,w_pipe) = if inner.peek().is_some() {
 			let (r_pipe,w_pipe) = utils::SmartFD::pipe()?;
 			(Some(r_pipe),Some(w_pipe))
@@ -22,8 +40,15 @@ pub fn exec_pipeline<'a>(pipeline: Pair<'a,Rule>, slash: &mut Slash) -> SlashRes
 			(None,None)
 		};
 
-		let cmd_check = node.clone();
-		cmds.push(helper::get_pipeline_cmd(cmd_check)?);
+		let (status_r_pipe,status_w_pipe) = if unit.len() > 1 {
+			let (r_pipe,w_pipe) = utils::SmartFD::pipe()?;
+			(Some(r_pipe),Some(w_pipe))
+		} else {
+			(None,None)
+		};
+
+		let names = unit.iter().map(|node| helper::get_pipeline_cmd(node)).collect::<SlashResult<Vec<String>>>()?;
+		cmds.push(names.join(" | "));
 
 		match unsafe { fork() } {
 			Ok(ForkResult::Child) => {
@@ -46,16 +71,52 @@ pub fn exec_pipeline<'a>(pipeline: Pair<'a,Rule>, slash: &mut Slash) -> SlashRes
 				if inner.peek().is_none() {
 					// If the pipeline ends with output, redirect it here
 					slash.ctx_mut().extend_redirs(out_redirs.into());
+				} else {
+					// Only the last command can fail the pipeline, so the others report their own failures
+					slash.ctx_mut().flags_mut().remove(utils::ExecFlags::IN_TRY);
 				}
 
-				dispatch::dispatch_exec(node, slash)?;
-				std::process::exit(1)
+				// Builtins and shell commands end up here, external commands exec() before this point
+				let result = match unit.as_slice() {
+					[node] => dispatch::dispatch_exec(node, slash),
+					stages => {
+						let stages = stages.iter().filter_map(|node| match node {
+							Command::Simple(cmd) => Some(cmd),
+							_ => None
+						}).collect::<Vec<_>>();
+						builtin::structured::exec_stages(&stages, slash).and_then(|codes| {
+							let statuses = codes.iter().map(|code| code.to_string()).collect::<Vec<String>>();
+							write!(status_w_pipe.unwrap(),"{}",statuses.join(" "))?;
+							slash.set_code(codes.last().copied().unwrap_or(0));
+							Ok(())
+						})
+					}
+				};
+				let code = match result {
+					Ok(()) => slash.get_status(),
+					Err(err) => {
+						let low_err = match &err {
+							Low(low_err) => low_err,
+							High(high_err) => high_err.get_err()
+						};
+						// `exit`, `return`, `break` and `continue` only end the stage, so there is nothing to report
+						if low_err.kind().is_some() {
+							eprintln!("{}",err);
+						}
+						low_err.code()
+					}
+				};
+				std::process::exit(code)
 			}
 			Ok(ForkResult::Parent { child }) => {
 				if let Some(mut pipe) = w_pipe {
 					pipe.close()?
 				}
+				if let Some(mut pipe) = status_w_pipe {
+					pipe.close()?
+				}
 				prev_read_pipe = r_pipe;
+				status_pipes.push(status_r_pipe);
 				pids.push(child);
 				if pgid.is_none() {
 					pgid = Some(child);
@@ -73,7 +134,7 @@ pub fn exec_pipeline<'a>(pipeline: Pair<'a,Rule>, slash: &mut Slash) -> SlashRes
 						.with_children(children)
 						.build();
 
-					helper::handle_fg(slash,job)?;
+					codes = helper::handle_fg(slash,job)?;
 				}
 			}
 			Err(e) => return Err(High(SlashErrHigh::exec_err("Command in pipeline failed", blame)))
@@ -82,6 +143,98 @@ pub fn exec_pipeline<'a>(pipeline: Pair<'a,Rule>, slash: &mut Slash) -> SlashRes
 			first = false;
 		}
 	}
-	slash.set_code(0);
+	if status_pipes.iter().any(|pipe| pipe.is_some()) {
+		codes = stage_codes(codes, status_pipes)?;
+		helper::set_pipe_status(slash, &codes)?;
+	}
+	if slash.meta().flags().contains(EnvFlags::PIPEFAIL) {
+		// The rightmost failing command decides the status of the whole pipeline
+		let code = codes.iter().rev().find(|code| **code != 0).copied().unwrap_or(0);
+		slash.set_code(code);
+	}
 	Ok(())
 }
+
+/// Replaces the status of each process that ran several stages with the statuses of those stages
+/// A process that was stopped or killed before it could send them gives its own status to each of its stages
+fn stage_codes(codes: Vec<i32>, status_pipes: Vec<Option<utils::SmartFD>>) -> SlashResult<Vec<i32>> {
+	let mut stage_codes = vec![];
+	for (code,pipe) in codes.into_iter().zip(status_pipes) {
+		let Some(mut pipe) = pipe else {
+			stage_codes.push(code);
+			continue
+		};
+		// A stopped process still has its end of the pipe open, so this can't wait for it to be closed
+		fcntl(pipe.as_raw_fd(), nix::fcntl::FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).map_err(|_| Low(SlashErrLow::from_io()))?;
+		let mut statuses = String::new();
+		let _ = pipe.read_to_string(&mut statuses);
+		pipe.close()?;
+		let statuses = statuses.split_whitespace().filter_map(|status| status.parse::<i32>().ok()).collect::<Vec<i32>>();
+		if statuses.is_empty() {
+			stage_codes.push(code);
+		} else {
+			stage_codes.extend(statuses);
+		}
+	}
+	Ok(stage_codes)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{execute, shellenv::SlashVal};
+
+	use super::*;
+
+	#[test]
+	fn test_pipeline_status() {
+		let mut slash = Slash::new();
+		let input = "false | sh -c 'exit 3' | true";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 0);
+		let pipestatus = vec![SlashVal::Int(1),SlashVal::Int(3),SlashVal::Int(0)];
+		assert_eq!(slash.vars().get_var("PIPESTATUS"), Some(SlashVal::Array(pipestatus)));
+	}
+	#[test]
+	fn test_pipefail() {
+		let mut slash = Slash::new();
+		let input = "set -o pipefail; sh -c 'exit 3' | false | true";
+
+		// The rightmost failing command decides the status
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 1)
+	}
+	#[test]
+	fn test_pipeline_redir_out() {
+		let mut slash = Slash::new();
+		let path = env::temp_dir().join(format!("slash_pipeline_redir_out_{}",std::process::id()));
+		let input = format!("piped=$(echo hi > {} | cat)", path.display());
+
+		// The command's own redirection comes after the pipe, so the output goes to the file
+		execute::dispatch::exec_input(input, &mut slash).unwrap();
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi\n");
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(slash.vars().get_var("piped"), Some(SlashVal::String(String::new())))
+	}
+	#[test]
+	fn test_pipeline_redir_in() {
+		let mut slash = Slash::new();
+		let path = env::temp_dir().join(format!("slash_pipeline_redir_in_{}",std::process::id()));
+		std::fs::write(&path, "from file\n").unwrap();
+		let input = format!("output=$(echo from pipe | cat < {})", path.display());
+
+		execute::dispatch::exec_input(input, &mut slash).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(slash.vars().get_var("output"), Some(SlashVal::String("from file".into())))
+	}
+	#[test]
+	fn test_pipeline_exit_status() {
+		let mut slash = Slash::new();
+		let input = "true | exit 4";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 4);
+		let pipestatus = vec![SlashVal::Int(0),SlashVal::Int(4)];
+		assert_eq!(slash.vars().get_var("PIPESTATUS"), Some(SlashVal::Array(pipestatus)));
+	}
+}
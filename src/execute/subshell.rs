@@ -1,3 +1,4 @@
+use crate::ast::{Block, SubshBody, Subshell};
 use crate::expand;
 use crate::helper;
 use crate::prelude::*;
@@ -8,23 +9,19 @@ use crate::utils;
 
 use super::dispatch;
 
-pub fn exec_subshell<'a>(subsh: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let mut shebang = None;
-	let body = subsh.scry(Rule::subsh_body).unpack()?.as_str();
-	if let Some(subshebang) = subsh.scry(Rule::subshebang) {
-		let raw_shebang = subshebang.as_str().to_string();
-		shebang = Some(expand::misc::expand_shebang(slash,&raw_shebang));
-	}
-
-	let argv = helper::prepare_argv(subsh.clone(),slash)?;
-	let redirs = helper::prepare_redirs(subsh)?;
+pub fn exec_subshell(subsh: &Subshell, slash: &mut Slash) -> SlashResult<()> {
+	let argv = helper::prepare_argv(&subsh.args,slash)?;
+	let redirs = helper::prepare_redirs(&subsh.redirs);
 
 	slash.ctx_mut().extend_redirs(redirs);
-	if let Some(shebang) = shebang {
-		let script = format!("{}{}",shebang,body);
-		handle_external_subshell(script,argv,slash)?;
-	} else {
-		handle_internal_subshell(body.to_string(),argv,slash)?;
+	match &subsh.body {
+		SubshBody::Foreign(body) => {
+			let raw_shebang = subsh.shebang.clone().unwrap_or_default();
+			let shebang = expand::misc::expand_shebang(slash,&raw_shebang);
+			let script = format!("{}{}",shebang,body);
+			handle_external_subshell(script,argv,slash)?;
+		}
+		SubshBody::Slash(body) => handle_internal_subshell(body,argv,slash)?
 	}
 
 	slash.set_code(0);
@@ -38,7 +35,7 @@ fn handle_external_subshell(script: String, argv: VecDeque<String>, slash: &mut
 	write!(memfd,"{}",script)?;
 
 	let fd_path = CString::new(format!("/proc/self/fd/{memfd}")).unwrap();
-	slash.ctx_mut().activate_redirs()?;
+	slash.activate_redirs()?;
 
 	if slash.in_pipe() {
 		execve(&fd_path, &argv, &envp).unwrap();
@@ -66,14 +63,11 @@ fn handle_external_subshell(script: String, argv: VecDeque<String>, slash: &mut
 	Ok(())
 }
 
-fn handle_internal_subshell(body: String, argv: VecDeque<String>, slash: &mut Slash) -> SlashResult<()> {
+fn handle_internal_subshell(body: &Block, argv: VecDeque<String>, slash: &mut Slash) -> SlashResult<()> {
 	let snapshot = slash.clone();
-	slash.ctx_mut().activate_redirs()?;
-	slash.vars_mut().reset_params();
-	for arg in argv {
-		slash.vars_mut().pos_param_pushback(&arg);
-	}
-	dispatch::exec_input(body.consume_escapes(), slash)?;
+	slash.activate_redirs()?;
+	slash.vars_mut().set_pos_params(argv);
+	dispatch::exec_block(body, slash)?;
 	*slash = snapshot;
 	Ok(())
 }
@@ -0,0 +1,353 @@
+use once_cell::sync::Lazy;
+use pest::{iterators::Pairs, pratt_parser::{Assoc, Op, PrattParser}};
+
+use crate::{error::SlashErr, prelude::*, shellenv::{HashFloat, SlashVal}};
+
+use super::dispatch::{expand_param, expand_var};
+
+/// Operator precedence for arithmetic expressions, from lowest to highest
+static PRATT: Lazy<PrattParser<Rule>> = Lazy::new(|| {
+	PrattParser::new()
+		.op(Op::infix(Rule::arith_ternary, Assoc::Right))
+		.op(Op::infix(Rule::arith_or, Assoc::Left))
+		.op(Op::infix(Rule::arith_and, Assoc::Left))
+		.op(Op::infix(Rule::arith_bitor, Assoc::Left))
+		.op(Op::infix(Rule::arith_bitxor, Assoc::Left))
+		.op(Op::infix(Rule::arith_bitand, Assoc::Left))
+		.op(Op::infix(Rule::arith_eq, Assoc::Left) | Op::infix(Rule::arith_ne, Assoc::Left))
+		.op(Op::infix(Rule::arith_lt, Assoc::Left) | Op::infix(Rule::arith_le, Assoc::Left) | Op::infix(Rule::arith_gt, Assoc::Left) | Op::infix(Rule::arith_ge, Assoc::Left))
+		.op(Op::infix(Rule::arith_shl, Assoc::Left) | Op::infix(Rule::arith_shr, Assoc::Left))
+		.op(Op::infix(Rule::arith_add, Assoc::Left) | Op::infix(Rule::arith_minus, Assoc::Left))
+		.op(Op::infix(Rule::arith_mul, Assoc::Left) | Op::infix(Rule::arith_div, Assoc::Left) | Op::infix(Rule::arith_mod, Assoc::Left))
+		.op(Op::infix(Rule::arith_pow, Assoc::Right))
+		.op(Op::prefix(Rule::arith_neg) | Op::prefix(Rule::arith_pos) | Op::prefix(Rule::arith_not) | Op::prefix(Rule::arith_bitnot))
+});
+
+/// An arithmetic expression, built before evaluating so that `&&`, `||` and `?:` can skip the side they don't need
+#[derive(Debug)]
+enum ArithExpr {
+	Num(SlashVal),
+	Var(String),
+	Param(String),
+	Assign { var: String, op: String, val: Box<ArithExpr> },
+	IncDec { var: String, op: String, prefix: bool },
+	Unary(Rule, Box<ArithExpr>),
+	Binary(Rule, Box<ArithExpr>, Box<ArithExpr>),
+	Ternary(Box<ArithExpr>, Box<ArithExpr>, Box<ArithExpr>)
+}
+
+/// Expands `$((expr))` into the result of the expression
+pub fn expand_arith(mut pair: Pair<Rule>, slash: &mut Slash) -> SlashResult<String> {
+	if pair.as_rule() == Rule::word {
+		pair = pair.step(1).unpack()?;
+	}
+	let body = pair.as_str();
+	let body = &body[3..body.len() - 2]; // From '$((this))' to 'this'
+	Ok(eval(body, slash)?.to_string())
+}
+
+/// Evaluates an arithmetic expression, returning an int or a float
+/// Comma separated expressions are all evaluated, and the last one gives the result
+pub fn eval(input: &str, slash: &mut Slash) -> SlashResult<SlashVal> {
+	let main = SlashParse::parse(Rule::arith_main, input)
+		.map_err(|_| Low(SlashErrLow::InvalidSyntax(format!("Invalid arithmetic expression: {}",input.trim()))))?
+		.next()
+		.unpack()?;
+	let mut result = SlashVal::Int(0);
+	for expr in main.into_inner().filter(|pr| pr.as_rule() == Rule::arith_expr) {
+		let expr = build_expr(expr.into_inner())?;
+		result = eval_expr(&expr, slash)?;
+	}
+	Ok(result)
+}
+
+/// Whether a result counts as true, for `(( ))` and `let`
+pub fn is_truthy(val: &SlashVal) -> bool {
+	match val {
+		SlashVal::Int(int) => *int != 0,
+		SlashVal::Float(float) => float.0 != 0.0,
+		_ => false
+	}
+}
+
+fn build_expr(pairs: Pairs<Rule>) -> SlashResult<ArithExpr> {
+	PRATT
+		.map_primary(build_primary)
+		.map_prefix(|op, rhs| Ok(ArithExpr::Unary(op.as_rule(), Box::new(rhs?))))
+		.map_infix(|lhs, op, rhs| {
+			if op.as_rule() == Rule::arith_ternary {
+				let middle = build_expr(op.scry(Rule::arith_expr).unpack()?.into_inner())?;
+				Ok(ArithExpr::Ternary(Box::new(lhs?), Box::new(middle), Box::new(rhs?)))
+			} else {
+				Ok(ArithExpr::Binary(op.as_rule(), Box::new(lhs?), Box::new(rhs?)))
+			}
+		})
+		.parse(pairs)
+}
+
+fn build_primary(pair: Pair<Rule>) -> SlashResult<ArithExpr> {
+	let var_name = |pair: &Pair<Rule>| -> SlashResult<String> {
+		Ok(pair.scry(Rule::arith_var).unpack()?.as_str().trim_start_matches('$').to_string())
+	};
+	match pair.as_rule() {
+		Rule::arith_int => {
+			let int = pair.as_str();
+			let parsed = match int.strip_prefix("0x").or(int.strip_prefix("0X")) {
+				Some(hex) => i64::from_str_radix(hex, 16),
+				None => int.parse::<i64>()
+			};
+			let int = parsed.map_err(|_| Low(SlashErrLow::ExecFailed(format!("Value too great for an integer: {}",int))))?;
+			Ok(ArithExpr::Num(SlashVal::Int(int)))
+		}
+		Rule::arith_float => {
+			let float = pair.as_str().parse::<f64>().map_err(|_| Low(SlashErrLow::InvalidSyntax(format!("Invalid float: {}",pair.as_str()))))?;
+			Ok(ArithExpr::Num(SlashVal::Float(HashFloat(float))))
+		}
+		Rule::arith_var => Ok(ArithExpr::Var(pair.as_str().trim_start_matches('$').to_string())),
+		Rule::arith_param => Ok(ArithExpr::Param(pair.as_str()[1..].to_string())),
+		Rule::arith_group => build_expr(pair.scry(Rule::arith_expr).unpack()?.into_inner()),
+		Rule::arith_assign => {
+			let var = var_name(&pair)?;
+			let op = pair.scry(Rule::arith_assign_op).unpack()?.as_str().to_string();
+			let val = build_expr(pair.scry(Rule::arith_expr).unpack()?.into_inner())?;
+			Ok(ArithExpr::Assign { var, op, val: Box::new(val) })
+		}
+		Rule::arith_pre | Rule::arith_post => {
+			let var = var_name(&pair)?;
+			let op = pair.scry(Rule::arith_incdec).unpack()?.as_str().to_string();
+			Ok(ArithExpr::IncDec { var, op, prefix: pair.as_rule() == Rule::arith_pre })
+		}
+		_ => Err(Low(SlashErrLow::InternalErr(format!("Unexpected rule in arithmetic expression: {:?}",pair.as_rule()))))
+	}
+}
+
+fn eval_expr(expr: &ArithExpr, slash: &mut Slash) -> SlashResult<SlashVal> {
+	match expr {
+		ArithExpr::Num(num) => Ok(num.clone()),
+		ArithExpr::Var(var) => read_var(var, slash),
+		ArithExpr::Param(param) => to_number(SlashVal::parse(&expand_param(param, slash)?)?, param),
+		ArithExpr::Assign { var, op, val } => {
+			let mut val = eval_expr(val, slash)?;
+			if op != "=" {
+				let current = read_var(var, slash)?;
+				val = apply_op(op, current, val)?;
+			}
+			slash.vars_mut().set_var(var, val.clone())?;
+			Ok(val)
+		}
+		ArithExpr::IncDec { var, op, prefix } => {
+			let current = read_var(var, slash)?;
+			let op_rule = if op == "++" { Rule::arith_add } else { Rule::arith_minus };
+			let new_val = apply_binary(op_rule, current.clone(), SlashVal::Int(1))?;
+			slash.vars_mut().set_var(var, new_val.clone())?;
+			Ok(if *prefix { new_val } else { current })
+		}
+		ArithExpr::Unary(op_rule, operand) => {
+			let operand = eval_expr(operand, slash)?;
+			match (op_rule, operand) {
+				(Rule::arith_pos, val) => Ok(val),
+				(Rule::arith_neg, SlashVal::Int(int)) => Ok(SlashVal::Int(int.checked_neg().ok_or_else(overflow)?)),
+				(Rule::arith_neg, SlashVal::Float(float)) => Ok(SlashVal::Float(HashFloat(-float.0))),
+				(Rule::arith_not, val) => Ok(bool_val(!is_truthy(&val))),
+				(Rule::arith_bitnot, SlashVal::Int(int)) => Ok(SlashVal::Int(!int)),
+				(_, val) => Err(Low(SlashErrLow::ExecFailed(format!("Invalid operand for bitwise not: {}",val))))
+			}
+		}
+		ArithExpr::Binary(op_rule @ (Rule::arith_and | Rule::arith_or), lhs, rhs) => {
+			// These two short circuit, so the right hand side is only evaluated if it is needed
+			let lhs = is_truthy(&eval_expr(lhs, slash)?);
+			let result = match op_rule {
+				Rule::arith_and => lhs && is_truthy(&eval_expr(rhs, slash)?),
+				_ => lhs || is_truthy(&eval_expr(rhs, slash)?)
+			};
+			Ok(bool_val(result))
+		}
+		ArithExpr::Binary(op_rule, lhs, rhs) => {
+			let lhs = eval_expr(lhs, slash)?;
+			let rhs = eval_expr(rhs, slash)?;
+			apply_binary(*op_rule, lhs, rhs)
+		}
+		ArithExpr::Ternary(cond, if_true, if_false) => {
+			if is_truthy(&eval_expr(cond, slash)?) {
+				eval_expr(if_true, slash)
+			} else {
+				eval_expr(if_false, slash)
+			}
+		}
+	}
+}
+
+/// Applies the operator of a compound assignment like `+=` or `**=` to two numbers
+/// Integers are promoted to floats if either side is a float, and integer overflow is an error
+pub fn apply_op(op: &str, lhs: SlashVal, rhs: SlashVal) -> SlashResult<SlashVal> {
+	let op_rule = match op.trim_end_matches('=') {
+		"**" => Rule::arith_pow,
+		"<<" => Rule::arith_shl,
+		">>" => Rule::arith_shr,
+		"+" => Rule::arith_add,
+		"-" => Rule::arith_minus,
+		"*" => Rule::arith_mul,
+		"/" => Rule::arith_div,
+		"%" => Rule::arith_mod,
+		"&" => Rule::arith_bitand,
+		"^" => Rule::arith_bitxor,
+		"|" => Rule::arith_bitor,
+		_ => return Err(Low(SlashErrLow::InternalErr(format!("Unexpected arithmetic operator: {}",op))))
+	};
+	apply_binary(op_rule, lhs, rhs)
+}
+
+fn apply_binary(op_rule: Rule, lhs: SlashVal, rhs: SlashVal) -> SlashResult<SlashVal> {
+	match (lhs, rhs) {
+		(SlashVal::Int(lhs), SlashVal::Int(rhs)) => int_binary(op_rule, lhs, rhs),
+		(lhs, rhs) => {
+			// If either side is a float, then both sides are treated as floats
+			let to_float = |val: &SlashVal| val.as_float().or(val.as_int().map(|int| int as f64)).unwrap_or_default();
+			float_binary(op_rule, to_float(&lhs), to_float(&rhs))
+		}
+	}
+}
+
+fn int_binary(op_rule: Rule, lhs: i64, rhs: i64) -> SlashResult<SlashVal> {
+	let div_by_zero = || Low(SlashErrLow::ExecFailed("Division by zero".into()));
+	let result = match op_rule {
+		Rule::arith_add => lhs.checked_add(rhs).ok_or_else(overflow)?,
+		Rule::arith_minus => lhs.checked_sub(rhs).ok_or_else(overflow)?,
+		Rule::arith_mul => lhs.checked_mul(rhs).ok_or_else(overflow)?,
+		Rule::arith_div if rhs == 0 => return Err(div_by_zero()),
+		Rule::arith_div => lhs.checked_div(rhs).ok_or_else(overflow)?,
+		Rule::arith_mod if rhs == 0 => return Err(div_by_zero()),
+		Rule::arith_mod => lhs.checked_rem(rhs).ok_or_else(overflow)?,
+		Rule::arith_pow => {
+			let exp = u32::try_from(rhs).map_err(|_| Low(SlashErrLow::ExecFailed("Exponent less than 0".into())))?;
+			lhs.checked_pow(exp).ok_or_else(overflow)?
+		}
+		Rule::arith_shl => lhs.wrapping_shl(rhs as u32),
+		Rule::arith_shr => lhs.wrapping_shr(rhs as u32),
+		Rule::arith_bitand => lhs & rhs,
+		Rule::arith_bitxor => lhs ^ rhs,
+		Rule::arith_bitor => lhs | rhs,
+		Rule::arith_lt => (lhs < rhs) as i64,
+		Rule::arith_le => (lhs <= rhs) as i64,
+		Rule::arith_gt => (lhs > rhs) as i64,
+		Rule::arith_ge => (lhs >= rhs) as i64,
+		Rule::arith_eq => (lhs == rhs) as i64,
+		Rule::arith_ne => (lhs != rhs) as i64,
+		_ => return Err(Low(SlashErrLow::InternalErr(format!("Unexpected arithmetic op_rule: {:?}",op_rule))))
+	};
+	Ok(SlashVal::Int(result))
+}
+
+fn overflow() -> SlashErr {
+	Low(SlashErrLow::ExecFailed("Integer overflow in arithmetic expression".into()))
+}
+
+fn float_binary(op_rule: Rule, lhs: f64, rhs: f64) -> SlashResult<SlashVal> {
+	let result = match op_rule {
+		Rule::arith_add => lhs + rhs,
+		Rule::arith_minus => lhs - rhs,
+		Rule::arith_mul => lhs * rhs,
+		Rule::arith_div | Rule::arith_mod if rhs == 0.0 => {
+			return Err(Low(SlashErrLow::ExecFailed("Division by zero".into())))
+		}
+		Rule::arith_div => lhs / rhs,
+		Rule::arith_mod => lhs % rhs,
+		Rule::arith_pow => lhs.powf(rhs),
+		Rule::arith_lt => return Ok(bool_val(lhs < rhs)),
+		Rule::arith_le => return Ok(bool_val(lhs <= rhs)),
+		Rule::arith_gt => return Ok(bool_val(lhs > rhs)),
+		Rule::arith_ge => return Ok(bool_val(lhs >= rhs)),
+		Rule::arith_eq => return Ok(bool_val(lhs == rhs)),
+		Rule::arith_ne => return Ok(bool_val(lhs != rhs)),
+		_ => return Err(Low(SlashErrLow::ExecFailed("Bitwise operators only work on integers".into())))
+	};
+	Ok(SlashVal::Float(HashFloat(result)))
+}
+
+fn bool_val(val: bool) -> SlashVal {
+	SlashVal::Int(val as i64)
+}
+
+/// Variables are read without needing a `$`, and unset or empty variables count as zero
+fn read_var(var_name: &str, slash: &Slash) -> SlashResult<SlashVal> {
+	match slash.vars().get_var(var_name) {
+		Some(val) => to_number(val, var_name),
+		None => to_number(SlashVal::parse(&expand_var(var_name, slash)?)?, var_name)
+	}
+}
+
+fn to_number(val: SlashVal, name: &str) -> SlashResult<SlashVal> {
+	match val {
+		SlashVal::Int(_) | SlashVal::Float(_) => Ok(val),
+		SlashVal::Bool(boolean) => Ok(bool_val(boolean)),
+		SlashVal::Null => Ok(SlashVal::Int(0)),
+		SlashVal::String(ref string) if string.trim().is_empty() => Ok(SlashVal::Int(0)),
+		SlashVal::String(ref string) if matches!(SlashVal::parse(string.trim()), Ok(SlashVal::Int(_) | SlashVal::Float(_))) => {
+			SlashVal::parse(string.trim())
+		}
+		_ => Err(Low(SlashErrLow::ExecFailed(format!("{}: value is not a number: {}",name,val))))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::execute;
+
+	use super::*;
+
+	#[test]
+	fn test_arithmetic() {
+		let mut slash = Slash::new();
+		let cases = [
+			("1 + 2 * 3", SlashVal::Int(7)),
+			("(1 + 2) * 3", SlashVal::Int(9)),
+			("2 ** 3 ** 2", SlashVal::Int(512)),
+			("-2 ** 2", SlashVal::Int(4)),
+			("7 / 2 + 7 % 2", SlashVal::Int(4)),
+			("7 / 2.0", SlashVal::Float(HashFloat(3.5))),
+			("1 << 4 | 3 & 1 ^ 2", SlashVal::Int(19)),
+			("3 > 2 && 2 >= 3 || !0", SlashVal::Int(1)),
+			("0x1f == 31 ? 10 : 20", SlashVal::Int(10)),
+			("~5", SlashVal::Int(-6)),
+		];
+		for (input,expected) in cases {
+			assert_eq!(eval(input, &mut slash).unwrap(), expected, "{}", input);
+		}
+		assert!(eval("1 / 0", &mut slash).is_err());
+		assert!(eval("1 +", &mut slash).is_err());
+	}
+	#[test]
+	fn test_arith_sub() {
+		let mut slash = Slash::new();
+		let input = "int x=5; a=$((x * 2 + $x))";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("a"), Some(SlashVal::Int(15)))
+	}
+	#[test]
+	fn test_arith_float_truncated() {
+		let mut slash = Slash::new();
+		let input = "float f=1.5; b=$(( f * 2 ))";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("b"), Some(SlashVal::Int(3)))
+	}
+	#[test]
+	fn test_arith_assignment() {
+		let mut slash = Slash::new();
+		let input = "int x=5; int c=$((x++ + ++x, x <<= 1))";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("c"), Some(SlashVal::Int(14)));
+		assert_eq!(slash.vars().get_var("x"), Some(SlashVal::Int(14)));
+	}
+	#[test]
+	fn test_arith_short_circuit() {
+		let mut slash = Slash::new();
+		let input = "int x=5; (( 0 && x++ ))";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 1);
+		assert_eq!(slash.vars().get_var("x"), Some(SlashVal::Int(5)));
+	}
+}
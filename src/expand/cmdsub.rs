@@ -1,4 +1,6 @@
-use crate::{execute, prelude::*, utils};
+use nix::{sys::wait::waitpid, unistd::{lseek, Whence}};
+
+use crate::{ast::{self, Block}, execute, prelude::*, shellenv::write_jobs, utils};
 
 pub fn expand_cmd_sub(mut pair: Pair<Rule>,slash: &mut Slash) -> SlashResult<String> {
 	if pair.as_rule() == Rule::word {
@@ -8,41 +10,139 @@ pub fn expand_cmd_sub(mut pair: Pair<Rule>,slash: &mut Slash) -> SlashResult<Str
 	// Get the subshell token
 	let body = pair.as_str();
 	let body = &body[2..body.len() - 1]; // From '$(this)' to 'this'
+	cmd_sub_from_str(body, slash)
+}
+
+/// Runs the body of a command substitution and returns its output
+/// The body runs inside of the current process unless it calls something that would take the process with it
+pub fn cmd_sub_from_str(input: &str,slash: &mut Slash) -> SlashResult<String> {
+	let input = super::dispatch::expand_aliases(input.consume_escapes(), 0, vec![], slash)?;
+	let body = ast::parse(&input)?;
+	if needs_fork(&body) {
+		fork_cmd_sub(&body, slash)
+	} else {
+		in_process_cmd_sub(&body, slash)
+	}
+}
+
+/// `exec` replaces the process that runs it, and `trap` changes how the whole process handles signals
+fn needs_fork(body: &Block) -> bool {
+	body.any_simple_cmd(&|cmd| {
+		// `builtin` and `command` run their first argument as the command
+		let name = cmd.words().map(|word| word.as_str()).find(|name| !matches!(*name, "builtin" | "command"));
+		matches!(name, Some("exec" | "trap"))
+	})
+}
+
+/// Runs the body on a copy of the shell, with stdout pointed at an in-memory file
+/// Builtins and functions write straight into the buffer, and only external commands fork
+/// The working directory and environment are put back afterwards, since those belong to the process
+pub fn in_process_cmd_sub(body: &Block,slash: &mut Slash) -> SlashResult<String> {
+	let saved_fds = utils::save_fds()?;
+	let saved_dir = env::current_dir()?;
+	let saved_env = env::vars().collect::<HashMap<String,String>>();
 
+	let mut buffer_fd = utils::SmartFD::new_memfd("cmd_sub", false)?;
+	buffer_fd.dup2(&1)?;
+	let mut sub_slash = slash.clone();
+	sub_slash.ctx_mut().set_redirs(VecDeque::new());
+	sub_slash.ctx_mut().flags_mut().remove(utils::ExecFlags::NO_FORK | utils::ExecFlags::BACKGROUND);
+
+	let result = execute::dispatch::exec_block(body, &mut sub_slash);
+
+	utils::restore_fds(saved_fds, &mut sub_slash)?;
+	if env::current_dir().ok().as_ref() != Some(&saved_dir) {
+		env::set_current_dir(&saved_dir)?;
+	}
+	for (key,_) in env::vars() {
+		if !saved_env.contains_key(&key) {
+			env::remove_var(key);
+		}
+	}
+	for (key,val) in &saved_env {
+		if env::var(key).ok().as_ref() != Some(val) {
+			env::set_var(key, val);
+		}
+	}
+
+	slash.set_code(sub_status(result, &sub_slash));
+
+	lseek(buffer_fd.as_raw_fd(), 0, Whence::SeekSet).map_err(|_| Low(SlashErrLow::from_io()))?;
+	let mut buffer = String::new();
+	buffer_fd.read_to_string(&mut buffer)?;
+	buffer_fd.close()?;
+
+	Ok(buffer.trim().to_string())
+}
+
+/// Runs the body in a child process, reading its output through a pipe
+pub fn fork_cmd_sub(body: &Block,slash: &mut Slash) -> SlashResult<String> {
 	let (mut r_pipe, mut w_pipe) = utils::SmartFD::pipe()?;
-	let redir = utils::Redir::from_raw(1,w_pipe.as_raw_fd());
 	let mut sub_slash = slash.clone();
-	let flags = sub_slash.ctx_mut().flags_mut();
-	*flags |= utils::ExecFlags::NO_FORK; // Tell the child proc to not fork since it's already in a fork
-	sub_slash.ctx_mut().push_redir(redir);
+	sub_slash.ctx_mut().set_redirs(VecDeque::new());
+	// The body can be several commands, so it runs like a script instead of taking the process with its first command
+	sub_slash.ctx_mut().flags_mut().remove(utils::ExecFlags::NO_FORK | utils::ExecFlags::BACKGROUND);
 
-	match unsafe { fork() } {
+	let child = match unsafe { fork() } {
 		Ok(ForkResult::Child) => {
 			r_pipe.close()?;
-			// Execute the subshell body with the ctx payload
-			execute::dispatch::exec_input(body.consume_escapes(), &mut sub_slash)?;
-			std::process::exit(1);
+			w_pipe.dup2(&1)?;
+			w_pipe.close()?;
+			let result = execute::dispatch::exec_block(body, &mut sub_slash);
+			std::process::exit(sub_status(result, &sub_slash));
 		}
-		Ok(ForkResult::Parent { child: _ }) => {
+		Ok(ForkResult::Parent { child }) => {
 			w_pipe.close()?;
+			child
 		}
 		Err(_) => panic!()
-	}
+	};
 
 	let mut buffer = String::new();
 	r_pipe.read_to_string(&mut buffer)?;
 	r_pipe.close()?;
+	match waitpid(child, None) {
+		Ok(WaitStatus::Exited(_, code)) => slash.set_code(code),
+		Ok(WaitStatus::Signaled(_, sig, _)) => slash.set_code(utils::SIG_EXIT_OFFSET + sig as i32),
+		_ => {}
+	}
 
 	Ok(buffer.trim().to_string())
 }
 
-/// Used in tests
-pub fn cmd_sub_from_str(input: &str,slash: &mut Slash) -> SlashResult<String> {
-	// Get the subshell token
-	let body = input;
+/// The status that a command substitution leaves behind in `$?`
+/// Errors are reported here, except for `exit`, which just ends the substitution
+fn sub_status(result: SlashResult<()>, sub_slash: &Slash) -> i32 {
+	match result {
+		Ok(_) => sub_slash.get_status(),
+		Err(err) => {
+			let low_err = match &err {
+				Low(low_err) => low_err,
+				High(high_err) => high_err.get_err()
+			};
+			if !matches!(low_err, SlashErrLow::CleanExit(_)) {
+				eprintln!("{}",err);
+			}
+			low_err.code()
+		}
+	}
+}
 
-	let (mut r_pipe, mut w_pipe) = utils::SmartFD::pipe()?;
-	let redir = utils::Redir::from_raw(1,w_pipe.as_raw_fd());
+/// Runs the body of `<(...)` or `>(...)` in the background, connected to the shell by a pipe
+/// Expands to a `/dev/fd/N` path for the shell's end of the pipe, which stays open until the command using it is done
+pub fn expand_proc_sub(pair: Pair<Rule>,slash: &mut Slash) -> SlashResult<String> {
+	expand_proc_sub_str(pair.as_str(), slash)
+}
+
+/// The same as expand_proc_sub(), for a process substitution that has already been taken out of its pair
+pub fn expand_proc_sub_str(body: &str,slash: &mut Slash) -> SlashResult<String> {
+	let reads_output = body.starts_with('<');
+	let body = &body[2..body.len() - 1]; // From '<(this)' to 'this'
+
+	let (r_pipe, w_pipe) = utils::SmartFD::pipe()?;
+	let (mut ours, mut theirs) = if reads_output { (r_pipe,w_pipe) } else { (w_pipe,r_pipe) };
+	let their_fd = if reads_output { 1 } else { 0 };
+	let redir = utils::Redir::from_raw(their_fd,theirs.as_raw_fd());
 	let mut sub_slash = slash.clone();
 	let flags = sub_slash.ctx_mut().flags_mut();
 	*flags |= utils::ExecFlags::NO_FORK; // Tell the child proc to not fork since it's already in a fork
@@ -50,24 +150,112 @@ pub fn cmd_sub_from_str(input: &str,slash: &mut Slash) -> SlashResult<String> {
 
 	match unsafe { fork() } {
 		Ok(ForkResult::Child) => {
-			r_pipe.close()?;
-			// Execute the subshell body with the ctx payload
-			execute::dispatch::exec_input(body.consume_escapes(), &mut sub_slash)?;
-			std::process::exit(1);
+			ours.close()?;
+			let code = match execute::dispatch::exec_input(body.consume_escapes(), &mut sub_slash) {
+				Ok(_) => sub_slash.get_status(),
+				Err(_) => 1
+			};
+			std::process::exit(code);
 		}
-		Ok(ForkResult::Parent { child: _ }) => {
-			w_pipe.close()?;
+		Ok(ForkResult::Parent { child }) => {
+			theirs.close()?;
+			let depth = slash.ctx().depth();
+			let fd = ours.into_raw_fd();
+			write_jobs(|j| j.push_proc_sub(child, fd, depth))?;
+			Ok(format!("/dev/fd/{}",fd))
 		}
-		Err(_) => panic!()
+		Err(_) => Err(Low(SlashErrLow::from_io()))
 	}
+}
 
-	let mut buffer = String::new();
-	r_pipe.read_to_string(&mut buffer)?;
-	r_pipe.close()?;
+#[cfg(test)]
+mod tests {
+	use std::time::Instant;
 
-	Ok(buffer.trim().to_string())
-}
+	use crate::{execute, shellenv::SlashVal};
 
-pub fn expand_proc_sub(pair: Pair<Rule>) -> String {
-	todo!()
+	use super::*;
+
+	#[test]
+	fn test_cmd_sub_in_process() {
+		let mut slash = Slash::new();
+		let cwd = env::current_dir().unwrap();
+		let input = "f() { echo \"func $1\"; }; output=$(int inner=5; cd /; f $inner)";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("output"), Some(SlashVal::String("func 5".into())));
+		// The substitution gets its own copy of the shell, and the process state is put back afterwards
+		assert_eq!(slash.vars().get_var("inner"), None);
+		assert_eq!(env::current_dir().unwrap(), cwd);
+	}
+	#[test]
+	fn test_needs_fork() {
+		let needs_fork = |input: &str| needs_fork(&ast::parse(input).unwrap());
+		assert!(needs_fork("exec ls"));
+		assert!(needs_fork("if true; then trap 'echo' INT; fi"));
+		assert!(needs_fork("builtin exec ls"));
+		assert!(!needs_fork("echo exec"));
+		assert!(!needs_fork("printf '%s' trap | cat"));
+	}
+	#[test]
+	fn test_cmd_sub_forked_status() {
+		let mut slash = Slash::new();
+		let body = ast::parse("echo hi; exit 3").unwrap();
+
+		assert_eq!(fork_cmd_sub(&body, &mut slash).unwrap(), "hi");
+		assert_eq!(slash.get_status(), 3);
+	}
+	/// Running the body in process is the point of not forking, so it has to be faster
+	#[test]
+	fn test_cmd_sub_faster_in_process() {
+		const RUNS: usize = 50;
+		let mut slash = Slash::new();
+		execute::dispatch::exec_input("f() { echo \"$1\"; }".to_string(), &mut slash).unwrap();
+		let body = ast::parse("f hello").unwrap();
+
+		// The fastest run of each is reported, so that noise from the rest of the system doesn't decide the result
+		let mut in_process = vec![];
+		let mut forked = vec![];
+		for _ in 0..RUNS {
+			let start = Instant::now();
+			assert_eq!(in_process_cmd_sub(&body, &mut slash).unwrap(), "hello");
+			in_process.push(start.elapsed());
+
+			let start = Instant::now();
+			assert_eq!(fork_cmd_sub(&body, &mut slash).unwrap(), "hello");
+			forked.push(start.elapsed());
+		}
+		let in_process = in_process.into_iter().min().unwrap();
+		let forked = forked.into_iter().min().unwrap();
+		assert!(in_process < forked, "fastest of {} command substitutions: {:?} in process, {:?} forked", RUNS, in_process, forked);
+	}
+	#[test]
+	fn test_proc_sub() {
+		let mut slash = Slash::new();
+		let input = "output=$(cat <(echo hello))";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("output"), Some(SlashVal::String("hello".into())));
+	}
+	#[test]
+	fn test_proc_sub_args() {
+		let mut slash = Slash::new();
+		let input = "cmp -s <(echo one) <(echo two)";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 1);
+		// The shell's ends of the pipes are closed once the command using them is done
+		assert!(write_jobs(|j| j.take_proc_subs(0)).unwrap().is_empty());
+	}
+	#[test]
+	fn test_proc_sub_waited() {
+		let mut slash = Slash::new();
+		let path = env::temp_dir().join(format!("slash_proc_sub_{}", std::process::id()));
+		let input = format!("true >(sh -c 'sleep 0.1; echo finished > {}')", path.display());
+
+		// The substitution has exited by the time the command that used it returns
+		execute::dispatch::exec_input(input, &mut slash).unwrap();
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "finished\n");
+		std::fs::remove_file(&path).unwrap();
+	}
 }
@@ -1,4 +1,4 @@
-use crate::{expand, prelude::*};
+use crate::{expand, prelude::*, shellenv::EnvFlags};
 
 struct Expansion<'a> {
 	expanded: String,
@@ -68,9 +68,11 @@ pub fn expand_cmd<'a>(cmd: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<Stri
 	// Order matters
 	let expand_rules = [
 		Rule::var_sub,
+		Rule::param_exp,
 		Rule::param_sub,
 		Rule::glob_word,
 		Rule::dquoted,
+		Rule::arith_sub,
 		Rule::cmd_sub,
 		Rule::arr_index,
 		Rule::proc_sub,
@@ -134,6 +136,10 @@ pub fn rule_pass<'a>(rule: Rule, buffer: String, slash: &mut Slash) -> SlashResu
 		Rule::std_assign,
 		Rule::plus_assign,
 		Rule::minus_assign,
+		Rule::times_assign,
+		Rule::div_assign,
+		Rule::mod_assign,
+		Rule::pow_assign,
 		Rule::increment,
 		Rule::decrement
 	];
@@ -146,19 +152,16 @@ pub fn rule_pass<'a>(rule: Rule, buffer: String, slash: &mut Slash) -> SlashResu
 		if word.contains_rules(rule) {
 			let span = word.as_span();
 			let expanded = match rule {
-				Rule::var_sub => {
-					slash.vars().get_var(&word.as_str()[1..]).unwrap_or_default().to_string()
-				}
-				Rule::param_sub => {
-					let param = slash.vars().get_param(&word.as_str()[1..]).unwrap_or_default().to_string();
-					param
-				}
+				Rule::var_sub => expand_var(&word.as_str()[1..], slash)?,
+				Rule::param_exp => expand::param::expand_param_exp(word,slash)?,
+				Rule::param_sub => expand_param(&word.as_str()[1..], slash)?,
 				Rule::dquoted => expand::string::expand_string(word,slash)?,
 				Rule::arr_index => expand::index::expand_index(word,slash)?,
 				Rule::glob_word => expand::glob::expand_glob(word),
 				Rule::brace_word => expand::brace::expand_brace(word),
+				Rule::arith_sub => expand::arithmetic::expand_arith(word,slash)?,
 				Rule::cmd_sub => expand::cmdsub::expand_cmd_sub(word,slash)?,
-				Rule::proc_sub => expand::cmdsub::expand_proc_sub(word),
+				Rule::proc_sub => expand::cmdsub::expand_proc_sub(word,slash)?,
 				Rule::tilde_sub => expand::misc::expand_tilde(word)?,
 				_ => unreachable!()
 			};
@@ -169,17 +172,43 @@ pub fn rule_pass<'a>(rule: Rule, buffer: String, slash: &mut Slash) -> SlashResu
 	Ok(result)
 }
 
+/// Unset variables expand to nothing, unless `set -u` is on
+pub fn expand_var(var_name: &str, slash: &Slash) -> SlashResult<String> {
+	match slash.vars().get_var(var_name) {
+		Some(val) => Ok(val.to_string()),
+		None if slash.meta().flags().contains(EnvFlags::UNSET_IS_ERROR) => {
+			Err(Low(SlashErrLow::ExecFailed(format!("{}: unbound variable",var_name))))
+		}
+		None => Ok(String::new())
+	}
+}
+
+pub fn expand_param(param_name: &str, slash: &Slash) -> SlashResult<String> {
+	match slash.vars().get_param(param_name) {
+		Some(param) => Ok(param),
+		// $@ and $* are allowed to be empty even under `set -u`
+		None if slash.meta().flags().contains(EnvFlags::UNSET_IS_ERROR) && !matches!(param_name, "@" | "*") => {
+			Err(Low(SlashErrLow::ExecFailed(format!("{}: unbound variable",param_name))))
+		}
+		None => Ok(String::new())
+	}
+}
+
 pub fn rule_queue() -> Vec<Rule> {
 	vec![
 		Rule::cmd_sub,
+		Rule::arith_sub,
 		Rule::param_sub,
+		Rule::param_exp,
 		Rule::var_sub,
+		Rule::arr_index,
+		Rule::arr_len,
+		Rule::proc_sub,
 		Rule::dquoted
 	]
 }
 
-pub fn expand_word<'a>(pair: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<String> {
-	let word = pair.as_str();
+pub fn expand_word(word: &str, slash: &mut Slash) -> SlashResult<String> {
 	let mut rule_queue = rule_queue();
 	let expansions = match SlashParse::parse(Rule::expand_word_loud, word) {
 		Ok(mut parsed) => parsed.next().unwrap(),
@@ -191,21 +220,7 @@ pub fn expand_word<'a>(pair: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<St
 		let mut matches = expansions.seek_all(rule);
 		while let Some(pair) = matches.pop_front() {
 			let span = pair.as_span();
-			let expanded = match rule {
-				Rule::cmd_sub => expand::cmdsub::expand_cmd_sub(pair,slash)?,
-				Rule::param_sub => {
-					let param_name = &pair.as_str()[1..];
-					let param = slash.vars().get_param(param_name).unwrap_or_default().to_string();
-					param
-				}
-				Rule::var_sub => {
-					let var_name = &pair.as_str()[1..];
-					let result = slash.vars().get_var(var_name).unwrap_or_default().to_string();
-					result
-				}
-				Rule::dquoted => expand::string::expand_string(pair,slash)?,
-				_ => unreachable!()
-			};
+			let expanded = expand_pair(rule, pair, slash)?;
 			let exp = Expansion { expanded, span };
 			exp_ir.push_expansion(exp);
 		}
@@ -215,3 +230,19 @@ pub fn expand_word<'a>(pair: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<St
 
 	Ok(result)
 }
+
+/// Expands a single pair found by one of the rules in `rule_queue()`
+pub fn expand_pair(rule: Rule, pair: Pair<Rule>, slash: &mut Slash) -> SlashResult<String> {
+	Ok(match rule {
+		Rule::cmd_sub => expand::cmdsub::expand_cmd_sub(pair,slash)?,
+		Rule::arith_sub => expand::arithmetic::expand_arith(pair,slash)?,
+		Rule::param_sub => expand_param(&pair.as_str()[1..], slash)?,
+		Rule::var_sub => expand_var(&pair.as_str()[1..], slash)?,
+		Rule::arr_index => expand::index::expand_index(pair,slash)?,
+		Rule::arr_len => expand::index::expand_len(pair,slash)?,
+		Rule::param_exp => expand::param::expand_param_exp(pair,slash)?,
+		Rule::proc_sub => expand::cmdsub::expand_proc_sub(pair,slash)?,
+		Rule::dquoted => expand::string::expand_string(pair,slash)?,
+		_ => unreachable!()
+	})
+}
@@ -1,22 +1,109 @@
-use crate::{prelude::*, shellenv::SlashVal};
+use crate::{helper::StrExtension, prelude::*, shellenv::{self, SlashVal}};
 
+/// Expands `$arr[1]`, `$arr[-1]`, `$arr[0..2]`, `$arr[@]`, `$dict["key"]`, and any chain of these like `$dict["key"][0]`
+/// Indexing into something that isn't there expands to nothing
 pub fn expand_index(pair: Pair<Rule>,slash: &mut Slash) -> SlashResult<String> {
-	let mut inner = pair.step(1).unpack()?.into_inner().peekable();
-	let arr_name = inner.next().unpack()?;
+	Ok(index_value(pair, slash)?.map_or_else(String::new, |val| val.to_string()))
+}
+
+/// Gets the value that an index expression points to, keeping its type
+pub fn index_value(pair: Pair<Rule>,slash: &mut Slash) -> SlashResult<Option<SlashVal>> {
+	let arr_index = if pair.as_rule() == Rule::arr_index {
+		pair
+	} else {
+		pair.scry(Rule::arr_index).unpack()?
+	};
+	let mut inner = arr_index.into_inner();
+	let var_name = inner.next().unpack()?.as_str().trim_start_matches('{').trim_end_matches('}').to_string();
 
-	let array = slash.vars().get_var(arr_name.as_str());
-	let mut cur_val = match array {
-		Some(SlashVal::Array(vec)) => Some(SlashVal::Array(vec)),
-		_ => return Ok(String::new()), // If not an array, return empty
+	let Some(mut cur_val) = slash.vars().get_var(&var_name) else {
+		return Ok(None)
 	};
 
-	while let Some(index) = inner.next() {
-		let idx = index.as_str().parse::<usize>().map_err(|_| Low(SlashErrLow::IndexErr(format!("Index '{}' out of range for array '{}'",index,arr_name.as_str()))))?;
-		cur_val = match cur_val {
-			Some(SlashVal::Array(vec)) => vec.get(idx).cloned(),
-			_ => return Ok(String::new()), // Invalid nesting (e.g., indexing a non-array)
+	for index in inner {
+		cur_val = match (index.as_rule(), cur_val) {
+			// `[@]` takes the whole value, which only matters when it gets splatted into separate words
+			(Rule::splat, val) => val,
+			(Rule::slice, SlashVal::Array(vec)) => {
+				let (start,end) = index.as_str().split_once("..").unwrap_or_default();
+				let start = slice_bound(start, 0, vec.len());
+				let end = slice_bound(end, vec.len(), vec.len()).max(start);
+				SlashVal::Array(vec[start..end].to_vec())
+			}
+			(Rule::slice, _) => return Ok(None),
+			(_, val) => {
+				let key = expand_key(index.as_str(), slash)?;
+				match val.get_element(&[key]) {
+					Some(element) => element.clone(),
+					None => return Ok(None)
+				}
+			}
 		};
 	}
 
-	Ok(cur_val.map_or_else(String::new, |val| val.to_string()))
+	Ok(Some(cur_val))
+}
+
+/// Expands `$#arr` to the number of elements in an array or dict, or the number of characters in anything else
+/// If no variable has that name, this is `$#` followed by plain text, so `"$#args"` still works the way it does in other shells
+pub fn expand_len(pair: Pair<Rule>,slash: &mut Slash) -> SlashResult<String> {
+	let ident = pair.scry(Rule::var_ident).unpack()?.as_str();
+	let var_name = ident.trim_start_matches('{').trim_end_matches('}');
+	match var_len(var_name, slash) {
+		Some(len) => Ok(len.to_string()),
+		None => Ok(format!("{}{}",slash.vars().get_param("#").unwrap_or("0".into()),ident))
+	}
+}
+
+/// The length that `$#name` gives for a variable, or None if it is unset
+pub fn var_len(var_name: &str,slash: &Slash) -> Option<usize> {
+	match slash.vars().get_var(var_name)? {
+		SlashVal::Array(arr) => Some(arr.len()),
+		SlashVal::Dict(dict) => Some(dict.len()),
+		val => Some(val.to_string().chars().count())
+	}
+}
+
+/// Gets the elements of a word that is nothing but `$arr[@]` or `${arr[@]}`, so that each one can become a separate argument
+/// Dicts give their values
+pub fn splat_word(word: &str, slash: &mut Slash) -> SlashResult<Option<Vec<String>>> {
+	let word = word.trim_quotes();
+	let word = match word.strip_prefix("${").and_then(|inner| inner.strip_suffix('}')) {
+		Some(inner) => format!("${}",inner),
+		None => word.to_string()
+	};
+	if !word.ends_with("[@]") {
+		return Ok(None)
+	}
+	let Ok(mut parsed) = SlashParse::parse(Rule::arr_index, &word) else {
+		return Ok(None)
+	};
+	let pair = parsed.next().unpack()?;
+	if pair.as_str().len() != word.len() {
+		return Ok(None)
+	}
+	let elements = match index_value(pair, slash)? {
+		Some(SlashVal::Array(arr)) => arr.iter().map(|val| val.to_string()).collect(),
+		Some(SlashVal::Dict(dict)) => dict.values().map(|val| val.to_string()).collect(),
+		Some(val) => vec![val.to_string()],
+		None => vec![]
+	};
+	Ok(Some(elements))
+}
+
+/// Keys in double quotes are expanded, and keys in single quotes are taken literally
+pub fn expand_key(key: &str, slash: &mut Slash) -> SlashResult<String> {
+	if key.starts_with('"') {
+		Ok(super::dispatch::expand_word(key, slash)?.trim_quotes().to_string())
+	} else {
+		Ok(key.trim_quotes().to_string())
+	}
+}
+
+/// Missing bounds default to the start or end of the array, and bounds past the end are clamped to it
+fn slice_bound(bound: &str, default: usize, len: usize) -> usize {
+	if bound.is_empty() {
+		return default
+	}
+	shellenv::array_pos(bound, len).unwrap_or(0).min(len)
 }
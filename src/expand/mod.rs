@@ -1,7 +1,10 @@
+pub mod arithmetic;
 pub mod brace;
 pub mod cmdsub;
 pub mod dispatch;
 pub mod glob;
 pub mod index;
 pub mod misc;
+pub mod param;
+pub mod split;
 pub mod string;
@@ -0,0 +1,229 @@
+use regex::{NoExpand, Regex};
+
+use crate::{prelude::*, shellenv::SlashVal};
+
+use super::{arithmetic, dispatch::{expand_param, expand_var, expand_word}};
+
+/// Expands `${...}`, applying whichever operator is used inside of the braces
+pub fn expand_param_exp(mut pair: Pair<Rule>, slash: &mut Slash) -> SlashResult<String> {
+	if pair.as_rule() == Rule::word {
+		pair = pair.step(1).unpack()?;
+	}
+	let body = pair.as_str();
+	let body = &body[2..body.len() - 1]; // From '${this}' to 'this'
+	if let Some(expanded) = expand_bracketed_index(body, slash)? {
+		return Ok(expanded)
+	}
+	let exp = SlashParse::parse(Rule::param_exp_main, body)
+		.map_err(|_| Low(SlashErrLow::InvalidSyntax(format!("Bad substitution: ${{{}}}",body))))?
+		.next()
+		.unpack()?
+		.step(1)
+		.unpack()?;
+	let name = exp.scry(Rule::param_name).unpack()?.as_str().to_string();
+	let arg = match exp.scry(Rule::param_arg) {
+		Some(arg) => expand_word(arg.as_str(), slash)?,
+		None => String::new()
+	};
+
+	let expanded = match exp.as_rule() {
+		Rule::param_default | Rule::param_assign | Rule::param_error | Rule::param_alt => {
+			// These only care about whether the parameter is unset or empty, so they never trip `set -u`
+			let val = lookup(&name, slash).filter(|val| !val.is_empty());
+			match (exp.as_rule(), val) {
+				(Rule::param_alt, Some(_)) => arg,
+				(Rule::param_alt, None) => String::new(),
+				(_, Some(val)) => val,
+				(Rule::param_default, None) => arg,
+				(Rule::param_assign, None) => {
+					if !is_var_name(&name) {
+						return Err(Low(SlashErrLow::ExecFailed(format!("${}: cannot assign in this way",name))))
+					}
+					slash.vars_mut().set_var(&name, SlashVal::parse(&arg)?)?;
+					arg
+				}
+				_ => {
+					let msg = if arg.is_empty() { "parameter null or not set" } else { arg.as_str() };
+					return Err(Low(SlashErrLow::ExecFailed(format!("{}: {}",name,msg))))
+				}
+			}
+		}
+		Rule::param_length => read(&name, slash)?.chars().count().to_string(),
+		Rule::param_substr => {
+			let val = read(&name, slash)?.chars().collect::<Vec<char>>();
+			let mut bounds = exp.into_inner().filter(|pr| pr.as_rule() == Rule::param_arith);
+			let offset = eval_index(bounds.next().unpack()?.as_str(), slash)?;
+			let len = val.len() as i64;
+			// Negative offsets and lengths count backwards from the end
+			let start = if offset < 0 { (len + offset).max(0) } else { offset.min(len) };
+			let end = match bounds.next() {
+				Some(bound) => {
+					let bound = eval_index(bound.as_str(), slash)?;
+					if bound < 0 { len + bound } else { start + bound }
+				}
+				None => len
+			};
+			if end < start {
+				return Err(Low(SlashErrLow::ExecFailed(format!("{}: substring expression < 0",name))))
+			}
+			val[start as usize..end.min(len) as usize].iter().collect()
+		}
+		Rule::param_trim_pre | Rule::param_trim_pre_long | Rule::param_trim_suf | Rule::param_trim_suf_long => {
+			let val = read(&name, slash)?;
+			let pat = glob_to_regex(&arg, true)?;
+			let rule = exp.as_rule();
+			let longest = matches!(rule, Rule::param_trim_pre_long | Rule::param_trim_suf_long);
+			let mut bounds = val.char_indices().map(|(i,_)| i).chain([val.len()]).collect::<Vec<usize>>();
+			if matches!(rule, Rule::param_trim_pre | Rule::param_trim_pre_long) {
+				if longest {
+					bounds.reverse();
+				}
+				match bounds.into_iter().find(|i| pat.is_match(&val[..*i])) {
+					Some(i) => val[i..].to_string(),
+					None => val
+				}
+			} else {
+				if !longest {
+					bounds.reverse();
+				}
+				match bounds.into_iter().find(|i| pat.is_match(&val[*i..])) {
+					Some(i) => val[..i].to_string(),
+					None => val
+				}
+			}
+		}
+		Rule::param_replace | Rule::param_replace_all => {
+			let val = read(&name, slash)?;
+			let pat = exp.scry(Rule::param_pat).unpack()?.as_str().replace("\\/", "/");
+			let pat = glob_to_regex(&expand_word(&pat, slash)?, false)?;
+			if exp.as_rule() == Rule::param_replace_all {
+				pat.replace_all(&val, NoExpand(&arg)).to_string()
+			} else {
+				pat.replace(&val, NoExpand(&arg)).to_string()
+			}
+		}
+		Rule::param_upper => read(&name, slash)?.to_uppercase(),
+		Rule::param_lower => read(&name, slash)?.to_lowercase(),
+		Rule::param_plain => read(&name, slash)?,
+		_ => return Err(Low(SlashErrLow::InternalErr(format!("Unexpected rule in parameter expansion: {:?}",exp.as_rule()))))
+	};
+	Ok(expanded)
+}
+
+/// `${arr[1]}` and `${#arr[@]}` are the same as `$arr[1]` and `$#arr`
+fn expand_bracketed_index(body: &str, slash: &mut Slash) -> SlashResult<Option<String>> {
+	if !body.ends_with(']') {
+		return Ok(None)
+	}
+	if let Some(name) = body.strip_prefix('#').and_then(|name| name.strip_suffix("[@]")) {
+		// Unlike `$#arr`, this can't be read as `$#` followed by text, so an unset array is just empty
+		let valid = is_var_name(name) && name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+		return Ok(valid.then(|| super::index::var_len(name, slash).unwrap_or(0).to_string()))
+	}
+	let sub = format!("${}",body);
+	let Ok(mut parsed) = SlashParse::parse(Rule::arr_index, &sub) else {
+		return Ok(None)
+	};
+	let pair = parsed.next().unpack()?;
+	if pair.as_str().len() != sub.len() {
+		return Ok(None)
+	}
+	Ok(Some(super::index::expand_index(pair, slash)?))
+}
+
+fn is_var_name(name: &str) -> bool {
+	name.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_')
+}
+
+/// Gets the value of a variable or parameter, or None if it is unset
+fn lookup(name: &str, slash: &Slash) -> Option<String> {
+	if is_var_name(name) {
+		slash.vars().get_var(name).map(|val| val.to_string())
+	} else {
+		slash.vars().get_param(name)
+	}
+}
+
+/// The same as lookup(), but unset names expand to nothing, or fail under `set -u`
+fn read(name: &str, slash: &Slash) -> SlashResult<String> {
+	if is_var_name(name) {
+		expand_var(name, slash)
+	} else {
+		expand_param(name, slash)
+	}
+}
+
+fn eval_index(expr: &str, slash: &mut Slash) -> SlashResult<i64> {
+	match arithmetic::eval(expr, slash)? {
+		SlashVal::Int(int) => Ok(int),
+		val => Err(Low(SlashErrLow::ExecFailed(format!("Expected an integer in substring expansion, got {}",val))))
+	}
+}
+
+/// Translates a glob pattern into a regex, so that `*`, `?` and `[...]` work in `${var#pat}` and friends
+/// If `anchored` is true, the pattern has to match the entire string
+pub fn glob_to_regex(pat: &str, anchored: bool) -> SlashResult<Regex> {
+	let mut regex = String::new();
+	let mut chars = pat.chars().peekable();
+	while let Some(ch) = chars.next() {
+		match ch {
+			'*' => regex.push_str(".*"),
+			'?' => regex.push('.'),
+			'\\' => {
+				if let Some(escaped) = chars.next() {
+					regex.push_str(&regex::escape(&escaped.to_string()));
+				}
+			}
+			'[' => {
+				let class = chars.by_ref().take_while(|ch| *ch != ']').collect::<String>();
+				let class = match class.strip_prefix('!') {
+					Some(negated) => format!("^{}",negated),
+					None => class
+				};
+				regex.push_str(&format!("[{}]",class.replace('\\', "\\\\")));
+			}
+			_ => regex.push_str(&regex::escape(&ch.to_string()))
+		}
+	}
+	let regex = if anchored { format!("(?s)^{}$",regex) } else { format!("(?s){}",regex) };
+	Regex::new(&regex).map_err(|_| Low(SlashErrLow::InvalidSyntax(format!("Invalid pattern: {}",pat))))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::execute;
+
+	use super::*;
+
+	#[test]
+	fn test_param_exp() {
+		let mut slash = Slash::new();
+		let input = "path=/usr/local/lib.tar.gz; word=Hello";
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+
+		let cases = [
+			("${unset_var:-fallback}", "fallback"),
+			("${word:+alt}", "alt"),
+			("${unset_var:+alt}", ""),
+			("${#path}", "21"),
+			("${path#*/}", "usr/local/lib.tar.gz"),
+			("${path##*/}", "lib.tar.gz"),
+			("${path%.*}", "/usr/local/lib.tar"),
+			("${path%%.*}", "/usr/local/lib"),
+			("${path/l/L}", "/usr/Local/lib.tar.gz"),
+			("${path//l/L}", "/usr/LocaL/Lib.tar.gz"),
+			("${word:1:3}", "ell"),
+			("${word: -3}", "llo"),
+			("${word:1:-1}", "ell"),
+			("${word^^}", "HELLO"),
+			("${word,,}", "hello"),
+		];
+		for (input,expected) in cases {
+			assert_eq!(expand_word(input, &mut slash).unwrap(), expected, "{}", input);
+		}
+
+		assert_eq!(expand_word("${new_var:=assigned}", &mut slash).unwrap(), "assigned");
+		assert_eq!(slash.vars().get_var("new_var"), Some(SlashVal::String("assigned".into())));
+		assert!(expand_word("${unset_var:?oops}", &mut slash).is_err());
+	}
+}
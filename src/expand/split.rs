@@ -0,0 +1,378 @@
+use crate::{ast::{Word, WordKind}, helper::{self, StrExtension}, prelude::*, shellenv::SlashVal};
+
+use super::dispatch;
+
+const DEFAULT_IFS: &str = " \t\n";
+
+/// One argument produced by expanding a word
+/// `pattern` is the same text with quoted glob characters escaped, so that only unquoted ones are used for globbing
+#[derive(Debug,Default,PartialEq)]
+pub struct Field {
+	pub text: String,
+	pub pattern: String
+}
+
+impl Field {
+	fn push(&mut self, ch: char, quoted: bool) {
+		self.text.push(ch);
+		if quoted {
+			self.pattern.push_str(&glob::Pattern::escape(&ch.to_string()));
+		} else {
+			self.pattern.push(ch);
+		}
+	}
+}
+
+/// Tracks the quoting in the literal text of a word as it is walked through
+#[derive(Default,Clone,Copy)]
+struct QuoteState {
+	quote: Option<char>,
+	escaped: bool
+}
+
+impl QuoteState {
+	/// Feeds one character of the word through quote removal, passing along the ones that are kept and whether they are quoted
+	/// A backslash escapes the next character outside of quotes, but inside of double quotes it only escapes the ones that are special there
+	fn feed(&mut self, ch: char, mut keep: impl FnMut(char, bool)) {
+		if self.escaped {
+			self.escaped = false;
+			if self.quote == Some('"') && !matches!(ch, '$' | '`' | '"' | '\\' | '\n') {
+				keep('\\', true);
+			}
+			keep(ch, true);
+			return
+		}
+		match (ch, self.quote) {
+			('\\', None | Some('"')) => self.escaped = true,
+			('\'' | '"', None) => self.quote = Some(ch),
+			(_, Some(quote)) if ch == quote => self.quote = None,
+			(_, quote) => keep(ch, quote.is_some())
+		}
+	}
+}
+
+/// Builds fields from the pieces of a word, keeping track of whether the current field exists yet
+/// A field exists once anything has been added to it, even an empty pair of quotes
+#[derive(Default)]
+struct FieldBuilder {
+	fields: Vec<Field>,
+	cur: Field,
+	has_cur: bool,
+	quoted: bool,
+	empty_params: bool,
+	state: QuoteState
+}
+
+impl FieldBuilder {
+	/// Adds literal text from the word, removing the quotes and backslashes in it
+	fn push_literal(&mut self, text: &str) {
+		for ch in text.chars() {
+			self.has_cur = true;
+			let cur = &mut self.cur;
+			let mut kept = false;
+			self.state.feed(ch, |ch, quoted| { kept = true; cur.push(ch, quoted) });
+			self.quoted |= !kept;
+		}
+	}
+	/// Adds the result of an expansion in double quotes, which is never split
+	fn push_quoted(&mut self, text: &str) {
+		self.has_cur = true;
+		for ch in text.chars() {
+			self.cur.push(ch, true);
+		}
+	}
+	/// Adds `"$@"`, which gives each positional parameter as its own field
+	/// Text around it is joined onto the first and last parameters
+	fn push_params(&mut self, params: &VecDeque<String>) {
+		self.empty_params |= params.is_empty();
+		for (i,param) in params.iter().enumerate() {
+			if i > 0 {
+				self.fields.push(std::mem::take(&mut self.cur));
+			}
+			self.push_quoted(param);
+		}
+	}
+	/// Adds the result of an unquoted expansion, splitting it on the characters in `ifs`
+	/// Runs of whitespace in `ifs` count as one delimiter and are dropped at the edges of the result,
+	/// while each of the other characters in `ifs` ends a field, even an empty one
+	fn push_split(&mut self, text: &str, ifs: &str) {
+		let mut chars = text.chars().peekable();
+		while let Some(ch) = chars.next() {
+			if !ifs.contains(ch) {
+				self.has_cur = true;
+				self.cur.push(ch, false);
+				continue
+			}
+			let mut hard = !ch.is_whitespace();
+			while let Some(&next) = chars.peek() {
+				if !ifs.contains(next) || (hard && !next.is_whitespace()) {
+					break
+				}
+				hard |= !next.is_whitespace();
+				chars.next();
+			}
+			if self.has_cur || hard {
+				self.fields.push(std::mem::take(&mut self.cur));
+			}
+			self.has_cur = false;
+			self.empty_params = false;
+		}
+	}
+	fn finish(mut self) -> Vec<Field> {
+		if self.state.escaped {
+			// A trailing backslash has nothing to escape, so it is kept
+			self.cur.push('\\', false);
+		}
+		// With no positional parameters, `"$@"` gives no field at all instead of an empty one
+		if self.has_cur && !(self.empty_params && self.cur.text.is_empty()) {
+			self.fields.push(self.cur);
+		}
+		self.fields
+	}
+}
+
+/// Expands a word into the fields it produces
+/// Results of unquoted expansions are split using `IFS`, which defaults to space, tab, and newline, and an empty `IFS` turns splitting off
+/// Quotes are removed afterwards, so that quoted parts of the word are never split
+/// Expansions inside of single quotes are left as they are
+/// Array and dict literals and assignments always give a single field
+pub fn expand_fields(word: &Word, slash: &mut Slash) -> SlashResult<Vec<Field>> {
+	match &word.kind {
+		WordKind::Array | WordKind::Dict => {
+			let text = helper::try_expansion(slash, word)?.trim_quotes();
+			Ok(vec![Field { pattern: text.clone(), text }])
+		}
+		WordKind::Assign { var, val } => {
+			let val = match val {
+				Some(val) => expand_value_text(val, slash)?.0,
+				None => String::new()
+			};
+			let text = format!("{}={}",var,val);
+			Ok(vec![Field { pattern: text.clone(), text }])
+		}
+		_ => {
+			let ifs = slash.vars().get_var("IFS").map_or(DEFAULT_IFS.to_string(), |ifs| ifs.to_string());
+			Ok(build_fields(word, Some(&ifs), slash)?.finish())
+		}
+	}
+}
+
+/// Expands a word that gives a value instead of arguments, like the right side of an assignment
+/// The result is never split, and it is only parsed into a typed value if no part of the word was quoted
+pub fn expand_value(word: &Word, slash: &mut Slash) -> SlashResult<SlashVal> {
+	if matches!(word.kind, WordKind::Array | WordKind::Dict) {
+		return SlashVal::parse(&helper::try_expansion(slash, word)?)
+	}
+	match expand_value_text(word, slash)? {
+		(text, true) => Ok(SlashVal::String(text)),
+		(text, false) => SlashVal::parse(&text)
+	}
+}
+
+/// Expands a word into a single string, along with whether any part of it was quoted
+fn expand_value_text(word: &Word, slash: &mut Slash) -> SlashResult<(String, bool)> {
+	let builder = build_fields(word, None, slash)?;
+	let quoted = builder.quoted;
+	let text = builder.finish().pop().map(|field| field.text).unwrap_or_default();
+	Ok((text, quoted))
+}
+
+/// Runs the expansions in a word and removes its quotes
+/// Unquoted expansions are only split if `ifs` is given
+fn build_fields(word: &Word, ifs: Option<&str>, slash: &mut Slash) -> SlashResult<FieldBuilder> {
+	let raw = word.as_str();
+	let mut spans = vec![];
+	if word.expand {
+		if let Ok(mut parsed) = SlashParse::parse(Rule::expand_word_loud, raw) {
+			let expansions = parsed.next().unpack()?;
+			for rule in dispatch::rule_queue() {
+				for pair in expansions.seek_all(rule) {
+					spans.push((pair.as_span().start(), pair.as_span().end(), rule, pair));
+				}
+			}
+		}
+	}
+	spans.sort_by_key(|(start,..)| *start);
+	// Expansions nested inside of other ones are handled by the outer one
+	let mut outer_end = 0;
+	spans.retain(|(start, end, ..)| {
+		let is_outer = *start >= outer_end;
+		if is_outer {
+			outer_end = *end;
+		}
+		is_outer
+	});
+
+	// Find the quoting around each expansion before running any of them
+	let mut quotes = vec![];
+	let mut state = QuoteState::default();
+	let mut pos = 0;
+	for (start, end, ..) in &spans {
+		for ch in raw[pos..*start].chars() {
+			state.feed(ch, |_,_| {});
+		}
+		quotes.push(state.quote);
+		pos = *end;
+	}
+
+	// Expansions run in the same order as in expand_word(), and are put back together in the order they appear
+	let mut expanded: Vec<Option<String>> = vec![None; spans.len()];
+	for rule in dispatch::rule_queue().into_iter().rev() {
+		for (i,(_, _, pair_rule, pair)) in spans.iter().enumerate() {
+			if *pair_rule == rule && quotes[i] != Some('\'') {
+				expanded[i] = Some(dispatch::expand_pair(rule, pair.clone(), slash)?);
+			}
+		}
+	}
+
+	let mut builder = FieldBuilder::default();
+	let mut pos = 0;
+	for (i,(start, end, _, pair)) in spans.iter().enumerate() {
+		builder.push_literal(&raw[pos..*start]);
+		let all_params = matches!(pair.as_str(), "$@" | "${@}");
+		match (&expanded[i], quotes[i]) {
+			(Some(_), Some('"')) if all_params && ifs.is_some() => builder.push_params(slash.vars().borrow_pos_params()),
+			(Some(text), None) => match ifs {
+				Some(ifs) => builder.push_split(text, ifs),
+				None => builder.push_quoted(text)
+			}
+			(Some(text), Some(_)) => builder.push_quoted(text),
+			(None, _) => builder.push_literal(&raw[*start..*end])
+		}
+		pos = *end;
+	}
+	builder.push_literal(&raw[pos..]);
+	Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{execute, shellenv::SlashVal};
+
+	use super::*;
+
+	#[test]
+	fn test_field_splitting() {
+		let mut slash = Slash::new();
+		let input = "files='a  b c'; arr out=[]; for f in $files; do arr push out $f; done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		let expected = vec![SlashVal::String("a".into()), SlashVal::String("b".into()), SlashVal::String("c".into())];
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::Array(expected)))
+	}
+	#[test]
+	fn test_quoted_not_split() {
+		let mut slash = Slash::new();
+		let input = "files='a  b c'; int count=$(printf '%.0sx' \"$files\" | wc -c)";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("count"), Some(SlashVal::Int(1)))
+	}
+	#[test]
+	fn test_split_joined_text() {
+		let mut slash = Slash::new();
+		let input = "files='a  b c'; joined=$(printf '%s.' x${files}y)";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("joined"), Some(SlashVal::String("xa.b.cy.".into())))
+	}
+	#[test]
+	fn test_custom_ifs() {
+		let mut slash = Slash::new();
+		let input = "IFS=:; path='/bin::/usr/bin'; int parts=$(printf '%.0sx' $path | wc -c)";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("parts"), Some(SlashVal::Int(3)))
+	}
+	#[test]
+	fn test_empty_ifs() {
+		let mut slash = Slash::new();
+		let input = "IFS=''; path='/bin /usr/bin'; int parts=$(printf '%.0sx' $path | wc -c)";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("parts"), Some(SlashVal::Int(1)))
+	}
+	#[test]
+	fn test_push_split() {
+		let split = |text: &str, ifs: &str| {
+			let mut builder = FieldBuilder::default();
+			builder.push_split(text, ifs);
+			builder.finish().into_iter().map(|field| field.text).collect::<Vec<String>>()
+		};
+		assert_eq!(split("  a \t b\n", DEFAULT_IFS), vec!["a", "b"]);
+		assert_eq!(split("a::b:", ":"), vec!["a", "", "b"]);
+		assert_eq!(split(" a : b ", " :"), vec!["a", "b"]);
+		assert_eq!(split("", DEFAULT_IFS), Vec::<String>::new());
+	}
+	#[test]
+	fn test_push_literal_escapes() {
+		let literal = |text: &str| {
+			let mut builder = FieldBuilder::default();
+			builder.push_literal(text);
+			builder.finish().into_iter().map(|field| field.text).collect::<Vec<String>>()
+		};
+		assert_eq!(literal(r#""say \"hi\"""#), vec![r#"say "hi""#]);
+		assert_eq!(literal(r#"a\ b\'c"#), vec!["a b'c"]);
+		assert_eq!(literal(r#""\$x \n \\""#), vec![r#"$x \n \"#]);
+		assert_eq!(literal(r#"'\n'"#), vec![r#"\n"#]);
+		assert_eq!(literal(r#"end\"#), vec![r#"end\"#]);
+	}
+	#[test]
+	fn test_escaped_quotes() {
+		let mut slash = Slash::new();
+		let input = r#"arr out=[]; arr push out "say \"hi\"" don\'t"#;
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		let expected = vec![SlashVal::String(r#"say "hi""#.into()), SlashVal::String("don't".into())];
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::Array(expected)));
+	}
+	#[test]
+	fn test_joined_words() {
+		let mut slash = Slash::new();
+		let input = r#"x="a b"c; arr out=[]; arr push out pre"$x"post 'it'\''s' "5"0"#;
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("x"), Some(SlashVal::String("a bc".into())));
+		let expected = vec![SlashVal::String("prea bcpost".into()), SlashVal::String("it's".into()), SlashVal::Int(50)];
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::Array(expected)));
+	}
+	#[test]
+	fn test_all_params() {
+		let mut slash = Slash::new();
+		let input = r#"set -- "a b" c; arr out=[]; arr push out "$@""#;
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		let expected = vec![SlashVal::String("a b".into()), SlashVal::String("c".into())];
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::Array(expected)))
+	}
+	#[test]
+	fn test_all_params_joined() {
+		let mut slash = Slash::new();
+		let input = r#"set -- "a b" c; arr out=[]; arr push out "x$@y" "[${@}]""#;
+
+		// The text around `"$@"` is joined onto the first and last fields
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		let expected = ["xa b", "cy", "[a b", "c]"].into_iter().map(|item| SlashVal::String(item.into())).collect();
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::Array(expected)))
+	}
+	#[test]
+	fn test_no_params() {
+		let mut slash = Slash::new();
+		let input = r#"set --; arr out=[]; arr push out "$@" "x$@""#;
+
+		// `"$@"` on its own leaves no field behind, but text next to it is still kept
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::Array(vec![SlashVal::String("x".into())])))
+	}
+	#[test]
+	fn test_quoted_values() {
+		let mut slash = Slash::new();
+		let input = r#"n=5; s="5"; t=$n; u='$n'"#;
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("n"), Some(SlashVal::Int(5)));
+		assert_eq!(slash.vars().get_var("s"), Some(SlashVal::String("5".into())));
+		assert_eq!(slash.vars().get_var("t"), Some(SlashVal::Int(5)));
+		assert_eq!(slash.vars().get_var("u"), Some(SlashVal::String("$n".into())));
+	}
+}
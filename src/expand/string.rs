@@ -17,19 +17,16 @@ pub fn expand_string(pair: Pair<Rule>, slash: &mut Slash) -> SlashResult<String>
 		} else {
 			let sub_type = inner.next().unpack()?;
 			let expanded = match sub_type.as_rule() {
-				Rule::var_sub => {
-					slash.vars().get_var(&word.as_str()[1..]).unwrap_or_default().to_string()
-				}
-				Rule::param_sub => {
-					let param = slash.vars().get_param(&word.as_str()[1..]).unwrap_or_default().to_string();
-					param
-				}
+				Rule::var_sub => super::dispatch::expand_var(&word.as_str()[1..], slash)?,
+				Rule::param_sub => super::dispatch::expand_param(&word.as_str()[1..], slash)?,
+				Rule::param_exp => super::param::expand_param_exp(sub_type,slash)?,
 				Rule::cmd_sub => {
 					let result = super::cmdsub::expand_cmd_sub(word,slash)?;
 					result
 				}
+				Rule::arith_sub => super::arithmetic::expand_arith(sub_type,slash)?,
 				Rule::arr_index => super::index::expand_index(word,slash)?,
-				Rule::proc_sub => super::cmdsub::expand_proc_sub(word),
+				Rule::proc_sub => super::cmdsub::expand_proc_sub(word,slash)?,
 				_ => continue
 			};
 			result.replace_span(span, &expanded);
@@ -38,3 +35,43 @@ pub fn expand_string(pair: Pair<Rule>, slash: &mut Slash) -> SlashResult<String>
 	result = format!("\"{}\"",result);
 	Ok(result)
 }
+
+/// Expands the body of a heredoc, which works like a double quoted string that has no closing quote
+/// Backslashes only escape `$`, `` ` ``, `\`, and newlines
+pub fn expand_heredoc(body: &str, slash: &mut Slash) -> SlashResult<String> {
+	let mut result = String::new();
+	let mut chars = body.char_indices().peekable();
+	while let Some((i,ch)) = chars.next() {
+		match ch {
+			'\\' => match chars.peek() {
+				Some((_, esc @ ('$' | '`' | '\\'))) => {
+					result.push(*esc);
+					chars.next();
+				}
+				Some((_, '\n')) => { chars.next(); }
+				_ => result.push(ch)
+			}
+			'$' => {
+				let rules = [Rule::arith_sub, Rule::param_exp, Rule::cmd_sub, Rule::arr_index, Rule::arr_len, Rule::var_sub, Rule::param_sub];
+				let Some(sub) = rules.into_iter().find_map(|rule| SlashParse::parse(rule, &body[i..]).ok()?.next()) else {
+					result.push(ch);
+					continue
+				};
+				let expanded = match sub.as_rule() {
+					Rule::arith_sub => super::arithmetic::expand_arith(sub.clone(),slash)?,
+					Rule::param_exp => super::param::expand_param_exp(sub.clone(),slash)?,
+					Rule::cmd_sub => super::cmdsub::expand_cmd_sub(sub.clone(),slash)?,
+					Rule::arr_index => super::index::expand_index(sub.clone(),slash)?,
+					Rule::arr_len => super::index::expand_len(sub.clone(),slash)?,
+					Rule::var_sub => super::dispatch::expand_var(&sub.as_str()[1..], slash)?,
+					_ => super::dispatch::expand_param(&sub.as_str()[1..], slash)?,
+				};
+				result.push_str(&expanded);
+				let end = i + sub.as_str().len();
+				while chars.next_if(|(j,_)| *j < end).is_some() {}
+			}
+			_ => result.push(ch)
+		}
+	}
+	Ok(result)
+}
@@ -4,8 +4,8 @@ use std::os::unix::fs::PermissionsExt;
 use io::Read;
 use nix::unistd::getpgrp;
 
-use crate::{expand, prelude::*, utils};
-use crate::{utils::REGEX, error::{SlashErr, SlashErrHigh, SlashErrLow}, shellenv::{self, attach_tty, disable_reaping, enable_reaping, write_jobs, DisplayWaitStatus, HashFloat, Job, Slash, SlashVal}, SlashResult};
+use crate::{ast::{Block, Command, Compound, LoopKind, Word, WordKind}, expand, prelude::*, utils};
+use crate::{utils::REGEX, error::{SlashErr, SlashErrHigh, SlashErrLow}, shellenv::{self, attach_tty, EnvFlags, write_jobs, DisplayWaitStatus, HashFloat, Job, Slash, SlashVal}, SlashResult};
 
 
 #[macro_export]
@@ -402,14 +402,22 @@ pub fn validate_autocd(slash: &mut Slash,argv: &VecDeque<String>) -> SlashResult
 	}
 }
 
-pub fn try_expansion<'a>(slash: &mut Slash,pair: Pair<'a,Rule>) -> SlashResult<String> {
-	if pair.contains_rules(&[Rule::expand_word,Rule::dquoted][..]) {
-		expand::dispatch::expand_word(pair,slash)
+pub fn try_expansion(slash: &mut Slash,word: &Word) -> SlashResult<String> {
+	if word.expand {
+		expand::dispatch::expand_word(word.as_str(),slash)
 	} else {
-		Ok(pair.as_str().to_string())
+		Ok(word.as_str().to_string())
 	}
 }
 
+/// Gets the value of the variable that a word refers to, if the word is nothing but `$name` or `${name}`
+/// Used by constructs that care about the type of a value, since expanding it would turn it into a string
+pub fn word_var(word: &str, slash: &Slash) -> Option<SlashVal> {
+	let name = word.strip_prefix('$')?;
+	let name = name.strip_prefix('{').and_then(|name| name.strip_suffix('}')).unwrap_or(name);
+	slash.vars().get_var(name)
+}
+
 pub fn try_glob(words: VecDeque<String>) -> VecDeque<String> {
 	let mut globs = VecDeque::new();
 	for word in &words {
@@ -452,61 +460,70 @@ pub fn try_brace(word: &str) -> VecDeque<String> {
 	unpacked
 }
 
-pub fn prepare_argv<'a>(pair: Pair<'a,Rule>,slash: &mut Slash) -> SlashResult<VecDeque<String>> {
+pub fn prepare_argv<'a>(words: impl IntoIterator<Item = &'a Word>,slash: &mut Slash) -> SlashResult<VecDeque<String>> {
 	let mut args = VecDeque::new();
-	let mut inner = pair.into_inner().filter(|pr| matches!(pr.as_rule(), Rule::cmd_name | Rule::arg_assign | Rule::word));
-	while let Some(pair) = inner.next() {
-		let word = pair.as_str().trim_quotes().to_string();
-		let expanded = VecDeque::from(vec![try_expansion(slash,pair)?]);
-		let expanded_ext = try_glob(expanded.clone());
-		let expanded_ext = try_tilde(expanded_ext);
-		if !expanded_ext.is_empty() {
-			for word in expanded_ext {
-				args.push_back(word.trim_quotes());
+	let no_glob = slash.meta().flags().contains(EnvFlags::NO_GLOB);
+	for word in words {
+		if word.expand {
+			// `$arr[@]` becomes one argument per element
+			if let Some(elements) = expand::index::splat_word(word.as_str(), slash)? {
+				args.extend(elements);
+				continue
 			}
-		} else {
-			for word in expanded {
-				args.push_back(word.trim_quotes());
+		}
+		let mut fields = VecDeque::new();
+		for field in expand::split::expand_fields(word, slash)? {
+			let globbed = if no_glob { VecDeque::new() } else { try_glob(VecDeque::from(vec![field.pattern.clone()])) };
+			match globbed.front() {
+				// Patterns without any glob characters come back unchanged, and ones that match nothing come back empty
+				Some(first) if globbed.len() > 1 || *first != field.pattern => fields.extend(globbed),
+				_ => fields.push_back(field.text)
 			}
 		}
+		// Only a literal tilde is expanded, not one that is quoted or came from an expansion
+		if word.as_str().starts_with('~') || matches!(word.kind, WordKind::Assign { .. }) {
+			fields = try_tilde(fields);
+		}
+		args.extend(fields);
 	}
 	Ok(args)
 }
 
-pub fn get_pipeline_cmd<'a>(pair: Pair<'a,Rule>) -> SlashResult<String> {
-	Ok(match pair.as_rule() {
-		Rule::simple_cmd => {
-			let mut argv = pair.into_inner();
-			let cmd = argv.next().unwrap();
-			cmd.as_str().to_string()
-		}
-		Rule::shell_cmd => {
-			let shell_cmd = pair.step(1).unpack()?;
-			match shell_cmd.as_rule() {
-				Rule::for_cmd => "for".into(),
-				Rule::if_cmd => "if".into(),
-				Rule::match_cmd => "match".into(),
-				Rule::loop_cmd => {
-					let mut inner = shell_cmd.into_inner();
-					let loop_kind = inner.next().unpack()?.as_str();
-					loop_kind.into()
+/// Prints a command to stderr before it runs, under `set -x`
+pub fn trace_cmd(cmd: &str, slash: &Slash) {
+	if slash.meta().flags().contains(EnvFlags::STACK_TRACE) {
+		let prefix = slash.vars().get_var("PS4").map(|ps4| ps4.to_string()).unwrap_or("+ ".into());
+		eprintln!("{}{}",prefix,cmd);
+	}
+}
+
+pub fn get_pipeline_cmd(cmd: &Command) -> SlashResult<String> {
+	Ok(match cmd {
+		Command::Simple(cmd) => cmd.name().unwrap_or_default().to_string(),
+		Command::Shell(shell_cmd) => {
+			match &shell_cmd.kind {
+				Compound::For(_) => "for".into(),
+				Compound::If(_) => "if".into(),
+				Compound::Select(_) => "select".into(),
+				Compound::Match(_) => "match".into(),
+				Compound::Case(_) => "case".into(),
+				Compound::Loop(loop_cmd) => {
+					match loop_cmd.kind {
+						LoopKind::While => "while".into(),
+						LoopKind::Until => "until".into()
+					}
 				}
-				Rule::subshell => "anonymous subshell".into(),
-				_ => todo!("shell cmd kind '{:?}'", shell_cmd.as_rule())
+				Compound::Subshell(_) => "anonymous subshell".into(),
+				Compound::BraceGrp(_) => "brace group".into(),
+				_ => todo!("shell cmd kind '{:?}'", shell_cmd.kind)
 			}
 		}
 		_ => unreachable!()
 	})
 }
 
-pub fn prepare_redirs<'a>(pair: Pair<'a,Rule>) -> SlashResult<VecDeque<utils::Redir>> {
-	let mut results = pair.filter(Rule::redir).into_iter().map(|pr| utils::Redir::from_pair(pr)).collect::<VecDeque<_>>();
-	let mut redirs = VecDeque::new();
-	while let Some(result) = results.pop_front() {
-		let extracted = result?;
-		redirs.push_back(extracted);
-	}
-	Ok(redirs)
+pub fn prepare_redirs(redirs: &[utils::Redir]) -> VecDeque<utils::Redir> {
+	redirs.iter().cloned().collect()
 }
 
 pub fn handle_nested(open: &str, close: &str, haystack: &mut VecDeque<char>) -> String {
@@ -715,7 +732,7 @@ pub fn write_alias(slash: &mut Slash,alias: &str, body: &str) -> SlashResult<()>
 	Ok(())
 }
 
-pub fn write_func(slash: &mut Slash,func: &str, body: &str) -> SlashResult<()> {
+pub fn write_func(slash: &mut Slash,func: &str, body: Arc<Block>) -> SlashResult<()> {
 	if slash.logic().get_alias(func).is_some() {
 		slash.logic_mut().remove_alias(func);
 	}
@@ -725,7 +742,7 @@ pub fn write_func(slash: &mut Slash,func: &str, body: &str) -> SlashResult<()> {
 
 pub fn unset_var_conflicts(slash: &mut Slash,key: &str) -> SlashResult<()> {
 	if slash.vars().get_var(key).is_some() {
-		slash.vars_mut().unset_var(key)
+		slash.vars_mut().unset_var(key)?;
 	}
 	if slash.vars().get_evar(key).is_some() {
 		std::env::remove_var(key);
@@ -735,34 +752,42 @@ pub fn unset_var_conflicts(slash: &mut Slash,key: &str) -> SlashResult<()> {
 	Ok(())
 }
 
-pub fn handle_fg(slash: &mut Slash, job: Job) -> SlashResult<()> {
-	let mut code = 0;
+/// Waits on a foreground job, and sets the exit status to the status of its last process
+/// The status of each process in the job is returned, and stored in `PIPESTATUS`
+pub fn handle_fg(slash: &mut Slash, job: Job) -> SlashResult<Vec<i32>> {
+	let mut codes = vec![];
 	attach_tty(job.pgid())?;
-	disable_reaping();
 	let statuses = write_jobs(|j| j.new_fg(job))??;
 	for status in statuses {
 		match status {
 			WaitStatus::Exited(_, exit_code) => {
-				code = exit_code;
+				codes.push(exit_code);
 			}
 			WaitStatus::Stopped(pid, sig) => {
 				crate::signal::handle_child_stop(pid, sig)?;
-				code = utils::SIG_EXIT_OFFSET + sig as i32;
+				codes.push(utils::SIG_EXIT_OFFSET + sig as i32);
 			},
 			WaitStatus::Signaled(pid, sig, _) => {
 				crate::signal::handle_child_signal(pid, sig)?;
-				code = utils::SIG_EXIT_OFFSET + sig as i32;
+				codes.push(utils::SIG_EXIT_OFFSET + sig as i32);
 			},
 			_ => { /* Do nothing */ }
 		}
 	}
 	attach_tty(getpgrp())?;
-	slash.set_code(code);
+	set_pipe_status(slash, &codes)?;
 	write_jobs(|j| {
 		j.update_job_statuses().unwrap();
 		j.reset_fg();
 	})?;
-	enable_reaping()
+	Ok(codes)
+}
+
+/// The last status becomes `$?`, and all of them go in `PIPESTATUS`
+pub fn set_pipe_status(slash: &mut Slash, codes: &[i32]) -> SlashResult<()> {
+	slash.set_code(codes.last().copied().unwrap_or(0));
+	let pipestatus = codes.iter().map(|code| SlashVal::Int((*code).into())).collect();
+	slash.vars_mut().set_var("PIPESTATUS", SlashVal::Array(pipestatus))
 }
 
 pub fn extract_return<T>(result: &SlashResult<T>) -> SlashResult<i32> {
@@ -770,17 +795,13 @@ pub fn extract_return<T>(result: &SlashResult<T>) -> SlashResult<i32> {
 		Ok(_) => Ok(0),
 		Err(err) => match err {
 			High(ref high) => match *high.get_err() {
-				SlashErrLow::FuncReturn(code) |
-				SlashErrLow::LoopBreak(code) => {
-					Ok(code)
-				}
+				SlashErrLow::FuncReturn(code) => Ok(code),
+				SlashErrLow::LoopBreak(_) => Ok(0),
 				_ => Err(err.clone())
 			}
 			Low(ref low) => match low {
-				SlashErrLow::FuncReturn(code) |
-				SlashErrLow::LoopBreak(code) => {
-					Ok(*code)
-				}
+				SlashErrLow::FuncReturn(code) => Ok(*code),
+				SlashErrLow::LoopBreak(_) => Ok(0),
 				_ => Err(err.clone())
 			}
 		}
@@ -1399,20 +1420,11 @@ pub fn subtract_vars<'a>(left: SlashVal, right: SlashVal) -> SlashResult<SlashVa
 				Ok(SlashVal::String(left_str))
 			}
 		}
-		SlashVal::Int(left_num) => {
-			if let SlashVal::Int(right_num) = right {
-				let diff = left_num - right_num;
-				Ok(SlashVal::Int(diff))
-			} else {
-				Err(SlashErr::Low(SlashErrLow::Parse(format!("Tried to subtract non-integer type '{}' from integer", right.fmt_type()))))
-			}
-		}
-		SlashVal::Float(left_num) => {
-			if let SlashVal::Float(right_num) = right {
-				let diff = left_num.to_f64() - right_num.to_f64();
-				Ok(SlashVal::Float(HashFloat::from_f64(diff)))
+		SlashVal::Int(_) | SlashVal::Float(_) => {
+			if matches!(right, SlashVal::Int(_) | SlashVal::Float(_)) {
+				expand::arithmetic::apply_op("-", left, right)
 			} else {
-				Err(SlashErr::Low(SlashErrLow::Parse(format!("Tried to subtract non-float type '{}' from float", right.fmt_type()))))
+				Err(SlashErr::Low(SlashErrLow::Parse(format!("Tried to subtract non-numeric type '{}' from {}", right.fmt_type(), left.fmt_type()))))
 			}
 		}
 		SlashVal::Bool(left_bool) => {
@@ -1432,7 +1444,11 @@ pub fn subtract_vars<'a>(left: SlashVal, right: SlashVal) -> SlashResult<SlashVa
 				Ok(SlashVal::Array(vec))
 			}
 		}
-		SlashVal::Dict(_) => todo!(),
+		SlashVal::Dict(mut map) => {
+			map.remove(&right.to_string());
+			Ok(SlashVal::Dict(map))
+		}
+		SlashVal::Null => Ok(SlashVal::Null)
 	}
 }
 
@@ -1455,8 +1471,8 @@ pub fn proc_res<T>(result: SlashResult<T>, pair: Pair<Rule>) -> SlashResult<T> {
 	}
 }
 
-pub fn build_slash_err<R: pest::RuleType>(pair: Pair<R>, message: String) -> String {
-	pest::error::Error::<R>::new_from_span(pest::error::ErrorVariant::CustomError { message }, pair.as_span()).to_string()
+pub fn build_slash_err<R: pest::RuleType>(span: Span, message: String) -> String {
+	pest::error::Error::<R>::new_from_span(pest::error::ErrorVariant::CustomError { message }, span).to_string()
 }
 
 pub fn add_vars<'a>(left: SlashVal, right: SlashVal) -> SlashResult<SlashVal> {
@@ -1466,20 +1482,11 @@ pub fn add_vars<'a>(left: SlashVal, right: SlashVal) -> SlashResult<SlashVal> {
 			let right_string = right.to_string();
 			Ok(SlashVal::String(format!("{}{}",left_string,right_string)))
 		}
-		SlashVal::Int(left_num) => {
-			if let SlashVal::Int(right_num) = right {
-				let sum = left_num + right_num;
-				Ok(SlashVal::Int(sum))
+		SlashVal::Int(_) | SlashVal::Float(_) => {
+			if matches!(right, SlashVal::Int(_) | SlashVal::Float(_)) {
+				expand::arithmetic::apply_op("+", left, right)
 			} else {
-				Err(SlashErr::Low(SlashErrLow::Parse(format!("Tried to add non-integer type '{}' from integer", right.fmt_type()))))
-			}
-		}
-		SlashVal::Float(left_num) => {
-			if let SlashVal::Float(right_num) = right {
-				let sum = left_num.to_f64() + right_num.to_f64();
-				Ok(SlashVal::Float(HashFloat::from_f64(sum)))
-			} else {
-				Err(SlashErr::Low(SlashErrLow::Parse(format!("Tried to add non-float type '{}' from float", right.fmt_type()))))
+				Err(SlashErr::Low(SlashErrLow::Parse(format!("Tried to add non-numeric type '{}' to {}", right.fmt_type(), left.fmt_type()))))
 			}
 		}
 		SlashVal::Bool(left_bool) => {
@@ -1490,11 +1497,22 @@ pub fn add_vars<'a>(left: SlashVal, right: SlashVal) -> SlashResult<SlashVal> {
 				Err(SlashErr::Low(SlashErrLow::Parse(format!("Tried to add non-bool type '{}' from bool", right.fmt_type()))))
 			}
 		}
-		SlashVal::Array(vec) => {
-			let mut new_vec = vec.clone();
-			new_vec.push(right);
-			Ok(SlashVal::Array(new_vec))
+		SlashVal::Array(mut vec) => {
+			// Adding an array appends each of its elements
+			match right {
+				SlashVal::Array(right_vec) => vec.extend(right_vec),
+				_ => vec.push(right)
+			}
+			Ok(SlashVal::Array(vec))
+		}
+		SlashVal::Dict(mut map) => {
+			if let SlashVal::Dict(right_map) = right {
+				map.extend(right_map);
+				Ok(SlashVal::Dict(map))
+			} else {
+				Err(SlashErr::Low(SlashErrLow::Parse(format!("Tried to add non-dict type '{}' to dict", right.fmt_type()))))
+			}
 		}
-		SlashVal::Dict(btree_map) => todo!(),
+		SlashVal::Null => Ok(right)
 	}
 }
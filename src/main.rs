@@ -4,7 +4,7 @@ use clap::{ArgAction, Parser as ClapParser};
 use error::{SlashErr, SlashErrExt, SlashErrLow, SlashResult};
 use execute::dispatch;
 use nix::{sys::termios::{self, LocalFlags, Termios}, unistd::isatty};
-use shellenv::Slash;
+use shellenv::{EnvFlags, Slash};
 
 pub mod prompt;
 pub mod execute;
@@ -19,6 +19,7 @@ pub mod prelude;
 pub mod utils;
 pub mod script;
 pub mod pest_ext;
+pub mod ast;
 
 
 #[derive(Debug,ClapParser)]
@@ -63,22 +64,49 @@ fn restore_termios(orig: &Option<Termios>) {
 	}
 }
 
+/// Runs the EXIT trap, if one is set, and then exits
+/// Calling `exit` from inside of the trap overrides the exit code
+fn exit_shell(slash: &mut Slash, mut code: i32, termios: &Option<Termios>) -> ! {
+	if let Err(e) = signal::run_trap(slash, signal::TrapTarget::Exit) {
+		let low = match &e {
+			SlashErr::Low(low) => low,
+			SlashErr::High(high) => high.get_err()
+		};
+		match low {
+			SlashErrLow::CleanExit(new_code) => code = *new_code,
+			_ => eprintln!("{}",e)
+		}
+	}
+	restore_termios(termios);
+	std::process::exit(code)
+}
+
 fn main() {
 
 	let mut slash = Slash::new(); // The shell environment
+	slash.meta_mut().mod_flags(|flags| *flags |= EnvFlags::INTERACTIVE);
 
 	let args = SlashArgs::parse();
 	if args.no_rc {
-		slash.vars_mut().export_var("PS1", "$> ");
+		slash.vars_mut().export_var("PS1", "$> ").catch();
 	}
 
 	if !args.no_rc {
 		slash.source_rc(args.rc_path).catch();
 	}
 
+	signal::sig_handler_setup();
 	let termios = set_termios();
 	loop {
-		let input = prompt::prompt::run_prompt(&mut slash).catch().unwrap_or_default();
+		// Background jobs that finished while the last command ran are reported before the next prompt
+		match signal::run_pending_traps(&mut slash) {
+			Err(SlashErr::Low(SlashErrLow::CleanExit(code))) => exit_shell(&mut slash, code, &termios),
+			result => { result.catch(); }
+		}
+		let input = match prompt::prompt::run_prompt(&mut slash) {
+			Err(SlashErr::Low(SlashErrLow::CleanExit(code))) => exit_shell(&mut slash, code, &termios),
+			result => result.catch().unwrap_or_default()
+		};
 
 		slash.start_timer();
 		slash.ctx_mut().push_state().catch();
@@ -94,13 +122,11 @@ fn main() {
 			Err(e) => {
 				match e {
 					SlashErr::Low(SlashErrLow::CleanExit(code)) => {
-						restore_termios(&termios);
-						std::process::exit(code)
+						exit_shell(&mut slash, code, &termios)
 					}
 					SlashErr::High(ref high) => {
 						if let SlashErrLow::CleanExit(code) = high.get_err() {
-							restore_termios(&termios);
-							std::process::exit(*code)
+							exit_shell(&mut slash, *code, &termios)
 						} else {
 							eprintln!("{}",e)
 						}
@@ -146,7 +146,7 @@ brace_expand      = @{ "{" ~ (alpha_range_upper | alpha_range_lower | num_range
 path_seg          = @{ path_root | path_rel }
 path_root         =  { ("/" ~ ident)+ }
 path_rel          =  { (ident ~ "/")+ }
-reserved          =  @{ ("if" | "for" | "while" | "do" | "done" | "fi" | "in" | "select" | "match") ~ word_bound }
+reserved          =  @{ ("if" | "for" | "while" | "do" | "done" | "fi" | "in" | "select" | "match" | "case" | "esac" | "try" | "except") ~ word_bound }
 
 // in case you need to explicitly mark where a word ends
 // necessary with shell constructs, for some reason
@@ -162,25 +162,34 @@ glob_word       = @{ ident? ~ glob_pat+ ~ ident? }
 tilde_sub       = @{ pwd | prev_pwd | (home ~ path_seg?) }
 brace_word      = @{ ident? ~ brace_expand+ ~ ident? }
 var_sub         = @{ !"\\$" ~ "$" ~ var_ident }
-index           =  { ASCII_DIGIT+ }
-slice           = ${ index ~ ".." ~ index }
-key             =  { dquoted | squoted }
-arr_index       = @{ !"\\$" ~ "$" ~ var_ident ~ ("[" ~ (key | slice | index) ~ "]")+ }
+index           =  { "-"? ~ ASCII_DIGIT+ }
+slice           = ${ index? ~ ".." ~ index? }
+splat           =  { "@" }
+key             = @{ dquoted | squoted }
+arr_index       = ${ !"\\$" ~ "$" ~ var_ident ~ ("[" ~ (key | slice | index | splat) ~ "]")+ }
+arr_len         = ${ !"\\$" ~ "$#" ~ var_ident }
 cmd_sub         = @{ !"\\$" ~ "$(" ~ subsh_body ~ ")" }
+arith_sub       = @{ !"\\$" ~ "$((" ~ arith_body ~ "))" }
+param_exp       = @{ !"\\$" ~ "${" ~ param_exp_body ~ "}" }
+param_exp_body  = @{ (("{" ~ param_exp_body ~ "}") | (!("{" | "}") ~ ANY))* }
 param_sub       = @{ !"\\$" ~ "$" ~ parameter }
 expansion       =  {
     tilde_sub
   | brace_word
-  | var_sub
+  | param_exp
   | arr_index
+  | arr_len
+  | var_sub
+  | arith_sub
   | cmd_sub
+  | proc_sub
   | param_sub
 }
 
 // A silent version for the first pass
 // A loud version for extracting the exact expansion types
 expand_word = @{ (ident? ~ expansion+ ~ ident?)+ }
-expand_word_loud = { ((ident? ~ NEWLINE*) ~ expansion+ ~ (NEWLINE* ~ ident?))+ }
+expand_word_loud = { ((ident_raw? ~ NEWLINE*) ~ expansion+ ~ (NEWLINE* ~ ident_raw?))+ }
 
 // Types of generic words
 dqt      = _{ "\"" }
@@ -196,30 +205,51 @@ squoted            =  { sqt ~ squote_body ~ sqt }
 var_ident_plain    = @{ NEWLINE* ~ !parameter ~ ASCII_ALPHA ~ (ASCII_ALPHANUMERIC | "." | "_")* }
 var_ident_brackets = @{ !"\\{" ~ "{" ~ var_ident_plain ~ !"\\}" ~ "}" }
 var_ident          =  { var_ident_brackets | var_ident_plain }
+// A quote only belongs to an ident if it doesn't start a quoted string, so that the quoted string can be joined onto the word instead
 ident              = _{
 	"[" |
     "]" |
     (("\\" ~ ANY) | // 'out' and 'in' refer to redir operators '>' and '<'
+  	(!out ~ !in ~ ASCII_ALPHANUMERIC | (!dquoted ~ "\"") | (!squoted ~ "'") | "[" | "]" | "*" | "?" | "_" | "-" | "!" | "%" | "+" | "=" | "\\" | "/" | "," | "." | ":" | "@"))+
+}
+// Takes quotes as plain characters, for finding the expansions in a word that has already been parsed
+ident_raw          = _{
+	"[" |
+    "]" |
+    (("\\" ~ ANY) |
   	(!out ~ !in ~ ASCII_ALPHANUMERIC | "\"" | "'" | "[" | "]" | "*" | "?" | "_" | "-" | "!" | "%" | "+" | "=" | "\\" | "/" | "," | "." | ":" | "@"))+
 }
 cmd_name           = @{ word }
+// Quoted and unquoted pieces with nothing between them make up a single word, like `"$HOME"/bin`
 word               = ${
-    dquoted
+	(dquoted
   | squoted
   | expand_word
-  | ident
+  | ident)+
 }
-array_elem         =  { array | (("\\," | "\\]" | "\\[") | !("[" | "]" | ",") ~ ANY)+ }
+array_elem         =  { array | dict | (("\\," | "\\]" | "\\[") | !("[" | "]" | ",") ~ ANY)+ }
 array              =  { "[" ~ (array_elem ~ ("," ~ array_elem)*)? ~ "]" }
+dict_key           = @{ dquoted | squoted | (ASCII_ALPHANUMERIC | "_" | "-" | ".")+ }
+dict_scalar        = @{ dquoted | squoted | (("\\" ~ ANY) | !("," | "{" | "}" | "[" | "]" | WHITESPACE | NEWLINE) ~ ANY)+ }
+dict_val           =  { dict | array | dict_scalar }
+dict_entry         =  { dict_key ~ ":" ~ dict_val }
+dict               =  { "{" ~ NEWLINE* ~ (dict_entry ~ (NEWLINE* ~ "," ~ NEWLINE* ~ dict_entry)*)? ~ NEWLINE* ~ ","? ~ NEWLINE* ~ "}" }
 word_list          =  { word ~ (NEWLINE* ~ word)* }
-std_assign         =  { var_ident ~ "=" ~ word? ~ (!sep ~ cmd_list)? }
-plus_assign        =  { var_ident ~ "+=" ~ word? ~ (!sep ~ cmd_list)? }
+index_target       = ${ var_ident ~ ("[" ~ (key | index) ~ "]")+ }
+std_assign         =  { (index_target | var_ident) ~ "=" ~ (array | word)? ~ (!sep ~ cmd_list)? }
+plus_assign        =  { var_ident ~ "+=" ~ (array | word)? ~ (!sep ~ cmd_list)? }
 increment          =  @{ var_ident ~ "++" ~ (!sep ~ WHITESPACE+ ~ cmd_list)? }
 decrement          =  @{ var_ident ~ "--" ~ (!sep ~ WHITESPACE+ ~ cmd_list)? }
 minus_assign       =  { var_ident ~ "-=" ~ word? ~ (!sep ~ cmd_list)? }
-assignment         =  { increment | decrement | std_assign | plus_assign | minus_assign }
-arg_assign         =  { var_ident ~ "=" ~ (array|word)? }
-sep                = _{ (";" | NEWLINE)+ }
+times_assign       =  { var_ident ~ "*=" ~ word? ~ (!sep ~ cmd_list)? }
+div_assign         =  { var_ident ~ "/=" ~ word? ~ (!sep ~ cmd_list)? }
+mod_assign         =  { var_ident ~ "%=" ~ word? ~ (!sep ~ cmd_list)? }
+pow_assign         =  { var_ident ~ "**=" ~ word? ~ (!sep ~ cmd_list)? }
+assignment         =  { increment | decrement | std_assign | plus_assign | minus_assign | times_assign | div_assign | mod_assign | pow_assign }
+// The name has to be right up against the `=`, so that arguments like `a == b` stay separate words
+arg_assign         =  { &arg_assign_head ~ var_ident ~ "=" ~ (dict|array|word)? }
+arg_assign_head    = @{ (var_ident_brackets | var_ident_plain) ~ "=" }
+sep                = _{ (";" | heredoc_body | NEWLINE)+ }
 
 // Types of commands
 cmd_list   =  { (bg_cmd | expr) ~ (#op = op ~ (bg_cmd | expr))* }
@@ -228,7 +258,7 @@ bg_cmd     =  { expr ~ !"&&" ~ "&" ~ word_bound }
 pipeline   =  { (shell_cmd | simple_cmd) ~ ("|" ~ (shell_cmd | simple_cmd))+ }
 expr       = _{ pipeline | shell_cmd | assignment | simple_cmd }
 shell_cmd  =  {
-    (for_cmd | match_cmd | loop_cmd | if_cmd | subshell | brace_grp | assignment | func_def) ~ redir*
+    (for_cmd | select_cmd | match_cmd | case_cmd | loop_cmd | if_cmd | try_cmd | arith_cmd | subshell | brace_grp | assignment | func_def) ~ redir*
 }
 
 
@@ -247,21 +277,142 @@ loop_body = { (!"done" ~ cmd_list ~ sep)+ }
 loop_kind = { "while" | "until" }
 loop_cmd  = { loop_kind ~ NEWLINE* ~ loop_cond ~ sep ~ "do" ~ NEWLINE* ~ loop_body ~ "done" }
 
-for_vars = { (!"in" ~ word ~ NEWLINE*)+ }
-for_arr  = { (word ~ NEWLINE*)+ }
-for_cmd  = { "for" ~ NEWLINE* ~ for_vars ~ in ~ NEWLINE* ~ for_arr+ ~ sep ~ "do" ~ NEWLINE* ~ loop_body ~ NEWLINE* ~ "done" ~ word_bound }
+for_var        = @{ (ASCII_ALPHA | "_") ~ (ASCII_ALPHANUMERIC | "_")* }
+for_vars       =  { (!(&reserved ~ "in") ~ for_var ~ ","?)+ }
+for_arr        =  { word* }
+// A range like `0..10` or `0..=10 step 2`, where the bounds can also be variables
+for_bound      = @{ ("-"? ~ ASCII_DIGIT+) | ("$"? ~ (ASCII_ALPHA | "_") ~ (ASCII_ALPHANUMERIC | "_")*) }
+for_range_op   =  { "..=" | ".." }
+for_range      = ${ for_bound ~ for_range_op ~ for_bound ~ (WHITESPACE+ ~ "step" ~ WHITESPACE+ ~ for_bound)? }
+// `for ((init; cond; step))`
+for_arith_part = @{ (("(" ~ arith_body ~ ")") | (!(";" | "(" | ")") ~ ANY))* }
+for_arith      = ${ "((" ~ for_arith_part ~ ";" ~ for_arith_part ~ ";" ~ for_arith_part ~ "))" }
+for_cmd        =  {
+		"for" ~ NEWLINE* ~ (for_arith | (for_vars ~ &reserved ~ "in" ~ ((for_range ~ &(";" | NEWLINE)) | for_arr))) ~ sep? ~ "do" ~ NEWLINE* ~ loop_body ~ NEWLINE* ~ "done" ~ word_bound
+}
+
+// Without an `in` list, select offers the positional parameters
+select_arr = { word* }
+select_cmd = {
+		"select" ~ NEWLINE* ~ var_ident_plain ~ (&reserved ~ "in" ~ select_arr)? ~ sep ~ "do" ~ NEWLINE* ~ loop_body ~ NEWLINE* ~ "done" ~ word_bound
+}
 
-match_pat  = { (!"=>" ~ word)+ }
+// An arm can match a glob, a regex like `r"^v[0-9]+"`, or the type of the subject like `<int>`, and `|` separates alternatives
+match_type  = @{ "<" ~ ("int" | "float" | "string" | "bool" | "array" | "dict") ~ ">" }
+match_regex = ${ "r" ~ (dquoted | squoted) }
+match_glob  =  { (!"=>" ~ word)+ }
+match_alt   =  { match_type | match_regex | match_glob }
+match_pat   =  { match_alt ~ ("|" ~ match_alt)* }
 match_body = { (brace_grp ~ ","? | (!"," ~ ANY)+ ~ ",") }
 match_arm  = { match_pat ~ "=>" ~ NEWLINE* ~ match_body }
 match_cmd  = {
-		"match" ~ NEWLINE* ~ word ~ NEWLINE* ~ in ~ NEWLINE* ~ match_arm ~ (NEWLINE* ~ match_arm)* ~ NEWLINE* ~ "done" ~ word_bound
+		"match" ~ NEWLINE* ~ word ~ NEWLINE* ~ &reserved ~ "in" ~ NEWLINE* ~ match_arm ~ (NEWLINE* ~ match_arm)* ~ NEWLINE* ~ "done" ~ word_bound
+}
+
+// `;;` ends the case, `;&` runs the next body too, and `;;&` goes on to test the next patterns
+case_term = { ";;&" | ";;" | ";&" }
+case_sep  = _{ (!case_term ~ (";" | heredoc_body | NEWLINE))+ }
+case_pats = { "("? ~ match_glob ~ ("|" ~ match_glob)* ~ ")" }
+case_body = { NEWLINE* ~ (!case_term ~ !(&reserved ~ "esac") ~ cmd_list ~ case_sep?)* }
+case_item = { case_pats ~ case_body ~ case_term? }
+case_cmd  = {
+		"case" ~ NEWLINE* ~ word ~ NEWLINE* ~ &reserved ~ "in" ~ NEWLINE* ~ (case_item ~ NEWLINE*)* ~ &reserved ~ "esac"
 }
 
 if_cmd     = { "if" ~ NEWLINE* ~ if_cond ~ sep ~ "then" ~ NEWLINE* ~ if_body ~ elif_block* ~ else_block? ~ "fi" ~ word_bound }
 elif_block = { "elif" ~ NEWLINE* ~ if_cond ~ sep ~ "then" ~ NEWLINE* ~ if_body }
 else_block = { "else" ~ NEWLINE* ~ (!("fi") ~ #else_body = cmd_list ~ sep)+ }
 
+except_kind = @{ !("as" ~ word_bound) ~ ASCII_ALPHA+ ~ word_bound }
+except_var  =  { "as" ~ var_ident_plain }
+except_arm  =  { "except" ~ except_kind? ~ except_var? ~ NEWLINE* ~ brace_grp }
+try_cmd     =  { "try" ~ NEWLINE* ~ brace_grp ~ (NEWLINE* ~ except_arm)+ }
+
+// Arithmetic
+// arith_body only has to find the end of a `$(( ))` or `(( ))`, arith_main is what actually gets evaluated
+arith_body      = @{ (("(" ~ arith_body ~ ")") | (!("(" | ")") ~ ANY))* }
+// A body that isn't arithmetic makes this a subshell that starts with a nested one instead, like `((cd dir; ls) )`
+arith_cmd       = ${ "((" ~ &arith_cmd_body ~ arith_body ~ "))" }
+arith_cmd_body  = !{ WHITESPACE* ~ arith_expr ~ ("," ~ arith_expr)* ~ "))" }
+
+arith_int       = @{ (("0x" | "0X") ~ ASCII_HEX_DIGIT+) | ASCII_DIGIT+ }
+arith_float     = @{ ASCII_DIGIT* ~ "." ~ ASCII_DIGIT+ }
+arith_var       = @{ "$"? ~ (ASCII_ALPHA | "_") ~ (ASCII_ALPHANUMERIC | "_")* }
+arith_param     = @{ "$" ~ parameter }
+arith_incdec    =  { "++" | "--" }
+arith_pre       =  { arith_incdec ~ arith_var }
+arith_post      =  { arith_var ~ arith_incdec }
+arith_assign_op = @{ "**=" | "<<=" | ">>=" | "+=" | "-=" | "*=" | "/=" | "%=" | "&=" | "^=" | "|=" | ("=" ~ !"=") }
+arith_assign    =  { arith_var ~ arith_assign_op ~ arith_expr }
+arith_group     =  { "(" ~ arith_expr ~ ")" }
+arith_primary   = _{ arith_assign | arith_pre | arith_post | arith_float | arith_int | arith_param | arith_var | arith_group }
+
+arith_neg    = @{ "-" ~ !"-" }
+arith_pos    = @{ "+" ~ !"+" }
+arith_not    = @{ "!" ~ !"=" }
+arith_bitnot = @{ "~" }
+arith_prefix = _{ arith_neg | arith_pos | arith_not | arith_bitnot }
+
+arith_pow     = { "**" }
+arith_mul     = { "*" }
+arith_div     = { "/" }
+arith_mod     = { "%" }
+arith_add     = { "+" }
+arith_minus   = { "-" }
+arith_shl     = { "<<" }
+arith_shr     = { ">>" }
+arith_le      = { "<=" }
+arith_ge      = { ">=" }
+arith_lt      = { "<" }
+arith_gt      = { ">" }
+arith_eq      = { "==" }
+arith_ne      = { "!=" }
+arith_and     = { "&&" }
+arith_or      = { "||" }
+arith_bitand  = { "&" }
+arith_bitxor  = { "^" }
+arith_bitor   = { "|" }
+arith_ternary = { "?" ~ arith_expr ~ ":" }
+arith_infix   = _{
+    arith_pow | arith_mul | arith_div | arith_mod | arith_add | arith_minus
+  | arith_shl | arith_shr | arith_le | arith_ge | arith_lt | arith_gt | arith_eq | arith_ne
+  | arith_and | arith_or | arith_bitand | arith_bitxor | arith_bitor | arith_ternary
+}
+
+arith_expr = { arith_prefix* ~ arith_primary ~ (arith_infix ~ arith_prefix* ~ arith_primary)* }
+arith_main = { SOI ~ arith_expr ~ ("," ~ arith_expr)* ~ EOI }
+
+// Parameter expansion operators
+// These are parsed from the body of a `${...}` expansion once it has been found
+param_name          = @{ ((ASCII_ALPHA | "_") ~ (ASCII_ALPHANUMERIC | "_")*) | ASCII_DIGIT+ | "@" | "*" | "?" | "$" | "!" | "-" | "#" }
+param_arg           = @{ ANY* }
+param_pat           = @{ ("\\/" | !"/" ~ ANY)* }
+param_arith         = @{ (!":" ~ ANY)+ }
+param_length        = ${ "#" ~ param_name }
+param_default       = ${ param_name ~ ":-" ~ param_arg }
+param_assign        = ${ param_name ~ ":=" ~ param_arg }
+param_error         = ${ param_name ~ ":?" ~ param_arg }
+param_alt           = ${ param_name ~ ":+" ~ param_arg }
+param_substr        = ${ param_name ~ ":" ~ param_arith ~ (":" ~ param_arith)? }
+param_trim_pre_long = ${ param_name ~ "#" ~ "#" ~ param_arg }
+param_trim_pre      = ${ param_name ~ "#" ~ param_arg }
+param_trim_suf_long = ${ param_name ~ "%%" ~ param_arg }
+param_trim_suf      = ${ param_name ~ "%" ~ param_arg }
+param_replace_all   = ${ param_name ~ "//" ~ param_pat ~ ("/" ~ param_arg)? }
+param_replace       = ${ param_name ~ "/" ~ param_pat ~ ("/" ~ param_arg)? }
+param_upper         = ${ param_name ~ "^^" }
+param_lower         = ${ param_name ~ ",," }
+param_plain         = ${ param_name }
+param_exp_main      = ${
+    SOI ~ (
+      (param_length ~ EOI)
+    | param_default | param_assign | param_error | param_alt | param_substr
+    | param_trim_pre_long | param_trim_pre | param_trim_suf_long | param_trim_suf
+    | param_replace_all | param_replace
+    | ((param_upper | param_lower | param_plain) ~ EOI)
+  ) ~ EOI
+}
+
 // Operator stuff
 and = { "&&" }
 or  = { "||" }
@@ -277,8 +428,28 @@ out        =  { ">" }
 force_out  =  { ">|" }
 in_out     =  { "<>" }
 append     =  { ">>" }
-heredoc    =  { "<<" }
+heredoc    =  { "<<-" | "<<" }
 herestring =  { "<<<" }
+
+// The delimiter of a heredoc is pushed onto the stack, so that the body can be read from the lines after the command
+// Quoting the delimiter keeps the body from being expanded
+heredoc_word  = @{ (!(WHITESPACE | NEWLINE | "'" | "\"" | ";" | "&" | "|" | "<" | ">" | "(" | ")") ~ ANY)+ }
+// Bodies are only read for one heredoc per line, since the delimiter on top of the stack is the one that ends the body
+// Any other heredoc on the same line is matched without pushing its delimiter, and refused when the AST is built
+heredoc_delim = ${
+    (!DROP ~ (
+      ("'" ~ PUSH(heredoc_word) ~ "'")
+    | ("\"" ~ PUSH(heredoc_word) ~ "\"")
+    | ("\\" ~ PUSH(heredoc_word))
+    | PUSH(heredoc_word)
+  ))
+  | heredoc_extra
+}
+heredoc_extra = @{ ("'" ~ heredoc_word ~ "'") | ("\"" ~ heredoc_word ~ "\"") | ("\\" ~ heredoc_word) | heredoc_word }
+heredoc_end  = _{ "\t"* ~ PEEK ~ &(NEWLINE | EOI) }
+heredoc_text = @{ (!heredoc_end ~ (!NEWLINE ~ ANY)* ~ NEWLINE)* }
+heredoc_body = ${ &DROP ~ NEWLINE ~ heredoc_text ~ "\t"* ~ POP ~ &(NEWLINE | EOI) }
+
 redir      =  {
     (out ~ file)
   | (in ~ file)
@@ -286,9 +457,9 @@ redir      =  {
   | (fd_out ~ out ~ file)
   | (append ~ file)
   | (fd_out ~ append ~ file)
-  | (heredoc ~ file)
   | (herestring ~ file)
-  | (fd_out ~ heredoc ~ file)
+  | (heredoc ~ heredoc_delim)
+  | (fd_out ~ heredoc ~ heredoc_delim)
   | (in ~ "&" ~ fd_target)
   | (fd_out ~ in ~ "&" ~ fd_target)
   | (out ~ "&" ~ fd_target)
@@ -411,9 +582,9 @@ hl_redir = {
   | (fd_out ~ out ~ file)
   | (append ~ file)
   | (fd_out ~ append ~ file)
-  | (heredoc ~ file)
   | (herestring ~ file)
-  | (fd_out ~ heredoc ~ file)
+  | (heredoc ~ heredoc_delim)
+  | (fd_out ~ heredoc ~ heredoc_delim)
   | (in_from_fd ~ fd_target)
   | (fd_out ~ in_from_fd ~ fd_target)
   | (out_to_fd ~ fd_target)
@@ -433,6 +604,7 @@ hl_word = ${
   | squoted
   | param_sub
   | arr_index
+  | arr_len
   | var_sub
   | cmd_sub
   | proc_sub
@@ -486,6 +658,7 @@ brace_grp = { "{" ~ sub_main ~ "}" }
 // sub_main is used for brace groups, it essentially allows for holding a script in the brace group
 // It's stored as a static string, but using sub_main makes sure that it actually parses first
 sub_main = _{ NEWLINE* ~ cmd_list? ~ (sep ~ cmd_list?)* ~ NEWLINE* }
-main     =  { SOI ~ NEWLINE* ~ cmd_list? ~ (sep ~ cmd_list?)* ~ NEWLINE* ~ EOI }
+// Any heredoc left on the stack at the end of the input is still waiting for its body
+main     =  { SOI ~ NEWLINE* ~ cmd_list? ~ (sep ~ cmd_list?)* ~ NEWLINE* ~ !DROP ~ EOI }
 "##]
 pub struct SlashParse;
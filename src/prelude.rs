@@ -1,5 +1,6 @@
 pub use std::{
 	collections::{
+		BTreeMap,
 		HashMap,
 		HashSet,
 		VecDeque
@@ -1,6 +1,5 @@
 use std::{env, path::Path};
 
-use nix::{sys::signal::{kill, Signal}, unistd::Pid};
 use rustyline::{completion::FilenameCompleter, error::ReadlineError, history::History, Helper};
 
 use crate::prelude::*;
@@ -103,8 +102,7 @@ pub fn run_prompt(slash: &mut Slash) -> SlashResult<String> {
 		}
 		Err(ReadlineError::Eof) => {
 			slash.meta_mut().leave_prompt();
-			kill(Pid::this(), Signal::SIGQUIT).map_err(|_| Low(SlashErrLow::from_io()))?;
-			Ok(String::new())
+			Err(Low(SlashErrLow::CleanExit(0)))
 		}
 		Err(e) => {
 			slash.meta_mut().leave_prompt();
@@ -1,35 +1,198 @@
-use crate::{prelude::*, shellenv::SlashVal};
+use crate::{ast::{ForCmd, ForKind, Word}, expand::arithmetic, helper, prelude::*, shellenv::SlashVal};
 
-pub fn exec_for_cmd<'a>(cmd: Pair<'a,Rule>,slash: &mut Slash) -> SlashResult<()> {
+pub fn exec_for_cmd(cmd: &ForCmd,slash: &mut Slash) -> SlashResult<()> {
+	if let ForKind::Arith { init, cond, step } = &cmd.kind {
+		return exec_arith_for(cmd, [init, cond, step], slash)
+	}
 	let mut saved_vars = HashMap::new();
-	let loop_body = cmd.scry(Rule::loop_body).unpack()?.as_str();
-	let loop_vars = cmd.scry(Rule::for_vars)
-		.unpack()?
-		.into_inner()
-		.into_iter()
+	let loop_vars = cmd.vars.iter()
 		.map(|var| var.as_str())
 		.collect::<Vec<&str>>();
-	let loop_arr = cmd.scry(Rule::for_arr)
-		.unpack()?
-		.into_inner()
-		.map(|elem| SlashVal::parse(elem.as_str()).unwrap())
-		.collect::<Vec<SlashVal>>();
 
-	let vars_len = loop_vars.len();
 	for var in &loop_vars {
 		let existing_val = slash.vars().get_var(var).unwrap_or_default();
 		saved_vars.insert(var,existing_val);
 	}
 
-	for (i,element) in loop_arr.iter().enumerate() {
-		let var_index = i % vars_len;
-		slash.vars_mut().set_var(loop_vars[var_index], element.clone());
-		slash.exec_as_body(loop_body)?;
-	}
+	// The loop variables are put back even if the loop is left early, like by `break 2`
+	let result = exec_passes(cmd, &loop_vars, slash);
 	for var in &loop_vars {
 		let saved_val = saved_vars.remove(var).unwrap_or_default();
-		slash.vars_mut().set_var(var, saved_val);
+		slash.vars_mut().set_var(var, saved_val).blame(&cmd.span)?;
+	}
+	result?;
+	slash.set_code(0);
+	Ok(())
+}
+
+fn exec_passes(cmd: &ForCmd, loop_vars: &[&str], slash: &mut Slash) -> SlashResult<()> {
+	match &cmd.kind {
+		ForKind::Words(words) => {
+			let loop_arr = expand_words(words, loop_vars.len(), slash)?;
+			// With more than one loop variable, each pass takes that many elements
+			for elements in loop_arr.chunks(loop_vars.len()) {
+				for (i,var) in loop_vars.iter().enumerate() {
+					let element = elements.get(i).cloned().unwrap_or(SlashVal::String(String::new()));
+					slash.vars_mut().set_var(var, element).blame(&cmd.span)?;
+				}
+				if !super::exec_loop_body(&cmd.body, slash)? {
+					break
+				}
+			}
+		}
+		ForKind::Range { start, end, inclusive, step } => {
+			if loop_vars.len() != 1 {
+				return Err(High(SlashErrHigh::syntax_err("Ranges in for loops take a single loop variable", &cmd.span)))
+			}
+			let start = eval_bound(start, slash).blame(&cmd.span)?;
+			let end = eval_bound(end, slash).blame(&cmd.span)?;
+			let step = match step {
+				Some(step) => eval_bound(step, slash).blame(&cmd.span)?,
+				None => 1
+			};
+			if step <= 0 {
+				return Err(High(SlashErrHigh::exec_err(format!("Expected a positive step in for loop range, got {}",step), &cmd.span)))
+			}
+			// Ranges count down if the start is past the end
+			let (step, past_end): (i64, fn(i64,i64) -> bool) = if start <= end {
+				(step, |i, end| i > end)
+			} else {
+				(-step, |i, end| i < end)
+			};
+			let mut i = start;
+			while !past_end(i, end) && (*inclusive || i != end) {
+				slash.vars_mut().set_var(loop_vars[0], SlashVal::Int(i)).blame(&cmd.span)?;
+				if !super::exec_loop_body(&cmd.body, slash)? {
+					break
+				}
+				// The range can end at the largest or smallest integer, so there may be no next value
+				match i.checked_add(step) {
+					Some(next) => i = next,
+					None => break
+				}
+			}
+		}
+		ForKind::Arith { .. } => unreachable!()
+	}
+	Ok(())
+}
+
+/// Runs `for ((init; cond; step))`, where an empty condition is always true
+fn exec_arith_for(cmd: &ForCmd, [init, cond, step]: [&String;3], slash: &mut Slash) -> SlashResult<()> {
+	if !init.trim().is_empty() {
+		arithmetic::eval(init, slash).blame(&cmd.span)?;
+	}
+	loop {
+		if !cond.trim().is_empty() && !arithmetic::is_truthy(&arithmetic::eval(cond, slash).blame(&cmd.span)?) {
+			break
+		}
+		if !super::exec_loop_body(&cmd.body, slash)? {
+			break
+		}
+		if !step.trim().is_empty() {
+			arithmetic::eval(step, slash).blame(&cmd.span)?;
+		}
 	}
 	slash.set_code(0);
 	Ok(())
 }
+
+/// Expands the words of the loop, splatting arrays into their elements
+/// A dict gives its keys to a single loop variable, or its keys and values to more than one
+fn expand_words(words: &[Word], var_count: usize, slash: &mut Slash) -> SlashResult<Vec<SlashVal>> {
+	let mut elements = vec![];
+	for word in words {
+		match helper::word_var(word.as_str(), slash) {
+			Some(SlashVal::Array(arr)) => elements.extend(arr),
+			Some(SlashVal::Dict(dict)) => {
+				for (key,val) in dict {
+					elements.push(SlashVal::String(key));
+					if var_count > 1 {
+						elements.push(val);
+					}
+				}
+			}
+			_ => {
+				for arg in helper::prepare_argv([word], slash)? {
+					elements.push(SlashVal::parse(&arg)?);
+				}
+			}
+		}
+	}
+	Ok(elements)
+}
+
+fn eval_bound(bound: &str, slash: &mut Slash) -> SlashResult<i64> {
+	match arithmetic::eval(bound, slash)? {
+		SlashVal::Int(int) => Ok(int),
+		val => Err(Low(SlashErrLow::ExecFailed(format!("Expected an integer in for loop range, got {}",val))))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::execute;
+
+	use super::*;
+
+	#[test]
+	fn test_for_words() {
+		let mut slash = Slash::new();
+		let input = "arr nums=[1,2,3]; int total=0; for n in $nums 4; do total=$((total + n)); done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("total"), Some(SlashVal::Int(10)))
+	}
+	#[test]
+	fn test_for_arith() {
+		let mut slash = Slash::new();
+		let input = "string seen=''; for ((i = 0; i < 5; i++)); do if (( i == 3 )); then continue; fi; seen=\"$seen$i\"; done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("seen"), Some(SlashVal::String("0124".into())))
+	}
+	#[test]
+	fn test_for_range_step() {
+		let mut slash = Slash::new();
+		let input = "string up=''; for i in 0..=10 step 5; do up=\"$up $i\"; done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("up"), Some(SlashVal::String(" 0 5 10".into())))
+	}
+	#[test]
+	fn test_for_range_down() {
+		let mut slash = Slash::new();
+		let input = "string down=''; int top=3; for i in $top..0; do down=\"$down $i\"; done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("down"), Some(SlashVal::String(" 3 2 1".into())))
+	}
+	#[test]
+	fn test_for_dict() {
+		let mut slash = Slash::new();
+		let mut dict = BTreeMap::new();
+		dict.insert("a".to_string(), SlashVal::Int(1));
+		dict.insert("b".to_string(), SlashVal::Int(2));
+		slash.vars_mut().set_var("pairs", SlashVal::Dict(dict)).unwrap();
+
+		let input = "string out=''; for k, v in $pairs; do out=\"$out$k=$v \"; if [ $k = b ]; then break; fi; done";
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::String("a=1 b=2 ".into())));
+	}
+	#[test]
+	fn test_for_range_to_max() {
+		let mut slash = Slash::new();
+		let input = "int count=0; for i in 9223372036854775806..=9223372036854775807; do count=$((count + 1)); done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("count"), Some(SlashVal::Int(2)))
+	}
+	#[test]
+	fn test_for_step_past_max() {
+		let mut slash = Slash::new();
+		let input = "int count=0; for i in 0..=9223372036854775807 step 9223372036854775807; do count=$((count + 1)); done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("count"), Some(SlashVal::Int(2)))
+	}
+}
@@ -1,11 +1,6 @@
-use crate::{prelude::*, shellenv::EnvFlags};
-
-pub fn exec_if_cmd<'a>(cmd: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let if_cond = cmd.scry(Rule::if_cond).unpack()?.as_str().to_string();
-	let if_body = cmd.scry(Rule::if_body).unpack()?.as_str().to_string();
-	let else_block = cmd.scry(Rule::else_block);
-	let mut elif_blocks = cmd.into_inner().filter(|pr| pr.as_rule() == Rule::elif_block).collect::<VecDeque<_>>();
+use crate::{ast::IfCmd, prelude::*, shellenv::EnvFlags};
 
+pub fn exec_if_cmd(cmd: &IfCmd, slash: &mut Slash) -> SlashResult<()> {
 	let in_pipe = slash.in_pipe();
 	if in_pipe {
 		// We are going to temporarily remove this flag here, to make sure that cond/body executions fork the process
@@ -13,27 +8,22 @@ pub fn exec_if_cmd<'a>(cmd: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()>
 		slash.meta_mut().mod_flags(|f| *f &= !EnvFlags::IN_SUB_PROC);
 	}
 
-	slash.exec_as_cond(&if_cond)?;
+	slash.exec_as_cond(&cmd.cond)?;
 	if slash.get_status() == 0 {
-		slash.exec_as_body(&if_body)?;
+		slash.exec_as_body(&cmd.body)?;
 		return Ok(())
 	}
 
-	while let Some(elif_block) = elif_blocks.pop_front() {
-		let inner = elif_block.into_inner().next().unpack()?;
-		let elif_cond = inner.scry(Rule::if_cond).unpack()?.as_str().to_string();
-		let elif_body = inner.scry(Rule::if_body).unpack()?.as_str().to_string();
-
-		slash.exec_as_cond(&elif_cond)?;
+	for (elif_cond,elif_body) in &cmd.elifs {
+		slash.exec_as_cond(elif_cond)?;
 		if slash.get_status() == 0 {
-			slash.exec_as_body(&elif_body)?;
+			slash.exec_as_body(elif_body)?;
 			return Ok(())
 		}
 	}
 
-	if let Some(else_block) = else_block {
-		let else_body = else_block.step(1).unpack()?.as_str().to_string();
-		slash.exec_as_body(&else_body)?;
+	if let Some(else_body) = &cmd.else_body {
+		slash.exec_as_body(else_body)?;
 	}
 
 	slash.set_code(0);
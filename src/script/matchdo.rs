@@ -1,24 +1,194 @@
-use crate::prelude::*;
+use regex::Regex;
 
-pub fn exec_match_cmd<'a>(cmd: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	let mut inner = cmd.into_inner();
-	let match_pat = inner.next().unpack()?;
-	let mut arms = VecDeque::new();
+use crate::{ast::{Block, CaseCmd, CaseTerm, MatchCmd, MatchPat}, expand, helper::{self, StrExtension}, prelude::*, shellenv::SlashVal};
 
-	while let Some(arm) = inner.next() {
-		arms.push_back(arm);
+/// Runs the body of the first arm with a pattern that matches the subject
+pub fn exec_match_cmd(cmd: &MatchCmd, slash: &mut Slash) -> SlashResult<()> {
+	let subject = helper::try_expansion(slash, &cmd.subject)?.trim_quotes();
+	let subject_val = subject_value(cmd.subject.as_str(), &subject, slash)?;
+
+	for arm in &cmd.arms {
+		if let Some(bindings) = any_matches(&arm.pats, &subject, &subject_val, slash).blame(&arm.span)? {
+			return exec_with_bindings(&arm.body, bindings, slash).blame(&arm.span)
+		}
 	}
+	slash.set_code(0);
+	Ok(())
+}
 
-	while let Some(arm) = arms.pop_front() {
-		let mut inner = arm.into_inner();
-		let arm_pat = inner.next().unpack()?;
-		let arm_body = inner.next().unpack()?.as_str();
+/// Runs `case ... esac`, using the same patterns as the globs in `match` arms
+/// Each item ends with `;;` to stop, `;&` to fall through into the next body, or `;;&` to keep testing patterns
+pub fn exec_case_cmd(cmd: &CaseCmd, slash: &mut Slash) -> SlashResult<()> {
+	let subject = helper::try_expansion(slash, &cmd.subject)?.trim_quotes();
+	let subject_val = subject_value(cmd.subject.as_str(), &subject, slash)?;
 
-		if arm_pat.as_str().trim() == match_pat.as_str().trim() {
-			slash.exec_as_body(arm_body)?;
-			break
+	slash.set_code(0);
+	let mut fall_through = false;
+	for item in &cmd.items {
+		let bindings = if fall_through {
+			vec![]
+		} else {
+			match any_matches(&item.pats, &subject, &subject_val, slash).blame(&item.span)? {
+				Some(bindings) => bindings,
+				None => continue
+			}
+		};
+		exec_with_bindings(&item.body, bindings, slash).blame(&item.span)?;
+		match item.term {
+			CaseTerm::Break => break,
+			CaseTerm::FallThrough => fall_through = true,
+			CaseTerm::Continue => fall_through = false
 		}
 	}
-	slash.set_code(0);
 	Ok(())
 }
+
+/// A subject that names a variable keeps the type of that variable
+/// Anything else is typed the same way that an assignment would type it
+fn subject_value(raw: &str, expanded: &str, slash: &Slash) -> SlashResult<SlashVal> {
+	match helper::word_var(raw, slash) {
+		Some(val) => Ok(val),
+		None => SlashVal::parse(expanded)
+	}
+}
+
+/// Variables bound by a matching pattern, which only exist while its body runs
+type Bindings = Vec<(String,SlashVal)>;
+
+/// Runs a body with the variables bound by its pattern, then puts back whatever those variables held before
+fn exec_with_bindings(body: &Block, bindings: Bindings, slash: &mut Slash) -> SlashResult<()> {
+	let mut saved_vars = vec![];
+	for (name,val) in bindings {
+		saved_vars.push((name.clone(), slash.vars().get_var(&name)));
+		slash.vars_mut().set_var(&name, val)?;
+	}
+	let result = slash.exec_as_body(body);
+	for (name,saved_val) in saved_vars.into_iter().rev() {
+		match saved_val {
+			Some(val) => slash.vars_mut().set_var(&name, val)?,
+			None => slash.vars_mut().unset_var(&name)?
+		}
+	}
+	result?;
+	Ok(())
+}
+
+fn any_matches(pats: &[MatchPat], subject: &str, subject_val: &SlashVal, slash: &mut Slash) -> SlashResult<Option<Bindings>> {
+	for pat in pats {
+		if let Some(bindings) = pat_matches(pat, subject, subject_val, slash)? {
+			return Ok(Some(bindings))
+		}
+	}
+	Ok(None)
+}
+
+fn pat_matches(pat: &MatchPat, subject: &str, subject_val: &SlashVal, slash: &mut Slash) -> SlashResult<Option<Bindings>> {
+	let matched = |is_match: bool| if is_match { Some(vec![]) } else { None };
+	match pat {
+		MatchPat::Wildcard => Ok(Some(vec![])),
+		MatchPat::Type(type_name) => Ok(matched(subject_val.fmt_type() == *type_name)),
+		MatchPat::Glob(words) => {
+			let mut glob = vec![];
+			for word in words {
+				let expanded = helper::try_expansion(slash, word)?;
+				if expanded.starts_with(['"', '\'']) {
+					// Quoted parts of the pattern are matched literally
+					let literal = expanded.trim_quotes();
+					glob.push(literal.chars().map(|ch| if "*?[\\".contains(ch) { format!("\\{}",ch) } else { ch.to_string() }).collect());
+				} else {
+					glob.push(expanded);
+				}
+			}
+			let glob = expand::param::glob_to_regex(&glob.join(" "), true)?;
+			Ok(matched(glob.is_match(subject)))
+		}
+		MatchPat::Regex(regex) => {
+			let regex = Regex::new(regex).map_err(|_| Low(SlashErrLow::InvalidSyntax(format!("Invalid regex in match arm: {}",regex))))?;
+			let Some(captures) = regex.captures(subject) else {
+				return Ok(None)
+			};
+			// The whole match and each group go in MATCH, and named groups also get a variable of their own
+			// Captured text is always a string, so that something like `007` keeps its leading zeros
+			let capture = |group: Option<regex::Match>| SlashVal::String(group.map(|group| group.as_str()).unwrap_or_default().to_string());
+			let groups = captures.iter().map(capture).collect();
+			let mut bindings = vec![("MATCH".to_string(), SlashVal::Array(groups))];
+			for name in regex.capture_names().flatten() {
+				bindings.push((name.to_string(), capture(captures.name(name))));
+			}
+			Ok(Some(bindings))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::execute;
+
+	use super::*;
+
+	#[test]
+	fn test_match_glob() {
+		let mut slash = Slash::new();
+		let input = "file=main.rs; match $file in\n\t*.c | *.h => string lang=c,\n\t*.rs => string lang=rust,\n\t_ => string lang=other,\ndone";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("lang"), Some(SlashVal::String("rust".into())))
+	}
+	#[test]
+	fn test_match_regex() {
+		let mut slash = Slash::new();
+		let input = "match v12.3 in\n\t'v*' => int arm=1,\n\tr\"^v(?<major>[0-9]+)\\.([0-9]+)$\" => int arm=2,\n\t_ => int arm=3,\ndone";
+
+		// A quoted pattern is matched literally, so the regex arm is the first to match
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("arm"), Some(SlashVal::Int(2)))
+	}
+	#[test]
+	fn test_match_type() {
+		let mut slash = Slash::new();
+		let input = "arr nums=[1,2]; match $nums in\n\t<int> | <string> => string kind=scalar,\n\t<array> => string kind=array,\ndone";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("kind"), Some(SlashVal::String("array".into())))
+	}
+	#[test]
+	fn test_regex_captures() {
+		let mut slash = Slash::new();
+		let pat = MatchPat::Regex(r"^v(?<major>[0-9]+)\.(true)$".into());
+		let subject = SlashVal::String("v007.true".into());
+
+		let bindings = pat_matches(&pat, "v007.true", &subject, &mut slash).unwrap().unwrap();
+		let groups = vec![SlashVal::String("v007.true".into()), SlashVal::String("007".into()), SlashVal::String("true".into())];
+		assert_eq!(bindings, vec![("MATCH".to_string(), SlashVal::Array(groups)), ("major".to_string(), SlashVal::String("007".into()))]);
+	}
+	#[test]
+	fn test_captures_scoped_to_arm() {
+		let mut slash = Slash::new();
+		let input = "major=old; match v12.3 in\n\tr\"^v(?<major>[0-9]+)\" => string seen=$major,\ndone";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("seen"), Some(SlashVal::String("12".into())));
+		assert_eq!(slash.vars().get_var("major"), Some(SlashVal::String("old".into())));
+		assert_eq!(slash.vars().get_var("MATCH"), None);
+	}
+	#[test]
+	fn test_case() {
+		let mut slash = Slash::new();
+		let input = "case \"main.rs\" in\n\t*.c|*.h) lang=c ;;\n\t(*.rs)\n\t\tlang=rust\n\t\t;;\n\t*) lang=other ;;\nesac";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("lang"), Some(SlashVal::String("rust".into())))
+	}
+	#[test]
+	fn test_case_fallthrough() {
+		let mut slash = Slash::new();
+		let input = "case abc in a*) int first=1 ;& x*) int second=1 ;;& *c) int third=1 ;; *) int fourth=1; esac";
+
+		// `;&` runs the next body without testing it, and `;;&` keeps looking for matches
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("first"), Some(SlashVal::Int(1)));
+		assert_eq!(slash.vars().get_var("second"), Some(SlashVal::Int(1)));
+		assert_eq!(slash.vars().get_var("third"), Some(SlashVal::Int(1)));
+		assert_eq!(slash.vars().get_var("fourth"), None);
+	}
+}
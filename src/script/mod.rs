@@ -3,3 +3,98 @@ pub mod ifthen;
 pub mod loopdo;
 pub mod matchdo;
 pub mod select;
+pub mod trydo;
+
+use crate::{ast::Block, prelude::*};
+
+/// Runs one pass of a loop body, and returns false if the loop should stop
+/// `break` and `continue` meant for an outer loop are passed along with one less level to go
+pub fn exec_loop_body(body: &Block, slash: &mut Slash) -> SlashResult<bool> {
+	slash.ctx_mut().enter_loop();
+	let result = slash.exec_as_body(body);
+	slash.ctx_mut().leave_loop();
+	let err = match result {
+		Ok(_) => return Ok(true),
+		Err(err) => err
+	};
+	let low_err = match &err {
+		High(high_err) => high_err.get_err(),
+		Low(low_err) => low_err
+	};
+	match low_err {
+		SlashErrLow::LoopBreak(1) | SlashErrLow::LoopCont(1) => {
+			slash.set_code(0);
+			Ok(matches!(low_err, SlashErrLow::LoopCont(_)))
+		}
+		SlashErrLow::LoopBreak(levels) => Err(Low(SlashErrLow::LoopBreak(levels - 1))),
+		SlashErrLow::LoopCont(levels) => Err(Low(SlashErrLow::LoopCont(levels - 1))),
+		_ => Err(err)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{execute, shellenv::SlashVal};
+
+	use super::*;
+
+	#[test]
+	fn test_continue_outer() {
+		let mut slash = Slash::new();
+		let input = "string out=''; for i in 1..3; do for j in 1..3; do if (( j == 2 )); then continue 2; fi; out=\"$out$i$j \"; done; done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::String("11 21 ".into())))
+	}
+	#[test]
+	fn test_break_outer() {
+		let mut slash = Slash::new();
+		let input = "string out=''; for i in 1..4; do int j=0; while (( j < 3 )); do (( j++ )); if (( i == 2 )); then break 2; fi; out=\"$out$i$j \"; done; done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::String("11 12 13 ".into())))
+	}
+	#[test]
+	fn test_break_status() {
+		let mut slash = Slash::new();
+		let input = "for i in 1..3; do for j in 1..3; do sh -c 'exit 4'; break 2; done; done; echo | break 2";
+
+		// The count only says how many loops to leave, and never becomes an exit status
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.get_status(), 0);
+		let pipestatus = vec![SlashVal::Int(0),SlashVal::Int(0)];
+		assert_eq!(slash.vars().get_var("PIPESTATUS"), Some(SlashVal::Array(pipestatus)));
+	}
+	#[test]
+	fn test_break_zero() {
+		let mut slash = Slash::new();
+		let input = "while true; do break 0; done";
+
+		assert!(execute::dispatch::exec_input(input.to_string(), &mut slash).is_err());
+	}
+	#[test]
+	fn test_break_clamped() {
+		let mut slash = Slash::new();
+		let input = "int n=0; for i in 1..3; do for j in 1..3; do n=$((n + 1)); break 5; done; done";
+
+		// A count larger than the number of loops breaks out of all of them
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("n"), Some(SlashVal::Int(1)))
+	}
+	#[test]
+	fn test_continue_clamped() {
+		let mut slash = Slash::new();
+		let input = "string out=''; for i in 1..3; do for j in 1..3; do out=\"$out$i$j \"; continue 9; done; done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("out"), Some(SlashVal::String("11 21 ".into())))
+	}
+	#[test]
+	fn test_loop_var_restored_by_outer_break() {
+		let mut slash = Slash::new();
+		let input = "i=keep; while true; do for i in 1..3; do break 2; done; done";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("i"), Some(SlashVal::String("keep".into())));
+	}
+}
@@ -1,5 +1,135 @@
-use crate::prelude::*;
+use nix::errno::Errno;
 
-pub fn exec_select_cmd<'a>(pair: Pair<'a,Rule>, slash: &mut Slash) -> SlashResult<()> {
-	todo!()
+use crate::{ast::SelectCmd, helper, prelude::*, shellenv::SlashVal, utils};
+
+/// The width used for the menu if `COLUMNS` is not set
+const DEFAULT_COLUMNS: usize = 80;
+
+/// Prints a numbered menu of words to stderr, and runs the body each time a line is read from stdin
+/// The line is stored in `REPLY`, and the variable is set to the chosen word, or left empty if the line doesn't pick one
+/// The loop ends on `break` or at the end of the input
+pub fn exec_select_cmd(cmd: &SelectCmd, slash: &mut Slash) -> SlashResult<()> {
+	let saved_fds = utils::save_fds()?;
+	let result = select_loop(cmd, slash);
+	utils::restore_fds(saved_fds, slash)?;
+	result
+}
+
+fn select_loop(cmd: &SelectCmd, slash: &mut Slash) -> SlashResult<()> {
+	// Input redirections are where the replies come from, so they are opened once for the whole loop
+	let (in_redirs,out_redirs) = slash.ctx().sort_redirs();
+	slash.ctx_mut().set_redirs(in_redirs.into());
+	slash.activate_redirs()?;
+	slash.ctx_mut().set_redirs(out_redirs.into());
+
+	let items = match &cmd.arr {
+		Some(arr) => Vec::from(helper::prepare_argv(arr, slash)?),
+		None => Vec::from(slash.vars().borrow_pos_params().clone())
+	};
+	slash.set_code(0);
+	let mut show_menu = true;
+	while !items.is_empty() {
+		if show_menu {
+			eprint!("{}",format_menu(&items, columns(slash)));
+		}
+		let prompt = slash.vars().get_var("PS3").map(|ps3| ps3.to_string()).unwrap_or("#? ".into());
+		eprint!("{}",prompt);
+		let Some(reply) = read_reply()? else {
+			eprintln!();
+			break
+		};
+		// An empty line just shows the menu again
+		show_menu = reply.trim().is_empty();
+		if show_menu {
+			continue
+		}
+
+		let choice = reply.trim().parse::<usize>().ok()
+			.and_then(|num| num.checked_sub(1))
+			.and_then(|index| items.get(index));
+		let val = match choice {
+			Some(item) => SlashVal::parse(item)?,
+			None => SlashVal::String(String::new())
+		};
+		slash.vars_mut().set_var("REPLY", SlashVal::String(reply)).blame(&cmd.span)?;
+		slash.vars_mut().set_var(&cmd.var, val).blame(&cmd.span)?;
+
+		if !super::exec_loop_body(&cmd.body, slash)? {
+			break
+		}
+	}
+	Ok(())
+}
+
+fn columns(slash: &Slash) -> usize {
+	slash.vars().get_var("COLUMNS").map(|cols| cols.to_string())
+		.or_else(|| slash.vars().get_evar("COLUMNS"))
+		.and_then(|cols| cols.parse::<usize>().ok())
+		.unwrap_or(DEFAULT_COLUMNS)
+}
+
+/// Lays the menu out in as many columns as fit in the given width, numbering the words down each column
+fn format_menu(items: &[String], width: usize) -> String {
+	let num_width = items.len().to_string().len();
+	let entries = items.iter()
+		.enumerate()
+		.map(|(i,item)| format!("{:>num_width$}) {}", i + 1, item))
+		.collect::<Vec<String>>();
+	let entry_width = entries.iter().map(|entry| entry.chars().count()).max().unwrap_or_default() + 2;
+	let cols = (width / entry_width).max(1);
+	let rows = entries.len().div_ceil(cols);
+
+	let mut menu = String::new();
+	for row in 0..rows {
+		let line = entries.iter()
+			.skip(row)
+			.step_by(rows)
+			.map(|entry| format!("{:<entry_width$}", entry))
+			.collect::<String>();
+		menu.push_str(line.trim_end());
+		menu.push('\n');
+	}
+	menu
+}
+
+/// Reads one line from stdin a byte at a time, so that nothing after the line gets used up
+/// Returns None at the end of the input
+fn read_reply() -> SlashResult<Option<String>> {
+	let mut line = vec![];
+	let mut byte = [0u8;1];
+	loop {
+		match nix::unistd::read(0, &mut byte) {
+			Ok(0) if line.is_empty() => return Ok(None),
+			Ok(0) => break,
+			Ok(_) if byte[0] == b'\n' => break,
+			Ok(_) => line.push(byte[0]),
+			Err(Errno::EINTR) => continue,
+			Err(_) => return Err(Low(SlashErrLow::from_io()))
+		}
+	}
+	Ok(Some(String::from_utf8_lossy(&line).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::execute;
+
+	use super::*;
+
+	#[test]
+	fn test_select() {
+		let mut slash = Slash::new();
+		let input = "select fruit in apple banana cherry; do string picked=$fruit; string reply=$REPLY; break; done <<< 2";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("picked"), Some(SlashVal::String("banana".into())));
+		assert_eq!(slash.vars().get_var("reply"), Some(SlashVal::String("2".into())));
+	}
+	#[test]
+	fn test_select_menu() {
+		let items = ["one","two","three","four","five"].map(String::from);
+		assert_eq!(format_menu(&items, 80), "1) one    2) two    3) three  4) four   5) five\n");
+		assert_eq!(format_menu(&items, 30), "1) one    3) three  5) five\n2) two    4) four\n");
+		assert_eq!(format_menu(&items, 10), "1) one\n2) two\n3) three\n4) four\n5) five\n");
+	}
 }
@@ -0,0 +1,81 @@
+use crate::{ast::TryCmd, error::SlashErrExt, prelude::*, shellenv::SlashVal};
+
+/// The variable that holds the caught error if an except arm doesn't name one
+const DEFAULT_ERR_VAR: &str = "err";
+
+/// Runs the body of a try block, handing any error it raises to the first except arm that catches it
+/// Errors that no arm catches are raised again, and `exit`, `return`, `break`, and `continue` are never caught
+pub fn exec_try_cmd(cmd: &TryCmd, slash: &mut Slash) -> SlashResult<()> {
+	for arm in &cmd.arms {
+		if let Some(kind) = arm.kind.as_deref().filter(|kind| !SlashErrLow::KINDS.contains(kind)) {
+			return Err(High(SlashErrHigh::syntax_err(format!("Unknown error kind in except arm: {}",kind), &arm.span)))
+		}
+	}
+
+	let err = match slash.exec_as_try(&cmd.body) {
+		Ok(_) => return Ok(()),
+		Err(High(err)) => err,
+		Err(Low(err)) => SlashErrHigh::blame(&cmd.span, err)
+	};
+	let low_err = err.get_err();
+	let Some(kind) = low_err.kind() else {
+		return Err(High(err))
+	};
+	let Some(arm) = cmd.arms.iter().find(|arm| arm.kind.as_deref().is_none_or(|arm_kind| arm_kind == kind)) else {
+		return Err(High(err))
+	};
+
+	let mut err_dict = BTreeMap::new();
+	err_dict.insert("kind".to_string(), SlashVal::String(kind.to_string()));
+	err_dict.insert("message".to_string(), SlashVal::String(low_err.to_string()));
+	err_dict.insert("code".to_string(), SlashVal::Int(low_err.code().into()));
+	err_dict.insert("line".to_string(), SlashVal::Int(err.line() as i64));
+	let var_name = arm.var.as_deref().unwrap_or(DEFAULT_ERR_VAR);
+	slash.vars_mut().set_var(var_name, SlashVal::Dict(err_dict)).blame(&arm.span)?;
+
+	slash.set_code(low_err.code());
+	slash.exec_as_body(&arm.body)?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::execute;
+
+	use super::*;
+
+	#[test]
+	fn test_try_except() {
+		let mut slash = Slash::new();
+		let input = "try { int before=1; sh -c 'exit 4'; int after=1 } except ExecFailed { kind=wrong } except { kind=caught }";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("before"), Some(SlashVal::Int(1)));
+		assert_eq!(slash.vars().get_var("after"), None);
+		assert_eq!(slash.vars().get_var("kind"), Some(SlashVal::String("caught".into())));
+		let Some(SlashVal::Dict(err)) = slash.vars().get_var("err") else { panic!() };
+		assert_eq!(err.get("kind"), Some(&SlashVal::String("ExitStatus".into())));
+		assert_eq!(err.get("code"), Some(&SlashVal::Int(4)));
+		assert_eq!(err.get("line"), Some(&SlashVal::Int(1)));
+	}
+	#[test]
+	fn test_try_except_kinds() {
+		let mut slash = Slash::new();
+		let input = "try {\n\tnot_a_real_command_123\n} except CmdNotFound as e { int caught=1 }";
+
+		execute::dispatch::exec_input(input.to_string(), &mut slash).unwrap();
+		assert_eq!(slash.vars().get_var("caught"), Some(SlashVal::Int(1)));
+		let Some(SlashVal::Dict(err)) = slash.vars().get_var("e") else { panic!() };
+		assert_eq!(err.get("code"), Some(&SlashVal::Int(127)));
+		assert_eq!(err.get("line"), Some(&SlashVal::Int(2)));
+	}
+	#[test]
+	fn test_try_uncaught() {
+		let mut slash = Slash::new();
+		let input = "try { false || true; if false; then true; fi; false } except IndexErr { int caught=1 }";
+
+		// Errors that no arm catches keep going, and neither `false || true` nor `if false` count as failures
+		assert!(execute::dispatch::exec_input(input.to_string(), &mut slash).is_err());
+		assert_eq!(slash.vars().get_var("caught"), None);
+	}
+}
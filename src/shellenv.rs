@@ -1,13 +1,12 @@
-use std::{collections::{BTreeMap, VecDeque}, env, ffi::{CString, OsStr}, fmt, hash::Hash, io::{self, Read}, mem::take, os::fd::BorrowedFd, path::{Path, PathBuf}, sync::{Arc, LazyLock}, time::{Duration, Instant}};
+use std::{cell::RefCell, collections::{BTreeMap, VecDeque}, env, ffi::{CString, OsStr}, fmt, hash::Hash, io::{self, Read}, mem::take, os::fd::BorrowedFd, path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant}};
 use std::collections::HashMap;
 
 use bitflags::bitflags;
-use nix::{sys::{signal::{kill, killpg, signal, SigHandler, SigmaskHow, Signal::{self, SIGCHLD, SIGTSTP, SIGTTIN, SIGTTOU}}, wait::{waitpid, WaitPidFlag, WaitStatus}}, unistd::{gethostname, getpgrp, isatty, setpgid, tcgetpgrp, tcsetpgrp, Pid, User}};
+use nix::{sys::{signal::{kill, killpg, SigmaskHow, Signal::{self, SIGCHLD, SIGTSTP, SIGTTIN, SIGTTOU}}, wait::{waitpid, WaitPidFlag, WaitStatus}}, unistd::{gethostname, getpgrp, isatty, setpgid, tcgetpgrp, tcsetpgrp, Pid, User}};
 use once_cell::sync::Lazy;
-use std::sync::RwLock;
 
-use crate::{execute::dispatch, prelude::*, utils::{self, Redir}};
-use crate::{error::{SlashErr::*, SlashErrLow}, helper::{self, VecDequeExtension}, shopt::ShOpts, SlashResult};
+use crate::{ast::Block, execute::dispatch, prelude::*, signal::TrapTarget, utils::{self, Redir}};
+use crate::{error::{SlashErr::*, SlashErrLow}, helper::{self, StrExtension, VecDequeExtension}, shopt::ShOpts, SlashResult};
 
 
 #[derive(Debug)]
@@ -48,13 +47,11 @@ impl fmt::Display for DisplayWaitStatus {
 
 pub static RSH_PATH: Lazy<String> = Lazy::new(|| std::env::current_exe().unwrap().to_str().unwrap().to_string());
 
-pub static JOBS: LazyLock<Arc<RwLock<JobTable>>> = LazyLock::new(|| {
-	Arc::new(
-		RwLock::new(
-			JobTable::new()
-		)
-	)
-});
+thread_local! {
+	/// The job table is only ever touched by the thread running the shell, since the signal handlers leave it alone,
+	/// so every thread gets its own. This also keeps shells running side by side in tests from seeing each other's jobs.
+	static JOBS: RefCell<JobTable> = RefCell::new(JobTable::new());
+}
 
 bitflags! {
 	#[derive(Debug,Copy,Clone,PartialEq)]
@@ -90,6 +87,7 @@ bitflags! {
 		const INHERIT_RET      = 0b00000100000000000000000000000000; // set -T
 		const SOURCING         = 0b00001000000000000000000000000000;
 		const INITIALIZED      = 0b00010000000000000000000000000000;
+		const PIPEFAIL         = 0b00100000000000000000000000000000; // set -o pipefail
 	}
 	#[derive(Debug,Copy,Clone)]
 	pub struct JobCmdFlags: i8 { // Options for the jobs builtin
@@ -100,6 +98,16 @@ bitflags! {
 		const STOPPED   = 0b00010000;
 		const INIT      = 0b00100000;
 	}
+	#[derive(Debug,Copy,Clone,PartialEq)]
+	pub struct VarFlags: u8 { // Variable attributes set by declare
+		const INTEGER   = 0b00000001; // -i
+		const ARRAY     = 0b00000010; // -a
+		const DICT      = 0b00000100; // -A
+		const READONLY  = 0b00001000; // -r
+		const EXPORT    = 0b00010000; // -x
+		const LOWER     = 0b00100000; // -l
+		const UPPER     = 0b01000000; // -u
+	}
 }
 
 #[derive(Debug,Clone)]
@@ -169,8 +177,16 @@ impl Slash {
 	}
 	pub fn consume_redirs(&mut self, redirs: VecDeque<Redir>) -> SlashResult<()> {
 		self.ctx_mut().extend_redirs(redirs);
-		self.ctx_mut().activate_redirs()?;
-		Ok(())
+		self.activate_redirs()
+	}
+	pub fn activate_redirs(&mut self) -> SlashResult<()> {
+		let mut redirs = self.ctx.take_redirs();
+		for redir in redirs.iter_mut() {
+			redir.expand(self)?;
+		}
+		let mut redirs = utils::CmdRedirs::new(redirs);
+		redirs.set_no_clobber(self.meta.flags().contains(EnvFlags::NO_OVERWRITE));
+		redirs.activate()
 	}
 	pub fn start_timer(&mut self) {
 		self.meta.timer_start = Some(Instant::now())
@@ -178,17 +194,17 @@ impl Slash {
 	pub fn stop_timer(&mut self) -> SlashResult<()> {
 		if let Some(start_time) = self.meta.timer_start {
 			self.meta.cmd_duration = Some(start_time.elapsed());
-			self.vars.export_var("OX_CMD_TIME", &self.meta.cmd_duration.unwrap().as_millis().to_string());
+			self.vars.export_var("OX_CMD_TIME", &self.meta.cmd_duration.unwrap().as_millis().to_string())?;
 		}
 		Ok(())
 	}
 
 	pub fn change_dir(&mut self, path: &Path) -> SlashResult<()> {
 		let cwd = env::var("PWD").map_err(|_| Low(SlashErrLow::from_io()))?;
-		self.vars.export_var("OLDPWD", &cwd);
+		self.vars.export_var("OLDPWD", &cwd)?;
 		env::set_current_dir(path)?;
 		let cwd = env::current_dir().map_err(|_| Low(SlashErrLow::from_io()))?;
-		self.vars.export_var("PWD", cwd.to_str().unwrap());
+		self.vars.export_var("PWD", cwd.to_str().unwrap())?;
 		Ok(())
 	}
 
@@ -286,19 +302,31 @@ impl Slash {
 
 		env_vars
 	}
-	pub fn exec_as_cond(&mut self, input: &str) -> SlashResult<i32> {
+	pub fn exec_as_cond(&mut self, block: &Block) -> SlashResult<i32> {
 		let saved = self.ctx.clone();
 		self.ctx = self.ctx.as_cond();
-		dispatch::exec_input(input.to_string(), self)?;
+		*self.ctx.flags_mut() |= utils::ExecFlags::IN_COND;
+		dispatch::exec_block(block, self)?;
 		let status = self.get_status();
 		self.ctx = saved;
 		self.set_code(status);
 		Ok(status)
 	}
-	pub fn exec_as_body(&mut self, input: &str) -> SlashResult<i32> {
+	/// Executes a block in which any failing command raises an error, instead of just setting the exit status
+	/// The context is restored even if the block fails, so that the error can be handled by the caller
+	pub fn exec_as_try(&mut self, block: &Block) -> SlashResult<i32> {
 		let saved = self.ctx.clone();
 		self.ctx = self.ctx.as_body();
-		dispatch::exec_input(input.to_string(), self)?;
+		*self.ctx.flags_mut() |= utils::ExecFlags::IN_TRY;
+		let result = dispatch::exec_block(block, self);
+		self.ctx = saved;
+		result?;
+		Ok(self.get_status())
+	}
+	pub fn exec_as_body(&mut self, block: &Block) -> SlashResult<i32> {
+		let saved = self.ctx.clone();
+		self.ctx = self.ctx.as_body();
+		dispatch::exec_block(block, self)?;
 		let status = self.get_status();
 		self.ctx = saved;
 		self.set_code(status);
@@ -311,6 +339,7 @@ pub struct ExecCtx {
 	redir_queue: VecDeque<utils::Redir>,
 	flags: utils::ExecFlags,
 	depth: usize,
+	loop_depth: usize,
 	state_stack: Vec<Box<ExecCtx>>,
 	max_recurse_depth: usize
 }
@@ -321,6 +350,7 @@ impl ExecCtx {
 			redir_queue: VecDeque::new(),
 			flags: utils::ExecFlags::empty(),
 			depth: 0,
+			loop_depth: 0,
 			state_stack: vec![], // Each alteration is local to a single layer of recursion
 			max_recurse_depth: 1000
 		}
@@ -351,8 +381,8 @@ impl ExecCtx {
 		let mut out_redirs = vec![];
 		for redir in self.redir_queue.clone() {
 			match redir.redir_type() {
-				Rule::r#in => in_redirs.push(redir.clone()),
-				Rule::out | Rule::append => out_redirs.push(redir.clone()),
+				Rule::r#in | Rule::heredoc | Rule::herestring => in_redirs.push(redir.clone()),
+				Rule::out | Rule::force_out | Rule::append => out_redirs.push(redir.clone()),
 				_ => unimplemented!()
 			}
 		}
@@ -370,9 +400,8 @@ impl ExecCtx {
 		Ok(())
 	}
 	pub fn ascend(&mut self) -> SlashResult<()> {
-		self.pop_state()?;
-		self.depth = self.depth.saturating_sub(1);
-		Ok(())
+		// The saved state already holds the depth from before we descended
+		self.pop_state()
 	}
 	pub fn descend(&mut self) -> SlashResult<()> {
 		self.push_state()?;
@@ -386,6 +415,16 @@ impl ExecCtx {
 	pub fn depth(&self) -> usize {
 		self.depth
 	}
+	/// How many loops the current command is inside of
+	pub fn loop_depth(&self) -> usize {
+		self.loop_depth
+	}
+	pub fn enter_loop(&mut self) {
+		self.loop_depth += 1;
+	}
+	pub fn leave_loop(&mut self) {
+		self.loop_depth = self.loop_depth.saturating_sub(1);
+	}
 	pub fn flags_mut(&mut self) -> &mut utils::ExecFlags {
 		&mut self.flags
 	}
@@ -410,10 +449,6 @@ impl ExecCtx {
 	pub fn consume_redirs(&mut self) -> utils::CmdRedirs {
 		utils::CmdRedirs::new(self.take_redirs())
 	}
-	pub fn activate_redirs(&mut self) -> SlashResult<()> {
-		let mut redirs = self.consume_redirs();
-		redirs.activate()
-	}
 }
 
 #[derive(Debug,Clone)]
@@ -487,6 +522,10 @@ impl<'a> ChildProc {
 	pub fn is_done(&self) -> bool {
 		matches!(self.status, WaitStatus::Exited(_, _))
 	}
+	/// Whether the process has terminated, either normally or from a signal
+	pub fn is_finished(&self) -> bool {
+		matches!(self.status, WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _))
+	}
 }
 
 pub struct JobBuilder {
@@ -549,6 +588,21 @@ impl Job {
 	pub fn is_alive(&self) -> bool {
 		!self.children.iter().all(|chld| chld.is_done())
 	}
+	pub fn is_finished(&self) -> bool {
+		self.children.iter().all(|chld| chld.is_finished())
+	}
+	pub fn is_stopped(&self) -> bool {
+		self.children.iter().any(|chld| chld.is_stopped())
+	}
+	/// The exit status of the job, which is taken from its last process
+	pub fn exit_code(&self) -> i32 {
+		match self.children.last().map(|chld| chld.status()) {
+			Some(WaitStatus::Exited(_, code)) => code,
+			Some(WaitStatus::Signaled(_, sig, _)) |
+			Some(WaitStatus::Stopped(_, sig)) => utils::SIG_EXIT_OFFSET + sig as i32,
+			_ => 0
+		}
+	}
 	pub fn table_id(&self) -> Option<usize> {
 		self.table_id
 	}
@@ -646,11 +700,16 @@ impl Job {
 		killpg(self.pgid, Some(signal)).map_err(|_| Low(SlashErrLow::from_io()))?;
 		Ok(())
 	}
-	pub fn wait_pgrp<'a>(&mut self) -> SlashResult<Vec<WaitStatus>> {
+	/// Waits on each child in the job that has not already been reaped
+	pub fn wait_pgrp<'a>(&mut self, flags: Option<WaitPidFlag>) -> SlashResult<Vec<WaitStatus>> {
 		let mut statuses = Vec::new();
 
 		for child in self.children.iter_mut() {
-			let result = child.waitpid(Some(WaitPidFlag::WUNTRACED));
+			if child.is_finished() {
+				statuses.push(child.status());
+				continue
+			}
+			let result = child.waitpid(flags);
 			match result {
 				Ok(status) => {
 					statuses.push(status);
@@ -696,17 +755,36 @@ impl Job {
 	}
 }
 
+/// The process behind a `<(...)` or `>(...)`, along with the shell's end of the pipe connected to it
+#[derive(Debug,Clone)]
+pub struct ProcSub {
+	pid: Pid,
+	fd: RawFd,
+	/// The execution depth of the command that uses this substitution
+	depth: usize
+}
+
 #[derive(Debug,Clone)]
 pub struct JobTable {
 	fg: Option<Job>,
 	jobs: Vec<Option<Job>>,
 	order: Vec<usize>,
-	new_updates: Vec<usize>
+	new_updates: Vec<usize>,
+	proc_subs: Vec<ProcSub>
 }
 
 impl JobTable {
 	pub fn new() -> Self {
-		Self { fg: None, jobs: vec![], order: vec![], new_updates: vec![] }
+		Self { fg: None, jobs: vec![], order: vec![], new_updates: vec![], proc_subs: vec![] }
+	}
+	pub fn push_proc_sub(&mut self, pid: Pid, fd: RawFd, depth: usize) {
+		self.proc_subs.push(ProcSub { pid, fd, depth })
+	}
+	/// Removes the process substitutions made at or below the given depth, so that they can be cleaned up
+	pub fn take_proc_subs(&mut self, depth: usize) -> Vec<ProcSub> {
+		let (taken, kept) = take(&mut self.proc_subs).into_iter().partition(|sub| sub.depth >= depth);
+		self.proc_subs = kept;
+		taken
 	}
 	pub fn reset_fg(&mut self) {
 		std::mem::take(&mut self.fg);
@@ -718,6 +796,9 @@ impl JobTable {
 			self.jobs.len()
 		}
 	}
+	pub fn jobs(&self) -> &[Option<Job>] {
+		&self.jobs
+	}
 	pub fn mut_jobs(&mut self) -> &mut Vec<Option<Job>> {
 		&mut self.jobs
 	}
@@ -755,7 +836,10 @@ impl JobTable {
 		let pgid = job.pgid();
 		self.fg = Some(job);
 		attach_tty(pgid)?;
-		let statuses = self.fg.as_mut().unwrap().wait_pgrp()?;
+		crate::signal::set_fg_pgid(Some(pgid));
+		let statuses = self.fg.as_mut().unwrap().wait_pgrp(Some(WaitPidFlag::WUNTRACED));
+		crate::signal::set_fg_pgid(None);
+		let statuses = statuses?;
 		attach_tty(getpgrp())?;
 		Ok(statuses)
 	}
@@ -992,16 +1076,18 @@ impl HashFloat {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SlashVal {
 	String(String),
-	Int(i32),
+	Int(i64),
 	Float(HashFloat),
 	Bool(bool),
 	Array(Vec<SlashVal>),
 	Dict(BTreeMap<String, SlashVal>),
+	/// JSON's null, which expands to nothing
+	Null,
 }
 
 impl SlashVal {
 	pub fn parse(mut s: &str) -> SlashResult<Self> {
-		if let Ok(int) = s.parse::<i32>() {
+		if let Ok(int) = s.parse::<i64>() {
 			return Ok(SlashVal::Int(int));
 		}
 		if let Ok(float) = s.parse::<f64>() {
@@ -1030,6 +1116,20 @@ impl SlashVal {
 				return Ok(SlashVal::Array(elements))
 			}
 		}
+		if let Ok(mut dict) = SlashParse::parse(Rule::dict, s) {
+			let dict = dict.next().unpack()?;
+			// Only take the literal if it spans the whole string
+			if dict.as_str().len() == s.len() {
+				let mut map = BTreeMap::new();
+				for entry in dict.into_inner() {
+					let mut entry_inner = entry.into_inner();
+					let key = entry_inner.next().unpack()?.as_str().trim_quotes().to_string();
+					let val = SlashVal::parse(entry_inner.next().unpack()?.as_str())?;
+					map.insert(key,val);
+				}
+				return Ok(SlashVal::Dict(map))
+			}
+		}
 		Ok(SlashVal::String(s.to_string()))
 	}
 
@@ -1048,6 +1148,7 @@ impl SlashVal {
 			SlashVal::Bool(_) => String::from("bool"),
 			SlashVal::Array(_) => String::from("array"),
 			SlashVal::Dict(_) => String::from("dict"),
+			SlashVal::Null => String::from("null"),
 		}
 	}
 
@@ -1059,9 +1160,14 @@ impl SlashVal {
 	pub fn increment(&mut self) -> SlashResult<()> {
 		match *self {
 			Self::Int(i) => {
-				self.operate(|_| Self::Int(i + 1))?
+				let i = i.checked_add(1).ok_or_else(|| Low(SlashErrLow::ExecFailed("Integer overflow in increment".into())))?;
+				self.operate(|_| Self::Int(i))?
+			}
+			Self::Float(ref f) => {
+				let f = f.0 + 1.0;
+				self.operate(|_| Self::Float(HashFloat(f)))?
 			}
-			_ => return Err(Low(SlashErrLow::InternalErr("Expected an integer in increment call".into()))),
+			_ => return Err(Low(SlashErrLow::InternalErr("Expected a number in increment call".into()))),
 		}
 		Ok(())
 	}
@@ -1069,9 +1175,14 @@ impl SlashVal {
 	pub fn decrement(&mut self) -> SlashResult<()> {
 		match *self {
 			Self::Int(i) => {
-				self.operate(|_| Self::Int(i - 1))?
+				let i = i.checked_sub(1).ok_or_else(|| Low(SlashErrLow::ExecFailed("Integer overflow in decrement".into())))?;
+				self.operate(|_| Self::Int(i))?
 			}
-			_ => return Err(Low(SlashErrLow::InternalErr("Expected an integer in decrement call".into()))),
+			Self::Float(ref f) => {
+				let f = f.0 - 1.0;
+				self.operate(|_| Self::Float(HashFloat(f)))?
+			}
+			_ => return Err(Low(SlashErrLow::InternalErr("Expected a number in decrement call".into()))),
 		}
 		Ok(())
 	}
@@ -1113,7 +1224,7 @@ impl SlashVal {
 		}
 	}
 
-	pub fn as_int(&self) -> Option<i32> {
+	pub fn as_int(&self) -> Option<i64> {
 		if let SlashVal::Int(i) = self {
 			Some(*i)
 		} else {
@@ -1185,6 +1296,80 @@ impl SlashVal {
 			Err(Low(SlashErrLow::InternalErr("Called try_remove() on a non-dict SlashVal".into())))
 		}
 	}
+
+	/// Converts a JSON value, with objects becoming dicts
+	/// Integers too big for an `Int` are refused instead of being rounded into a float
+	pub fn from_json(json: serde_json::Value) -> SlashResult<Self> {
+		let val = match json {
+			serde_json::Value::Null => SlashVal::Null,
+			serde_json::Value::Bool(bool) => SlashVal::Bool(bool),
+			serde_json::Value::Number(num) => match num.as_i64() {
+				Some(int) => SlashVal::Int(int),
+				None if num.is_f64() => SlashVal::Float(HashFloat(num.as_f64().unwrap_or_default())),
+				None => return Err(Low(SlashErrLow::ExecFailed(format!("Integer is too big in JSON: {}",num))))
+			}
+			serde_json::Value::String(string) => SlashVal::String(string),
+			serde_json::Value::Array(arr) => SlashVal::Array(arr.into_iter().map(SlashVal::from_json).collect::<SlashResult<_>>()?),
+			serde_json::Value::Object(map) => {
+				let dict = map.into_iter().map(|(key,val)| Ok((key, SlashVal::from_json(val)?))).collect::<SlashResult<_>>()?;
+				SlashVal::Dict(dict)
+			}
+		};
+		Ok(val)
+	}
+
+	/// Converts to a JSON value, with dicts becoming objects
+	/// Floats that JSON can't represent, like NaN, become null
+	pub fn to_json(&self) -> serde_json::Value {
+		match self {
+			SlashVal::String(string) => serde_json::Value::String(string.clone()),
+			SlashVal::Int(int) => serde_json::Value::from(*int),
+			SlashVal::Float(float) => serde_json::Number::from_f64(float.0).map_or(serde_json::Value::Null, serde_json::Value::Number),
+			SlashVal::Bool(bool) => serde_json::Value::Bool(*bool),
+			SlashVal::Array(arr) => serde_json::Value::Array(arr.iter().map(|val| val.to_json()).collect()),
+			SlashVal::Dict(dict) => serde_json::Value::Object(dict.iter().map(|(key,val)| (key.clone(), val.to_json())).collect()),
+			SlashVal::Null => serde_json::Value::Null
+		}
+	}
+
+	/// Follows a chain of keys through nested dicts and arrays
+	/// Array elements are found by their position, so their keys have to be numbers, and negative numbers count back from the end
+	pub fn get_element(&self, keys: &[String]) -> Option<&SlashVal> {
+		let Some((key,rest)) = keys.split_first() else {
+			return Some(self)
+		};
+		match self {
+			SlashVal::Dict(map) => map.get(key)?.get_element(rest),
+			SlashVal::Array(arr) => arr.get(array_pos(key, arr.len())?)?.get_element(rest),
+			_ => None
+		}
+	}
+
+	/// Sets the element at the end of a chain of keys, creating dicts for any keys along the way that don't exist yet
+	pub fn set_element(&mut self, keys: &[String], val: SlashVal) -> SlashResult<()> {
+		let Some((key,rest)) = keys.split_first() else {
+			*self = val;
+			return Ok(())
+		};
+		match self {
+			SlashVal::Dict(map) => map.entry(key.clone()).or_insert_with(|| SlashVal::Dict(BTreeMap::new())).set_element(rest, val),
+			SlashVal::Array(arr) => {
+				let len = arr.len();
+				let element = array_pos(key, len)
+					.and_then(|idx| arr.get_mut(idx))
+					.ok_or_else(|| Low(SlashErrLow::IndexErr(format!("Index '{}' out of range for array of length {}",key,len))))?;
+				element.set_element(rest, val)
+			}
+			_ => Err(Low(SlashErrLow::ExecFailed(format!("Cannot index into a value of type {}",self.fmt_type()))))
+		}
+	}
+}
+
+/// Turns an array index into a position in the array, counting back from the end if it is negative
+pub fn array_pos(index: &str, len: usize) -> Option<usize> {
+	let index = index.parse::<i64>().ok()?;
+	let pos = if index < 0 { len as i64 + index } else { index };
+	usize::try_from(pos).ok()
 }
 
 impl Default for SlashVal {
@@ -1211,6 +1396,7 @@ impl fmt::Display for SlashVal {
 					.collect();
 					write!(f, "{{{}}}", formatted_dict.join(", "))
 			}
+			SlashVal::Null => Ok(())
 		}
 	}
 }
@@ -1219,12 +1405,23 @@ impl fmt::Display for SlashVal {
 
 
 
+/// The variables declared with `local` in a single function call
+/// The caller's positional parameters are kept here until the call returns
+#[derive(Debug,Clone)]
+struct Scope {
+	locals: HashMap<String,SlashVal>,
+	attrs: HashMap<String,VarFlags>,
+	saved_pos_params: VecDeque<String>
+}
+
 #[derive(Debug,Clone)]
 pub struct VarTable {
 	env: HashMap<String,String>,
 	params: HashMap<String,String>,
 	pos_params: VecDeque<String>,
-	vars: HashMap<String,SlashVal>
+	vars: HashMap<String,SlashVal>,
+	scopes: Vec<Scope>,
+	attrs: HashMap<String,VarFlags>
 }
 
 impl VarTable {
@@ -1233,7 +1430,9 @@ impl VarTable {
 			env,
 			params: HashMap::new(),
 			pos_params: VecDeque::new(),
-			vars: HashMap::new()
+			vars: HashMap::new(),
+			scopes: vec![],
+			attrs: HashMap::new()
 		}
 	}
 
@@ -1249,10 +1448,12 @@ impl VarTable {
 	pub fn get_evar(&self, key: &str) -> Option<String> {
 		self.env.get(key).cloned().map(|evar| evar.to_string())
 	}
-	pub fn export_var(&mut self, key: &str, val: &str) {
+	pub fn export_var(&mut self, key: &str, val: &str) -> SlashResult<()> {
+		self.check_writable(key)?;
 		let value = val.trim_matches(['"', '\'']).to_string();
 		self.env.insert(key.into(), value.clone());
 		std::env::set_var(key, value);
+		Ok(())
 	}
 	pub fn unset_evar(&mut self, key: &str) {
 		self.env.remove(key);
@@ -1262,7 +1463,12 @@ impl VarTable {
 	// Getters, setters, and unsetters for `params`
 	pub fn get_param(&self, key: &str) -> Option<String> {
 		if let Ok(index) = key.parse::<usize>() {
-			self.pos_params.get(index).cloned().map(|param| param.to_string())
+			if index == 0 {
+				// $0 is the name of the shell, and $1 is the first positional parameter
+				self.params.get("0").cloned().or_else(|| env::args().next())
+			} else {
+				self.pos_params.get(index - 1).cloned()
+			}
 		} else {
 			let result = self.params.get(key).cloned().map(|param| param.to_string());
 			result
@@ -1276,14 +1482,92 @@ impl VarTable {
 	}
 	pub fn pos_param_popfront(&mut self) -> Option<String> {
 		let popped_param = self.pos_params.pop_front();
-		self.set_param("@".into(), &self.pos_params.clone().to_vec().join(" "));
-		self.set_param("#".into(), &self.pos_params.len().to_string());
+		self.refresh_pos_param_info();
 		popped_param
 	}
 	pub fn pos_param_pushback(&mut self, param: &str) {
 		self.pos_params.push_back(param.to_string());
-		self.set_param("@".into(), &self.pos_params.clone().to_vec().join(" "));
-		self.set_param("#".into(), &self.pos_params.len().to_string());
+		self.refresh_pos_param_info();
+	}
+	pub fn set_pos_params(&mut self, params: VecDeque<String>) {
+		self.pos_params = params;
+		self.refresh_pos_param_info();
+	}
+	fn refresh_pos_param_info(&mut self) {
+		self.set_param("@", &self.pos_params.clone().to_vec().join(" "));
+		self.set_param("#", &self.pos_params.len().to_string());
+	}
+
+	/// Enters a new function call scope with the given positional parameters
+	pub fn push_scope(&mut self, args: VecDeque<String>) {
+		let saved_pos_params = std::mem::replace(&mut self.pos_params, args);
+		self.scopes.push(Scope { locals: HashMap::new(), attrs: HashMap::new(), saved_pos_params });
+		self.refresh_pos_param_info();
+	}
+	/// Leaves the current function call scope, dropping its locals and restoring the caller's positional parameters
+	pub fn pop_scope(&mut self) {
+		if let Some(scope) = self.scopes.pop() {
+			self.pos_params = scope.saved_pos_params;
+			self.refresh_pos_param_info();
+		}
+	}
+	pub fn in_func_scope(&self) -> bool {
+		!self.scopes.is_empty()
+	}
+	/// Whether the variable has been declared local in the innermost function scope
+	pub fn is_local(&self, key: &str) -> bool {
+		self.scopes.last().is_some_and(|scope| scope.locals.contains_key(key))
+	}
+	/// Declares a variable in the innermost function scope, shadowing any variable with the same name
+	/// Returns false if there is no function scope to declare it in
+	pub fn set_local(&mut self, key: &str, val: SlashVal) -> SlashResult<()> {
+		self.check_writable(key)?;
+		if !self.in_func_scope() {
+			return Err(Low(SlashErrLow::ExecFailed("`local' can only be used inside of a function".into())))
+		}
+		// A new local does not inherit the attributes of the variable it shadows
+		let val = if self.is_local(key) { self.apply_attrs(key, val)? } else { val };
+		self.scopes.last_mut().unwrap().locals.insert(key.to_string(), val);
+		Ok(())
+	}
+	/// Every variable that is currently visible, with locals shadowing globals
+	pub fn visible_vars(&self) -> BTreeMap<&str,&SlashVal> {
+		let mut visible = self.vars.iter().map(|(k,v)| (k.as_str(),v)).collect::<BTreeMap<&str,&SlashVal>>();
+		for scope in &self.scopes {
+			visible.extend(scope.locals.iter().map(|(k,v)| (k.as_str(),v)));
+		}
+		visible
+	}
+	/// Finds the map that currently owns a variable
+	/// Variables are resolved dynamically, so the innermost scope that declares one wins, followed by the globals
+	fn owning_map(&self, key: &str) -> &HashMap<String,SlashVal> {
+		self.scopes.iter()
+			.rev()
+			.map(|scope| &scope.locals)
+			.find(|locals| locals.contains_key(key))
+			.unwrap_or(&self.vars)
+	}
+	fn owning_map_mut(&mut self, key: &str) -> &mut HashMap<String,SlashVal> {
+		self.scopes.iter_mut()
+			.rev()
+			.map(|scope| &mut scope.locals)
+			.find(|locals| locals.contains_key(key))
+			.unwrap_or(&mut self.vars)
+	}
+	/// Attributes belong to the same scope as the variable they describe
+	fn owning_attrs(&self, key: &str) -> &HashMap<String,VarFlags> {
+		self.scopes.iter()
+			.rev()
+			.find(|scope| scope.locals.contains_key(key))
+			.map(|scope| &scope.attrs)
+			.unwrap_or(&self.attrs)
+	}
+	fn owning_attrs_mut(&mut self, key: &str) -> &mut HashMap<String,VarFlags> {
+		self.scopes.iter_mut()
+			.rev()
+			.find(|scope| scope.locals.contains_key(key))
+			.map(|scope| &mut scope.attrs)
+			.unwrap_or(&mut self.attrs)
 	}
 	pub fn set_param(&mut self, key: &str, value: &str) {
 		self.params.insert(key.into(), value.into());
@@ -1295,14 +1579,74 @@ impl VarTable {
 		self.params.remove(key);
 	}
 
-	pub fn set_var(&mut self, key: &str, val: SlashVal) {
-		self.vars.insert(key.to_string(),val);
+	pub fn set_var(&mut self, key: &str, val: SlashVal) -> SlashResult<()> {
+		self.check_writable(key)?;
+		let val = self.apply_attrs(key, val)?;
+		if self.get_attrs(key).contains(VarFlags::EXPORT) {
+			self.export_var(key, &val.to_string())?;
+		}
+		self.owning_map_mut(key).insert(key.to_string(),val);
+		Ok(())
+	}
+	pub fn unset_var(&mut self, key: &str) -> SlashResult<()> {
+		self.check_writable(key)?;
+		self.owning_map_mut(key).remove(key);
+		Ok(())
+	}
+
+	// Getters and setters for variable attributes
+	pub fn get_attrs(&self, key: &str) -> VarFlags {
+		self.owning_attrs(key).get(key).copied().unwrap_or(VarFlags::empty())
+	}
+	pub fn add_attrs(&mut self, key: &str, flags: VarFlags) {
+		*self.owning_attrs_mut(key).entry(key.into()).or_insert(VarFlags::empty()) |= flags;
+	}
+	pub fn remove_attrs(&mut self, key: &str, flags: VarFlags) -> SlashResult<()> {
+		if flags.contains(VarFlags::READONLY) {
+			self.check_writable(key)?;
+		}
+		if let Some(attrs) = self.owning_attrs_mut(key).get_mut(key) {
+			attrs.remove(flags);
+		}
+		Ok(())
+	}
+	pub fn check_writable(&self, key: &str) -> SlashResult<()> {
+		if self.get_attrs(key).contains(VarFlags::READONLY) {
+			Err(Low(SlashErrLow::ExecFailed(format!("`{}' is a readonly variable",key))))
+		} else {
+			Ok(())
+		}
 	}
-	pub fn unset_var(&mut self, key: &str) {
-		self.vars.remove(key);
+	/// Coerces a value to fit the attributes of the variable it is being assigned to
+	fn apply_attrs(&self, key: &str, val: SlashVal) -> SlashResult<SlashVal> {
+		let attrs = self.get_attrs(key);
+		let val = match val {
+			SlashVal::String(string) if attrs.contains(VarFlags::INTEGER) => {
+				let int = string.trim().parse::<i64>()
+					.map_err(|_| Low(SlashErrLow::ExecFailed(format!("`{}' is an integer variable, got `{}'",key,string))))?;
+				SlashVal::Int(int)
+			}
+			SlashVal::Float(_) | SlashVal::Bool(_) | SlashVal::Null |
+			SlashVal::Array(_) | SlashVal::Dict(_) if attrs.contains(VarFlags::INTEGER) => {
+				return Err(Low(SlashErrLow::ExecFailed(format!("`{}' is an integer variable, got a {}",key,val.fmt_type()))))
+			}
+			SlashVal::String(string) if attrs.contains(VarFlags::LOWER) => SlashVal::String(string.to_lowercase()),
+			SlashVal::String(string) if attrs.contains(VarFlags::UPPER) => SlashVal::String(string.to_uppercase()),
+			_ => val
+		};
+		let val = match val {
+			SlashVal::Array(_) => val,
+			_ if attrs.contains(VarFlags::ARRAY) => SlashVal::Array(vec![val]),
+			SlashVal::Dict(_) => val,
+			_ if attrs.contains(VarFlags::DICT) => {
+				return Err(Low(SlashErrLow::ExecFailed(format!("`{}' is a dict variable, got a {}",key,val.fmt_type()))))
+			}
+			_ => val
+		};
+		Ok(val)
 	}
 	pub fn get_var(&self, key: &str) -> Option<SlashVal> {
-		if let Some(var) = self.vars.get(key).cloned() {
+		if let Some(var) = self.owning_map(key).get(key).cloned() {
 			Some(var)
 		} else if let Some(var) = self.params.get(key).cloned() {
 			let val = SlashVal::String(var);
@@ -1313,11 +1657,11 @@ impl VarTable {
 		}
 	}
 	pub fn get_var_mut(&mut self, key: &str) -> Option<&mut SlashVal> {
-		self.vars.get_mut(key)
+		self.owning_map_mut(key).get_mut(key)
 	}
 
 	pub fn index_arr(&self, key: &str, index: usize) -> SlashResult<SlashVal> {
-		if let Some(var) = self.vars.get(key) {
+		if let Some(var) = self.owning_map(key).get(key) {
 			if let SlashVal::Array(arr) = var {
 				if let Some(value) = arr.get(index) {
 					Ok(value.clone())
@@ -1335,15 +1679,17 @@ impl VarTable {
 
 #[derive(Debug,Clone)]
 pub struct LogicTable {
-	functions: HashMap<String,String>,
-	aliases: HashMap<String,String>
+	functions: HashMap<String,Arc<Block>>,
+	aliases: HashMap<String,String>,
+	traps: BTreeMap<TrapTarget,Arc<Block>>
 }
 
 impl LogicTable {
 	pub fn new() -> Self {
 		Self {
 			functions: HashMap::new(),
-			aliases: HashMap::new()
+			aliases: HashMap::new(),
+			traps: BTreeMap::new()
 		}
 	}
 	pub fn new_alias(&mut self, name: &str, value: String) {
@@ -1358,18 +1704,30 @@ impl LogicTable {
 	pub fn get_alias(&self, name: &str) -> Option<String> {
 		self.aliases.get(name).cloned()
 	}
-	pub fn new_func(&mut self, name: &str, instructions: &str) {
-		self.functions.insert(name.to_string(),instructions.to_string());
+	pub fn new_func(&mut self, name: &str, body: Arc<Block>) {
+		self.functions.insert(name.to_string(),body);
 	}
-	pub fn get_func(&self, name: &str) -> Option<String> {
+	pub fn get_func(&self, name: &str) -> Option<Arc<Block>> {
 		self.functions.get(name).cloned()
 	}
-	pub fn borrow_functions(&self) -> &HashMap<String,String> {
+	pub fn borrow_functions(&self) -> &HashMap<String,Arc<Block>> {
 		&self.functions
 	}
 	pub fn remove_func(&mut self, name: &str) {
 		self.functions.remove(name);
 	}
+	pub fn new_trap(&mut self, target: TrapTarget, body: Arc<Block>) {
+		self.traps.insert(target,body);
+	}
+	pub fn get_trap(&self, target: TrapTarget) -> Option<Arc<Block>> {
+		self.traps.get(&target).cloned()
+	}
+	pub fn borrow_traps(&self) -> &BTreeMap<TrapTarget,Arc<Block>> {
+		&self.traps
+	}
+	pub fn remove_trap(&mut self, target: TrapTarget) {
+		self.traps.remove(&target);
+	}
 }
 
 impl Default for LogicTable {
@@ -1463,28 +1821,32 @@ impl EnvMeta {
 }
 
 
-/// Override the default signal handler to manually wait on processes
-pub fn disable_reaping() {
-	unsafe { signal(Signal::SIGCHLD, SigHandler::Handler(crate::signal::ignore_sigchld)) }.unwrap();
-}
-
-/// Re-enable the sigchld handler
-pub fn enable_reaping<'a>() -> SlashResult<()> {
-	write_jobs(|j| j.update_job_statuses())??;
-	unsafe { signal(Signal::SIGCHLD, SigHandler::Handler(crate::signal::handle_sigchld)) }.unwrap();
+/// Closes the pipes of the process substitutions used by a command that has finished, and waits for them to exit
+/// Closing the pipe sends EOF to a `>(...)` reader, and breaks the pipe of a `<(...)` writer that still has output left,
+/// so neither of them keeps running for long once the command is done with it
+pub fn reap_proc_subs(depth: usize) -> SlashResult<()> {
+	let proc_subs = write_jobs(|j| j.take_proc_subs(depth))?;
+	for sub in proc_subs {
+		nix::unistd::close(sub.fd).ok();
+		while let Err(nix::errno::Errno::EINTR) = waitpid(sub.pid, None) {}
+	}
 	Ok(())
 }
 
 pub fn read_jobs<'a,F,T>(f: F) -> SlashResult<T>
 where F: FnOnce(&JobTable) -> T {
-	let lock = JOBS.read().map_err(|_| Low(SlashErrLow::InternalErr("Failed to obtain write lock; lock might be poisoned".into())))?;
-	Ok(f(&lock))
+	JOBS.with(|jobs| {
+		let table = jobs.try_borrow().map_err(|_| Low(SlashErrLow::InternalErr("Failed to borrow the job table; it is already being written to".into())))?;
+		Ok(f(&table))
+	})
 }
 
 pub fn write_jobs<'a,F,T>(f: F) -> SlashResult<T>
 where F: FnOnce(&mut JobTable) -> T {
-	let mut lock = JOBS.write().map_err(|_| Low(SlashErrLow::InternalErr("Failed to obtain write lock; lock might be poisoned".into())))?;
-	Ok(f(&mut lock))
+	JOBS.with(|jobs| {
+		let mut table = jobs.try_borrow_mut().map_err(|_| Low(SlashErrLow::InternalErr("Failed to borrow the job table; it is already in use".into())))?;
+		Ok(f(&mut table))
+	})
 }
 
 pub fn attach_tty<'a>(pgid: Pid) -> SlashResult<()> {
@@ -87,11 +87,11 @@ impl ShOptsCore {
 			"dotglob" => Ok(SlashVal::Bool(self.dotglob)),
 			"autocd" => Ok(SlashVal::Bool(self.autocd)),
 			"hist_ignore_dupes" => Ok(SlashVal::Bool(self.hist_ignore_dupes)),
-			"max_hist" => Ok(SlashVal::Int(self.max_hist as i32)),
+			"max_hist" => Ok(SlashVal::Int(self.max_hist as i64)),
 			"int_comments" => Ok(SlashVal::Bool(self.int_comments)),
 			"auto_hist" => Ok(SlashVal::Bool(self.auto_hist)),
-			"bell_style" => Ok(SlashVal::Int(self.bell_style as i32)),
-			"max_recurse_depth" => Ok(SlashVal::Int(self.max_recurse_depth as i32)),
+			"bell_style" => Ok(SlashVal::Int(self.bell_style as i64)),
+			"max_recurse_depth" => Ok(SlashVal::Int(self.max_recurse_depth as i64)),
 			_ => Err(SlashErr::Low(SlashErrLow::ExecFailed(format!("Invalid core opts key: {}",key))))
 		}
 	}
@@ -161,11 +161,11 @@ impl ShOptsPrompt {
 	pub fn get<'a>(&self, mut query: VecDeque<String>) -> SlashResult<SlashVal> {
 		let key = query.pop_front().unwrap();
 		match key.as_str() {
-			"trunc_prompt_path" => Ok(SlashVal::Int(self.trunc_prompt_path as i32)),
+			"trunc_prompt_path" => Ok(SlashVal::Int(self.trunc_prompt_path as i64)),
 			"edit_mode" => Ok(SlashVal::String(self.edit_mode.clone())),
-			"comp_limit" => Ok(SlashVal::Int(self.comp_limit as i32)),
+			"comp_limit" => Ok(SlashVal::Int(self.comp_limit as i64)),
 			"prompt_highlight" => Ok(SlashVal::Bool(self.prompt_highlight)),
-			"tab_stop" => Ok(SlashVal::Int(self.tab_stop as i32)),
+			"tab_stop" => Ok(SlashVal::Int(self.tab_stop as i64)),
 			"exit_status" => Ok(self.exit_status.get(query)?),
 			"custom" => Ok(self.custom.get(query)?),
 			_ => Err(SlashErr::Low(SlashErrLow::ExecFailed(format!("Invalid key for prompt opts: {}",key))))
@@ -1,83 +1,224 @@
+use std::{fmt::Display, str::FromStr, sync::atomic::{AtomicI32, AtomicU64, Ordering}};
+
 use nix::{sys::{signal::{killpg, signal, SigHandler, Signal} , wait::{waitpid, WaitPidFlag, WaitStatus}}, unistd::{getpgid, getpgrp, Pid}};
 
-use crate::{error::{SlashErr, SlashErrLow}, helper, shellenv::{self, read_jobs, write_jobs, JobCmdFlags, JobID}, SlashResult};
+use crate::{error::{SlashErr, SlashErrLow}, execute::dispatch, helper, shellenv::{self, read_jobs, write_jobs, JobCmdFlags, JobID, Slash}, utils::ExecFlags, SlashResult};
 
-pub fn sig_handler_setup() {
-	unsafe {
-		signal(Signal::SIGCHLD, SigHandler::Handler(handle_sigchld)).unwrap();
-		signal(Signal::SIGQUIT, SigHandler::Handler(handle_sigquit)).unwrap();
-		signal(Signal::SIGTSTP, SigHandler::Handler(handle_sigtstp)).unwrap();
-		signal(Signal::SIGHUP, SigHandler::Handler(handle_sighup)).unwrap();
-		signal(Signal::SIGINT, SigHandler::Handler(handle_sigint)).unwrap();
-		signal(Signal::SIGTTIN, SigHandler::SigIgn).unwrap();
-		signal(Signal::SIGTTOU, SigHandler::SigIgn).unwrap();
+/// Signals that the shell installs its own handlers for
+const SHELL_SIGNALS: [Signal; 7] = [
+	Signal::SIGCHLD,
+	Signal::SIGQUIT,
+	Signal::SIGTSTP,
+	Signal::SIGHUP,
+	Signal::SIGINT,
+	Signal::SIGTTIN,
+	Signal::SIGTTOU,
+];
+
+/// A bitmask of trapped signals which have been received, but whose handlers have not been run yet.
+/// User handlers can't be run from inside of the signal handler, so they are run later at a safe point.
+static PENDING_TRAPS: AtomicU64 = AtomicU64::new(0);
+
+/// The same kind of bitmask, but for signals that the shell handles itself, like SIGCHLD and SIGHUP
+/// Reaping children and touching the job table aren't safe to do in a signal handler either, so that work is also done at a safe point
+static PENDING_SIGNALS: AtomicU64 = AtomicU64::new(0);
+
+/// The process group of the job currently running in the foreground, or 0 if there isn't one
+/// This lets SIGINT and SIGTSTP be forwarded to the job without going through the job table
+static FG_PGID: AtomicI32 = AtomicI32::new(0);
+
+/// The things that a trap can be set on
+/// The pseudo-signals are raised by the shell itself rather than by the kernel
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub enum TrapTarget {
+	Exit,
+	Err,
+	Debug,
+	Return,
+	Signal(Signal)
+}
+
+impl FromStr for TrapTarget {
+	type Err = SlashErr;
+	/// Accepts signal names with or without the SIG prefix in any case, as well as signal numbers
+	fn from_str(s: &str) -> Result<Self, SlashErr> {
+		let upper = s.to_uppercase();
+		let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+		let target = match name {
+			"EXIT" | "0" => Some(TrapTarget::Exit),
+			"ERR" => Some(TrapTarget::Err),
+			"DEBUG" => Some(TrapTarget::Debug),
+			"RETURN" => Some(TrapTarget::Return),
+			_ => match name.parse::<i32>() {
+				Ok(num) => Signal::try_from(num).ok(),
+				Err(_) => Signal::from_str(&format!("SIG{}",name)).ok()
+			}.map(TrapTarget::Signal)
+		};
+		target.ok_or_else(|| SlashErr::Low(SlashErrLow::ExecFailed(format!("Invalid signal specification: {}",s))))
 	}
 }
 
-extern "C" fn handle_sighup(_: libc::c_int) {
-	write_jobs(|j| {
-		for job in j.mut_jobs().iter_mut().flatten() {
-			job.killpg(Signal::SIGTERM).unwrap();
+impl Display for TrapTarget {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			TrapTarget::Exit => write!(f,"EXIT"),
+			TrapTarget::Err => write!(f,"ERR"),
+			TrapTarget::Debug => write!(f,"DEBUG"),
+			TrapTarget::Return => write!(f,"RETURN"),
+			TrapTarget::Signal(sig) => write!(f,"{}",sig.as_str())
 		}
-	}).unwrap();
-	std::process::exit(0);
+	}
 }
 
-extern "C" fn handle_sigtstp(_: libc::c_int) {
-	write_jobs(|j| {
-		if let Some(job) = j.get_fg_mut() {
-			job.killpg(Signal::SIGTSTP).unwrap();
-		}
-	}).unwrap();
+/// The handler used for a signal when no trap is set on it
+fn default_handler(sig: Signal) -> SigHandler {
+	match sig {
+		Signal::SIGCHLD |
+		Signal::SIGQUIT |
+		Signal::SIGHUP => SigHandler::Handler(handle_shell_signal),
+		Signal::SIGTSTP |
+		Signal::SIGINT => SigHandler::Handler(forward_to_fg),
+		Signal::SIGTTIN | Signal::SIGTTOU => SigHandler::SigIgn,
+		_ => SigHandler::SigDfl
+	}
 }
 
-extern "C" fn handle_sigint(_: libc::c_int) {
-	write_jobs(|j| {
-		if let Some(job) = j.get_fg_mut() {
-			job.killpg(Signal::SIGINT).unwrap();
-		}
-	}).unwrap();
+pub fn sig_handler_setup() {
+	for sig in SHELL_SIGNALS {
+		unsafe { signal(sig, default_handler(sig)) }.unwrap();
+	}
 }
 
-pub extern "C" fn ignore_sigchld(_: libc::c_int) {
-	/*
-	Do nothing
-	This function exists because using SIGIGN to ignore SIGCHLD
-	will cause the signal to bounce off of the program
-	which will prevents the code from being able to reap it elsewhere
-	which is the entire point of shellenv::disable_reaping()
+/// Replaces the shell's handling of a signal with a user trap
+/// If `ignore` is true, the signal is ignored instead
+pub fn set_trap_handler(sig: Signal, ignore: bool) -> SlashResult<()> {
+	let handler = if ignore {
+		SigHandler::SigIgn
+	} else {
+		SigHandler::Handler(handle_trapped)
+	};
+	unsafe { signal(sig, handler) }.map_err(|e| SlashErr::Low(SlashErrLow::ErrNo(e)))?;
+	Ok(())
+}
 
-	This function is used when we want to handle SIGCHLD explicitly,
-	like in the case of handling foreground jobs
-	*/
+/// Gives control of a signal back to the shell
+pub fn reset_trap_handler(sig: Signal) -> SlashResult<()> {
+	unsafe { signal(sig, default_handler(sig)) }.map_err(|e| SlashErr::Low(SlashErrLow::ErrNo(e)))?;
+	Ok(())
 }
 
-extern "C" fn handle_sigquit(_: libc::c_int) {
-	write_jobs(|j| {
-		for job in j.mut_jobs().iter_mut().flatten() {
-			job.killpg(Signal::SIGTERM).unwrap();
+/// Records the process group of the foreground job, so that terminal signals sent to the shell reach it
+/// Passing None clears it once the job is no longer in the foreground
+pub fn set_fg_pgid(pgid: Option<Pid>) {
+	FG_PGID.store(pgid.map(|pgid| pgid.as_raw()).unwrap_or(0), Ordering::SeqCst);
+}
+
+/// Does the work for any signals that the shell handles itself, and then runs the handlers for any trapped signals
+/// that have arrived since the last check
+pub fn run_pending_traps(slash: &mut Slash) -> SlashResult<()> {
+	run_pending_signals()?;
+	if slash.ctx().flags().contains(ExecFlags::IN_TRAP) {
+		return Ok(())
+	}
+	let pending = PENDING_TRAPS.swap(0, Ordering::SeqCst);
+	if pending == 0 {
+		return Ok(())
+	}
+	for sig in Signal::iterator() {
+		if pending & (1 << sig as i32) != 0 {
+			run_trap(slash, TrapTarget::Signal(sig))?;
 		}
-	}).unwrap();
-	std::process::exit(0);
+	}
+	Ok(())
 }
 
-pub extern "C" fn handle_sigchld(_: libc::c_int) {
+/// Runs the trap set on the given target, if there is one
+/// The exit status from before the trap ran is preserved, and traps do not fire from inside of other traps
+pub fn run_trap(slash: &mut Slash, target: TrapTarget) -> SlashResult<()> {
+	if slash.ctx().flags().contains(ExecFlags::IN_TRAP) {
+		return Ok(())
+	}
+	let Some(body) = slash.logic().get_trap(target) else {
+		return Ok(())
+	};
+	let status = slash.get_status();
+	let saved = slash.ctx().clone();
+	slash.ctx_mut().set_redirs(Default::default());
+	*slash.ctx_mut().flags_mut() |= ExecFlags::IN_TRAP;
+
+	let result = dispatch::exec_block(&body, slash);
+
+	*slash.ctx_mut() = saved;
+	slash.set_code(status);
+	result
+}
+
+extern "C" fn handle_trapped(sig: libc::c_int) {
+	PENDING_TRAPS.fetch_or(1 << sig, Ordering::SeqCst);
+}
+
+extern "C" fn handle_shell_signal(sig: libc::c_int) {
+	PENDING_SIGNALS.fetch_or(1 << sig, Ordering::SeqCst);
+}
+
+extern "C" fn forward_to_fg(sig: libc::c_int) {
+	// killpg() is async-signal-safe, unlike taking the job table lock
+	let pgid = FG_PGID.load(Ordering::SeqCst);
+	if pgid > 0 {
+		unsafe { libc::killpg(pgid, sig) };
+	}
+}
+
+/// Handles the signals that the shell's own handlers have recorded since the last check
+fn run_pending_signals() -> SlashResult<()> {
+	let pending = PENDING_SIGNALS.swap(0, Ordering::SeqCst);
+	if pending == 0 {
+		return Ok(())
+	}
+	if pending & (1 << Signal::SIGHUP as i32 | 1 << Signal::SIGQUIT as i32) != 0 {
+		read_jobs(|j| {
+			for job in j.jobs().iter().flatten() {
+				killpg(job.pgid(), Signal::SIGTERM).ok();
+			}
+		})?;
+		return Err(SlashErr::Low(SlashErrLow::CleanExit(0)))
+	}
+	if pending & (1 << Signal::SIGCHLD as i32) != 0 {
+		reap_jobs()?;
+	}
+	Ok(())
+}
+
+/// Collects the status changes of background jobs
+/// Only processes that belong to a job in the job table are waited on,
+/// so children that some other part of the shell is waiting on are left alone
+pub fn reap_jobs() -> SlashResult<()> {
 	/*
-	 * This is the signal handler's real job
 	 * Each WaitStatus has logic associated with it
 	 * But handle_child_exit() is the most important one
 	 */
-	let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED;
-	while let Ok(status) = waitpid(None, Some(flags)) {
-		let _ = match status {
-			WaitStatus::Exited(pid, _code) => handle_child_exit(pid, status),
-			WaitStatus::Signaled(pid, signal, _) => handle_child_signal(pid, signal),
-			WaitStatus::Stopped(pid, signal) => handle_child_stop(pid, signal),
-			WaitStatus::Continued(pid) => handle_child_continue(pid),
-			WaitStatus::StillAlive => break, // No more processes to reap
-			_ => unreachable!(),
+	let pids = read_jobs(|j| {
+		j.jobs().iter()
+			.flatten()
+			.flat_map(|job| job.get_children())
+			.filter(|chld| !chld.is_finished())
+			.map(|chld| chld.pid())
+			.collect::<Vec<Pid>>()
+	})?;
+	let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+	for pid in pids {
+		let Ok(status) = waitpid(pid, Some(flags)) else {
+			continue
 		};
+		match status {
+			WaitStatus::Exited(pid, _code) => handle_child_exit(pid, status)?,
+			WaitStatus::Signaled(pid, signal, _) => handle_child_signal(pid, signal)?,
+			WaitStatus::Stopped(pid, signal) => handle_child_stop(pid, signal)?,
+			WaitStatus::Continued(pid) => handle_child_continue(pid)?,
+			_ => { /* Still running */ }
+		}
 	}
+	Ok(())
 }
 
 //TODO: extract some of this logic from the closure to spend less time holding a write lock
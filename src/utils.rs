@@ -1,8 +1,9 @@
 use libc::{S_IRGRP, S_IROTH, S_IRUSR, S_IWUSR};
+use nix::unistd::{lseek, Whence};
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-use crate::{helper, prelude::*, shellenv::{ChildProc, JobBuilder}};
+use crate::{error::Blame, expand, helper, prelude::*, shellenv::{write_jobs, ChildProc, JobBuilder}};
 
 pub const SIG_EXIT_OFFSET: i32 = 128;
 
@@ -51,6 +52,9 @@ bitflags::bitflags! {
 		const NO_RESET_IN   = 0b00000000000000000000000000010000;
 		const NO_RESET_OUT  = 0b00000000000000000000000000100000;
 		const NO_RESET_ERR  = 0b00000000000000000000000001000000;
+		const IN_TRAP       = 0b00000000000000000000000010000000;
+		const IN_COND       = 0b00000000000000000000000100000000;
+		const IN_TRY        = 0b00000000000000000000001000000000;
 	}
 }
 
@@ -60,7 +64,15 @@ pub struct Redir {
 	redir_type: Rule,
 	our_fd: i32,
 	their_fd: Option<i32>,
-	file_target: Option<PathBuf>
+	file_target: Option<PathBuf>,
+	/// The text fed to the command by a heredoc or herestring
+	text: Option<String>,
+	/// Whether `text` still has to be expanded before it is used
+	expand_text: bool,
+	/// `<<-` strips leading tabs from each line of the heredoc body
+	strip_tabs: bool,
+	/// The file target is a process substitution, which becomes a `/dev/fd/N` path once it is expanded
+	proc_sub: bool
 }
 
 impl Redir {
@@ -71,6 +83,9 @@ impl Redir {
 			let mut our_fd = None;
 			let mut their_fd = None;
 			let mut file_target = None;
+			let mut expand_text = false;
+			let mut strip_tabs = false;
+			let mut proc_sub = false;
 			while let Some(pair) = inner.next() {
 				match pair.as_rule() {
 					Rule::fd_out => {
@@ -78,6 +93,7 @@ impl Redir {
 						our_fd = Some(fd);
 					}
 					Rule::file => {
+						proc_sub = pair.scry(Rule::proc_sub).is_some();
 						let path = PathBuf::from(pair.as_str());
 						file_target = Some(path);
 					}
@@ -85,12 +101,16 @@ impl Redir {
 						let fd = pair.as_str().parse::<i32>().unwrap();
 						their_fd = Some(fd);
 					}
+					Rule::heredoc_delim => expand_text = !pair.as_str().starts_with(['\'', '"', '\\']),
+					Rule::heredoc => {
+						strip_tabs = pair.as_str() == "<<-";
+						redir_type = Some(pair.as_rule());
+					}
 					Rule::r#in |
 					Rule::out |
 					Rule::force_out |
 					Rule::in_out |
 					Rule::append |
-					Rule::heredoc |
 					Rule::herestring => redir_type = Some(pair.as_rule()),
 					_ => unreachable!()
 				}
@@ -101,13 +121,23 @@ impl Redir {
 				Rule::heredoc => 0,
 				_ => 1
 			});
+			// A herestring's word is the text itself, not a path
+			let mut text = None;
+			if redir_type == Some(Rule::herestring) {
+				text = file_target.take().map(|word| word.to_string_lossy().to_string());
+				expand_text = true;
+			}
 
 			Ok(
 				Self {
 					redir_type: redir_type.unwrap(),
 					our_fd,
 					their_fd,
-					file_target
+					file_target,
+					text,
+					expand_text,
+					strip_tabs,
+					proc_sub
 				}
 			)
 		} else {
@@ -119,37 +149,75 @@ impl Redir {
 			0 => Rule::r#in,
 			_ => Rule::out
 		};
-		Self { redir_type, our_fd, their_fd: Some(their_fd), file_target: None }
+		Self { redir_type, our_fd, their_fd: Some(their_fd), file_target: None, text: None, expand_text: false, strip_tabs: false, proc_sub: false }
 	}
 	pub fn redir_type(&self) -> Rule {
 		self.redir_type
 	}
+	pub fn our_fd(&self) -> RawFd {
+		self.our_fd
+	}
+	/// Attaches the body that was read from the lines following a heredoc
+	pub fn set_heredoc_body(&mut self, body: &str) {
+		let body = if self.strip_tabs {
+			body.split_inclusive('\n').map(|line| line.trim_start_matches('\t')).collect()
+		} else {
+			body.to_string()
+		};
+		self.text = Some(body);
+	}
+	/// Expands the parts of the redirection that depend on the state of the shell
+	/// That is the text of a heredoc or herestring, unless the heredoc delimiter was quoted, and process substitutions used as targets
+	pub fn expand(&mut self, slash: &mut Slash) -> SlashResult<()> {
+		if self.proc_sub {
+			if let Some(target) = self.file_target.take() {
+				let path = expand::cmdsub::expand_proc_sub_str(&target.to_string_lossy(), slash)?;
+				self.file_target = Some(PathBuf::from(path));
+			}
+			self.proc_sub = false;
+		}
+		if !self.expand_text {
+			return Ok(())
+		}
+		if let Some(text) = self.text.take() {
+			let expanded = if self.redir_type == Rule::herestring {
+				expand::dispatch::expand_word(&text, slash)?.trim_quotes()
+			} else {
+				expand::string::expand_heredoc(&text, slash)?
+			};
+			self.text = Some(expanded);
+		}
+		self.expand_text = false;
+		Ok(())
+	}
 }
 
 #[derive(Debug)]
 pub struct CmdRedirs {
 	open_fds: Vec<SmartFD>,
-	targets_fd: Vec<Redir>,
-	targets_file: Vec<Redir>
+	redirs: Vec<Redir>,
+	no_clobber: bool
 }
 
 impl CmdRedirs {
-	pub fn new(mut redirs: VecDeque<Redir>) -> Self {
-		let mut targets_fd = vec![];
-		let mut targets_file = vec![];
-		while let Some(redir) = redirs.pop_back() {
-			let Redir { redir_type: _, our_fd: _, their_fd, file_target: _ } = &redir;
-			if their_fd.is_some() {
-				targets_fd.push(redir);
+	pub fn new(redirs: VecDeque<Redir>) -> Self {
+		Self { open_fds: vec![], redirs: redirs.into(), no_clobber: false }
+	}
+	/// Refuse to truncate existing files with `>`, like `set -C`
+	/// `>|` still overwrites them
+	pub fn set_no_clobber(&mut self, no_clobber: bool) {
+		self.no_clobber = no_clobber
+	}
+	/// Redirections are applied in the order they were given, so a later one overrides an earlier one on the same fd
+	/// A pipeline's pipes come first, so `cmd > file | next` writes to the file instead of the pipe
+	pub fn activate(&mut self) -> SlashResult<()> {
+		for redir in std::mem::take(&mut self.redirs) {
+			if redir.their_fd.is_some() {
+				self.open_their_fd(&redir)?;
 			} else {
-				targets_file.push(redir);
+				self.open_file_target(&redir)?;
 			}
 		}
-		Self { open_fds: vec![], targets_fd, targets_file }
-	}
-	pub fn activate(&mut self) -> SlashResult<()> {
-		self.open_file_targets()?;
-		self.open_their_fds()?;
 		Ok(())
 	}
 	pub fn close_all(mut self) -> SlashResult<()> {
@@ -158,34 +226,47 @@ impl CmdRedirs {
 		}
 		Ok(())
 	}
-	pub fn open_file_targets(&mut self) -> SlashResult<()> {
-		for redir in &self.targets_file {
-			let Redir { redir_type, our_fd, their_fd: _, file_target } = redir;
-			let src_fd = SmartFD::new(*our_fd)?;
-			let path = file_target.as_ref().unwrap(); // We know that there's a file target so unwrap is safe
-			let flags = match redir_type {
-				Rule::r#in => OFlag::O_RDONLY,
-				Rule::out => OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC,
-				Rule::append => OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_APPEND,
-				_ => unreachable!(),
-			};
-			let mode = Mode::from_bits(0o644).unwrap();
-			let mut file_fd = SmartFD::open(path, flags, mode)?;
-			file_fd.dup2(&src_fd)?;
-			file_fd.close()?;
+	fn open_file_target(&mut self, redir: &Redir) -> SlashResult<()> {
+		let Redir { redir_type, our_fd, file_target, text, .. } = redir;
+		let src_fd = SmartFD::new(*our_fd)?;
+		if matches!(redir_type, Rule::heredoc | Rule::herestring) {
+			// The text is written to an in-memory file, which the command then reads from the start
+			let mut text = text.clone().unwrap_or_default();
+			if *redir_type == Rule::herestring {
+				text.push('\n');
+			}
+			let mut mem_fd = SmartFD::new_memfd("heredoc", false)?;
+			mem_fd.write_all(text.as_bytes())?;
+			lseek(mem_fd.as_raw_fd(), 0, Whence::SeekSet).map_err(|_| Low(SlashErrLow::from_io()))?;
+			mem_fd.dup2(&src_fd)?;
+			mem_fd.close()?;
 			self.open_fds.push(src_fd);
+			return Ok(())
 		}
+		let path = file_target.as_ref().unwrap(); // We know that there's a file target so unwrap is safe
+		if self.no_clobber && *redir_type == Rule::out && path.is_file() {
+			return Err(Low(SlashErrLow::ExecFailed(format!("Cannot overwrite existing file `{}'",path.display()))))
+		}
+		let flags = match redir_type {
+			Rule::r#in => OFlag::O_RDONLY,
+			Rule::out | Rule::force_out => OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC,
+			Rule::append => OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_APPEND,
+			_ => unreachable!(),
+		};
+		let mode = Mode::from_bits(0o644).unwrap();
+		let mut file_fd = SmartFD::open(path, flags, mode)?;
+		file_fd.dup2(&src_fd)?;
+		file_fd.close()?;
+		self.open_fds.push(src_fd);
 		Ok(())
 	}
-	pub fn open_their_fds(&mut self) -> SlashResult<()> {
-		for redir in &self.targets_fd {
-			let Redir { redir_type: _, our_fd, their_fd, file_target: _ } = redir;
-			let mut tgt_fd = SmartFD::new(their_fd.unwrap())?;
-			let src_fd = SmartFD::new(*our_fd)?;
-			tgt_fd.dup2(&src_fd)?;
-			tgt_fd.close()?;
-			self.open_fds.push(src_fd);
-		}
+	fn open_their_fd(&mut self, redir: &Redir) -> SlashResult<()> {
+		let Redir { our_fd, their_fd, .. } = redir;
+		let mut tgt_fd = SmartFD::new(their_fd.unwrap())?;
+		let src_fd = SmartFD::new(*our_fd)?;
+		tgt_fd.dup2(&src_fd)?;
+		tgt_fd.close()?;
+		self.open_fds.push(src_fd);
 		Ok(())
 	}
 }
@@ -363,7 +444,7 @@ impl<'a> SmartFD {
 			MemFdCreateFlag::MFD_CLOEXEC
 		};
 		let fd = memfd_create(&c_name, flags).map_err(|_| Low(SlashErrLow::from_io()))?;
-		Ok(SmartFD { fd: fd.as_raw_fd() })
+		Ok(SmartFD { fd: fd.into_raw_fd() })
 	}
 
 	/// Wrapper for nix::unistd::pipe(), simply produces two `SmartFDs` that point to a read and write pipe respectfully
@@ -476,20 +557,25 @@ impl FromRawFd for SmartFD {
 	}
 }
 
-pub fn exec_external(command: CString, argv: Vec<CString>, envp: Vec<CString>,blame: Pair<Rule>) -> ! {
+/// `report` is false when the shell will raise the failure itself, so that the error isn't printed twice
+pub fn exec_external<B: Blame>(command: CString, argv: Vec<CString>, envp: Vec<CString>, report: bool, blame: B) -> ! {
 	let Err(e) = execvpe(&command, &argv, &envp);
-	match e {
+	let code = match e {
 		Errno::ENOENT => {
-			let error = High(SlashErrHigh::cmd_not_found(command.to_str().unwrap(), blame));
-			eprintln!("{}",error);
+			if report {
+				eprintln!("{}",High(SlashErrHigh::cmd_not_found(command.to_str().unwrap(), blame)));
+			}
+			127
 		}
 		Errno::EACCES => {
-			let error = High(SlashErrHigh::no_permission(command.to_str().unwrap(), blame));
-			eprintln!("{}",error);
+			if report {
+				eprintln!("{}",High(SlashErrHigh::no_permission(command.to_str().unwrap(), blame)));
+			}
+			126
 		}
 		_ => unimplemented!("Case for `{}` not implemented", e.to_string())
-	}
-	std::process::exit(e as i32)
+	};
+	std::process::exit(code)
 }
 
 pub fn handle_parent_process<'a>(child: Pid, command: String, slash: &mut Slash) -> SlashResult<()> {
@@ -501,7 +587,12 @@ pub fn handle_parent_process<'a>(child: Pid, command: String, slash: &mut Slash)
 		.with_pgid(child)
 		.build();
 
-	helper::handle_fg(slash,job)?;
+	if slash.ctx().flags().contains(ExecFlags::BACKGROUND) {
+		slash.vars_mut().set_param("!", &child.to_string());
+		write_jobs(|j| j.insert_job(job,false))??;
+	} else {
+		helper::handle_fg(slash,job)?;
+	}
 	Ok(())
 }
 